@@ -1,6 +1,11 @@
+use crate::bigint::BigInt;
+use crate::lexer::Span;
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     Int(i64),
+    BigInt(BigInt),
     Float(f64),
     Bool(bool),
     Str(String),
@@ -25,23 +30,310 @@ pub enum UnaryOp {
     Not,
 }
 
+// Spans point at the first token of a node, e.g. the `{` of a block or the
+// `if` keyword. Binary expressions start where their left operand starts.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
-    Literal(Literal),
+    Literal(Literal, Span),
     Binary(Box<Expr>, BinaryOp, Box<Expr>),
-    Unary(UnaryOp, Box<Expr>),
-    Variable(String),
-    Call(String, Vec<Expr>),
-    Block(Vec<Stmt>, Option<Box<Expr>>),
-    If(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+    Unary(UnaryOp, Box<Expr>, Span),
+    Variable(String, Span),
+    Call(String, Vec<Expr>, Span),
+    /// `receiver.name(args)`, calling `name(receiver, args)`. The receiver
+    /// is the first argument and the span is the method name.
+    MethodCall(String, Vec<Expr>, Span),
+    Block(Vec<Stmt>, Option<Box<Expr>>, Span),
+    If(Box<Expr>, Box<Expr>, Option<Box<Expr>>, Span),
+    /// `expr?`, unwrapping an `Ok` or propagating an `Err`. The span is the `?`.
+    Propagate(Box<Expr>, Span),
+    /// `try { ... } catch name { ... }`.
+    Try(Box<Expr>, String, Box<Expr>, Span),
+    /// `(a, b)`, or `()` for the unit value.
+    Tuple(Vec<Expr>, Span),
+    /// `tuple.0`. The span is the index.
+    TupleIndex(Box<Expr>, usize, Span),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Binary(lhs, _, _) => lhs.span(),
+            Expr::MethodCall(_, args, span) => args.first().map_or(*span, Expr::span),
+            Expr::Literal(_, span)
+            | Expr::Unary(_, _, span)
+            | Expr::Variable(_, span)
+            | Expr::Call(_, _, span)
+            | Expr::Block(_, _, span)
+            | Expr::If(_, _, _, span)
+            | Expr::Try(_, _, _, span) => *span,
+            Expr::Propagate(expr, _) | Expr::TupleIndex(expr, _, _) => expr.span(),
+            Expr::Tuple(_, span) => *span,
+        }
+    }
+
+    /// Whether a `yield` runs as part of this expression, which makes a
+    /// function with it as its body a generator. Functions declared inside
+    /// it are not looked into.
+    pub fn contains_yield(&self) -> bool {
+        match self {
+            Expr::Literal(..) | Expr::Variable(..) => false,
+            Expr::Binary(lhs, _, rhs) => lhs.contains_yield() || rhs.contains_yield(),
+            Expr::Unary(_, operand, _)
+            | Expr::Propagate(operand, _)
+            | Expr::TupleIndex(operand, _, _) => operand.contains_yield(),
+            Expr::Call(_, args, _) | Expr::MethodCall(_, args, _) | Expr::Tuple(args, _) => {
+                args.iter().any(Expr::contains_yield)
+            }
+            Expr::Block(stmts, tail, _) => {
+                stmts.iter().any(Stmt::contains_yield)
+                    || tail.as_ref().is_some_and(|tail| tail.contains_yield())
+            }
+            Expr::If(condition, then_branch, else_branch, _) => {
+                condition.contains_yield()
+                    || then_branch.contains_yield()
+                    || else_branch
+                        .as_ref()
+                        .is_some_and(|else_branch| else_branch.contains_yield())
+            }
+            Expr::Try(body, _, handler, _) => body.contains_yield() || handler.contains_yield(),
+        }
+    }
+}
+
+/// A type annotation, such as `int` or `fn(int, str) -> bool`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    Bool,
+    Str,
+    Unit,
+    /// `(int, str)`. The unit type is the tuple of no types.
+    Tuple(Vec<Type>),
+    Fn(Vec<Type>, Box<Type>),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "str"),
+            Type::Unit => write!(f, "()"),
+            Type::Tuple(items) => write!(f, "{}", tuple(items)),
+            Type::Fn(params, ret) => {
+                write!(f, "fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ") -> {}", ret)
+            }
+        }
+    }
+}
+
+/// Renders items as a tuple, such as `(a, b)`. A tuple of one item keeps
+/// its comma, `(a,)`, to tell it apart from parentheses.
+pub fn tuple<T: fmt::Display>(items: &[T]) -> String {
+    let items: Vec<String> = items.iter().map(T::to_string).collect();
+    match items.as_slice() {
+        [item] => format!("({},)", item),
+        _ => format!("({})", items.join(", ")),
+    }
+}
+
+/// What a parameter or a `for` loop binds: a name, or a tuple of patterns
+/// such as `(q, r)` that takes a tuple apart.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Name(String),
+    Tuple(Vec<Pattern>),
+}
+
+impl Pattern {
+    /// The names bound by the pattern, from left to right.
+    pub fn names(&self) -> Vec<&String> {
+        match self {
+            Pattern::Name(name) => vec![name],
+            Pattern::Tuple(items) => items.iter().flat_map(Pattern::names).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Name(name) => write!(f, "{}", name),
+            Pattern::Tuple(items) => write!(f, "{}", tuple(items)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub pattern: Pattern,
+    pub ty: Option<Type>,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.ty {
+            Some(ty) => write!(f, "{}: {}", self.pattern, ty),
+            None => write!(f, "{}", self.pattern),
+        }
+    }
+}
+
+/// Renders `name(a: int, b) -> int`, the part of a function declaration
+/// after the `fn` keyword.
+pub fn signature(name: &str, params: &[Param], ret: Option<&Type>) -> String {
+    let params: Vec<String> = params.iter().map(Param::to_string).collect();
+    let mut signature = format!("{}({})", name, params.join(", "));
+    if let Some(ret) = ret {
+        signature.push_str(&format!(" -> {}", ret));
+    }
+    signature
+}
+
+/// What an `import` statement brings into scope. The definitions of the
+/// imported file are always available as `module::name`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Import {
+    /// `import "lib/utils.toy";`
+    File(String),
+    /// `import lib::utils::{add, mul};` also defines `add` and `mul`.
+    Names(Vec<String>, Vec<String>),
+}
+
+impl Import {
+    /// The path of the imported file, relative to the importing one.
+    pub fn path(&self) -> String {
+        match self {
+            Import::File(path) => path.clone(),
+            Import::Names(module, _) => format!("{}.toy", module.join("/")),
+        }
+    }
+
+    /// The name the imported definitions are qualified with: the file name
+    /// without its extension.
+    pub fn namespace(&self) -> String {
+        match self {
+            Import::File(path) => std::path::Path::new(path)
+                .file_stem()
+                .map_or_else(|| path.clone(), |stem| stem.to_string_lossy().into_owned()),
+            Import::Names(module, _) => module.last().cloned().unwrap_or_default(),
+        }
+    }
+
+    /// The names defined without qualification.
+    pub fn names(&self) -> &[String] {
+        match self {
+            Import::File(_) => &[],
+            Import::Names(_, names) => names,
+        }
+    }
+}
+
+impl fmt::Display for Import {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Import::File(path) => write!(f, "import \"{}\";", path),
+            Import::Names(module, names) => {
+                write!(f, "import {}::{{{}}};", module.join("::"), names.join(", "))
+            }
+        }
+    }
+}
+
+/// Whether a top-level definition is exported from its file. Only `pub`
+/// definitions can be used by files that import it.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Visibility {
+    #[default]
+    Private,
+    Public,
+}
+
+impl Visibility {
+    /// What a declaration with this visibility starts with: `"pub "` or
+    /// nothing.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Visibility::Private => "",
+            Visibility::Public => "pub ",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Stmt {
-    Let(String, Expr),
-    Fn(String, Vec<String>, Expr),
+    // The `Option<Type>`s hold type annotations and the `Option<String>`
+    // the `///` doc comment, if any.
+    Let(String, Option<Type>, Expr, Option<String>, Visibility, Span),
+    /// `let (q, r) = value;`, taking a tuple apart.
+    LetTuple(Vec<Pattern>, Option<Type>, Expr, Span),
+    // Name, parameters, return type, body, doc comment, visibility.
+    Fn(
+        String,
+        Vec<Param>,
+        Option<Type>,
+        Expr,
+        Option<String>,
+        Visibility,
+        Span,
+    ),
     Expression(Expr),
     ImplicitReturn(Expr),
-    Assign(String, Expr),
-    While(Expr, Expr),
+    Assign(String, Expr, Span),
+    While(Expr, Expr, Span),
+    /// `for pattern in iterable { body }`.
+    For(Pattern, Expr, Expr, Span),
+    /// `yield value;`, which makes the enclosing function a generator.
+    Yield(Expr, Span),
+    Import(Import, Span),
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expression(expr) | Stmt::ImplicitReturn(expr) => expr.span(),
+            Stmt::Let(.., span)
+            | Stmt::LetTuple(.., span)
+            | Stmt::Fn(.., span)
+            | Stmt::Assign(.., span)
+            | Stmt::While(.., span)
+            | Stmt::For(.., span)
+            | Stmt::Yield(.., span)
+            | Stmt::Import(.., span) => *span,
+        }
+    }
+
+    /// Whether a `yield` runs as part of this statement.
+    pub fn contains_yield(&self) -> bool {
+        match self {
+            Stmt::Yield(..) => true,
+            Stmt::Fn(..) | Stmt::Import(..) => false,
+            Stmt::Let(_, _, expr, ..)
+            | Stmt::LetTuple(_, _, expr, _)
+            | Stmt::Expression(expr)
+            | Stmt::ImplicitReturn(expr)
+            | Stmt::Assign(_, expr, _) => expr.contains_yield(),
+            Stmt::While(condition, body, _) | Stmt::For(_, condition, body, _) => {
+                condition.contains_yield() || body.contains_yield()
+            }
+        }
+    }
+
+    /// The names a `let` statement declares in its block.
+    pub fn declared_names(&self) -> Vec<&String> {
+        match self {
+            Stmt::Let(name, ..) => vec![name],
+            Stmt::LetTuple(patterns, ..) => patterns.iter().flat_map(Pattern::names).collect(),
+            _ => Vec::new(),
+        }
+    }
 }
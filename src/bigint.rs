@@ -0,0 +1,335 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+// Magnitudes are stored as little-endian limbs in base 10^9 so that printing
+// and parsing decimal numbers stays cheap.
+const BASE: u64 = 1_000_000_000;
+const BASE_DIGITS: usize = 9;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn from_i64(value: i64) -> Self {
+        let mut magnitude = value.unsigned_abs();
+        let mut limbs = Vec::new();
+        while magnitude > 0 {
+            limbs.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+        BigInt {
+            negative: value < 0,
+            limbs,
+        }
+    }
+
+    /// Parses a string of digits in the given radix. Returns `None` if the
+    /// string is empty or contains a digit that is not valid for the radix.
+    pub fn parse(digits: &str, radix: u32) -> Option<Self> {
+        if digits.is_empty() {
+            return None;
+        }
+        let mut limbs = Vec::new();
+        for ch in digits.chars() {
+            let digit = ch.to_digit(radix)?;
+            mul_small(&mut limbs, radix);
+            add_small(&mut limbs, digit);
+        }
+        Some(BigInt::from_parts(false, limbs))
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        let mut magnitude: u64 = 0;
+        for &limb in self.limbs.iter().rev() {
+            magnitude = magnitude.checked_mul(BASE)?.checked_add(limb as u64)?;
+        }
+        if self.negative {
+            if magnitude == i64::MIN.unsigned_abs() {
+                Some(i64::MIN)
+            } else {
+                i64::try_from(magnitude).ok().map(|m| -m)
+            }
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse::<f64>().unwrap()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.limbs.clone())
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_parts(self.negative, add_mag(&self.limbs, &other.limbs));
+        }
+        // Signs differ, so subtract the smaller magnitude from the larger one.
+        match cmp_mag(&self.limbs, &other.limbs) {
+            Ordering::Less => {
+                BigInt::from_parts(other.negative, sub_mag(&other.limbs, &self.limbs))
+            }
+            _ => BigInt::from_parts(self.negative, sub_mag(&self.limbs, &other.limbs)),
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        BigInt::from_parts(
+            self.negative != other.negative,
+            mul_mag(&self.limbs, &other.limbs),
+        )
+    }
+
+    /// Truncating division, matching the semantics of Rust's `/` on integers.
+    /// Returns `None` when dividing by zero.
+    pub fn div(&self, other: &BigInt) -> Option<BigInt> {
+        if other.is_zero() {
+            return None;
+        }
+        let (quotient, _) = divrem_mag(&self.limbs, &other.limbs);
+        Some(BigInt::from_parts(
+            self.negative != other.negative,
+            quotient,
+        ))
+    }
+
+    fn from_parts(negative: bool, mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        // Zero is never negative.
+        let negative = negative && !limbs.is_empty();
+        BigInt { negative, limbs }
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.limbs, &other.limbs),
+            (true, true) => cmp_mag(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some((most_significant, rest)) = self.limbs.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", most_significant)?;
+        for limb in rest.iter().rev() {
+            write!(f, "{:0width$}", limb, width = BASE_DIGITS)?;
+        }
+        Ok(())
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push((sum % BASE) as u32);
+        carry = sum / BASE;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+// Requires `a >= b`.
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut diff = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        if diff < 0 {
+            diff += BASE as i64;
+            borrow = 1;
+        } else {
+            borrow = 0;
+        }
+        result.push(diff as u32);
+    }
+    result
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut result = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let current = result[i + j] + x as u64 * y as u64 + carry;
+            result[i + j] = current % BASE;
+            carry = current / BASE;
+        }
+        result[i + b.len()] += carry;
+    }
+    result.into_iter().map(|limb| limb as u32).collect()
+}
+
+fn mul_small(limbs: &mut Vec<u32>, factor: u32) {
+    let mut carry = 0;
+    for limb in limbs.iter_mut() {
+        let product = *limb as u64 * factor as u64 + carry;
+        *limb = (product % BASE) as u32;
+        carry = product / BASE;
+    }
+    if carry > 0 {
+        limbs.push(carry as u32);
+    }
+}
+
+fn add_small(limbs: &mut Vec<u32>, value: u32) {
+    let mut carry = value as u64;
+    for limb in limbs.iter_mut() {
+        if carry == 0 {
+            return;
+        }
+        let sum = *limb as u64 + carry;
+        *limb = (sum % BASE) as u32;
+        carry = sum / BASE;
+    }
+    if carry > 0 {
+        limbs.push(carry as u32);
+    }
+}
+
+// Schoolbook long division, one base-10^9 digit of the quotient at a time.
+// Each quotient digit is found by binary search, which is slow compared to
+// Knuth's algorithm D but more than fast enough for script-sized numbers.
+fn divrem_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = Vec::new();
+    for i in (0..a.len()).rev() {
+        remainder.insert(0, a[i]);
+        while remainder.last() == Some(&0) {
+            remainder.pop();
+        }
+        let (mut low, mut high) = (0u32, (BASE - 1) as u32);
+        while low < high {
+            let mid = low + (high - low).div_ceil(2);
+            let mut candidate = b.to_vec();
+            mul_small(&mut candidate, mid);
+            if cmp_mag(&candidate, &remainder) == Ordering::Greater {
+                high = mid - 1;
+            } else {
+                low = mid;
+            }
+        }
+        if low > 0 {
+            let mut product = b.to_vec();
+            mul_small(&mut product, low);
+            remainder = sub_mag(&remainder, &product);
+            while remainder.last() == Some(&0) {
+                remainder.pop();
+            }
+        }
+        quotient[i] = low;
+    }
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(digits: &str) -> BigInt {
+        match digits.strip_prefix('-') {
+            Some(rest) => BigInt::parse(rest, 10).unwrap().neg(),
+            None => BigInt::parse(digits, 10).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(big("0").to_string(), "0");
+        assert_eq!(big("000123").to_string(), "123");
+        assert_eq!(
+            big("123456789012345678901234567890").to_string(),
+            "123456789012345678901234567890"
+        );
+        assert_eq!(big("-1000000000").to_string(), "-1000000000");
+        assert_eq!(BigInt::parse("ff", 16).unwrap().to_string(), "255");
+        assert_eq!(BigInt::parse("12a", 10), None);
+        assert_eq!(BigInt::parse("", 10), None);
+    }
+
+    #[test]
+    fn test_i64_round_trip() {
+        for value in [0, 1, -1, 999_999_999, 1_000_000_000, i64::MAX, i64::MIN] {
+            assert_eq!(BigInt::from_i64(value).to_i64(), Some(value));
+            assert_eq!(BigInt::from_i64(value).to_string(), value.to_string());
+        }
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(big("-9223372036854775809").to_i64(), None);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = big("99999999999999999999");
+        let b = big("1");
+        assert_eq!(a.add(&b), big("100000000000000000000"));
+        assert_eq!(b.sub(&a), big("-99999999999999999998"));
+        assert_eq!(a.sub(&a), BigInt::from_i64(0));
+        assert_eq!(a.mul(&a), big("9999999999999999999800000000000000000001"));
+        assert_eq!(a.mul(&big("-2")), big("-199999999999999999998"));
+        assert_eq!(big("-5").add(&big("3")), big("-2"));
+    }
+
+    #[test]
+    fn test_division() {
+        let a = big("9999999999999999999800000000000000000001");
+        let b = big("99999999999999999999");
+        assert_eq!(a.div(&b), Some(b.clone()));
+        assert_eq!(big("-7").div(&big("2")), Some(big("-3")));
+        assert_eq!(
+            big("123456789012345678901234567890").div(&big("1000000007")),
+            Some(big("123456788148148161864"))
+        );
+        assert_eq!(a.div(&BigInt::from_i64(0)), None);
+    }
+
+    #[test]
+    fn test_ordering() {
+        assert!(big("-10") < big("-9"));
+        assert!(big("-1") < big("0"));
+        assert!(big("100000000000000000000") > big("99999999999999999999"));
+        assert_eq!(big("-0"), BigInt::from_i64(0));
+    }
+}
@@ -0,0 +1,1121 @@
+use crate::ast::{BinaryOp, Expr, Literal, Pattern, Stmt, Type, UnaryOp};
+use crate::lexer::Span;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TypeError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+/// Infers the types of a program and checks them against its annotations,
+/// returning every error found in source order.
+///
+/// Unannotated code is inferred Hindley–Milner style, so `fn id(x) { x }`
+/// gets the type `fn('a) -> 'a` and can be used at any type. Names that
+/// cannot be resolved are left unconstrained, since with dynamic scoping
+/// they may still be defined by a caller at runtime.
+pub fn check(program: &[Stmt]) -> Vec<TypeError> {
+    let mut checker = Checker {
+        vars: Vec::new(),
+        scopes: vec![HashMap::new()],
+        catchers: Vec::new(),
+        errors: Vec::new(),
+    };
+    checker.stmts(program);
+    checker.errors.sort_by_key(|err| err.span);
+    checker.errors
+}
+
+// Which types a type variable may still become. Arithmetic works on numbers
+// and `+` also on strings, so operands are constrained rather than fixed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Any,
+    Number,
+    Addable,
+}
+
+impl Kind {
+    fn allows(self, ty: &Ty) -> bool {
+        match self {
+            Kind::Any => true,
+            Kind::Number => matches!(ty, Ty::Int | Ty::Float),
+            Kind::Addable => matches!(ty, Ty::Int | Ty::Float | Ty::Str),
+        }
+    }
+
+    fn meet(self, other: Kind) -> Kind {
+        match (self, other) {
+            (Kind::Number, _) | (_, Kind::Number) => Kind::Number,
+            (Kind::Addable, _) | (_, Kind::Addable) => Kind::Addable,
+            _ => Kind::Any,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ty {
+    Int,
+    Float,
+    Bool,
+    Str,
+    Unit,
+    Fn(Vec<Ty>, Box<Ty>),
+    // The type of `Ok(value)` and `Err(error)`.
+    Result(Box<Ty>, Box<Ty>),
+    List(Box<Ty>),
+    // A tuple of at least one item; the empty tuple is `Unit`.
+    Tuple(Vec<Ty>),
+    Var(usize),
+    // The type of `print` and `println`, which take any arguments.
+    Variadic(Box<Ty>),
+}
+
+impl Ty {
+    fn from_annotation(ty: &Type) -> Ty {
+        match ty {
+            Type::Int => Ty::Int,
+            Type::Float => Ty::Float,
+            Type::Bool => Ty::Bool,
+            Type::Str => Ty::Str,
+            Type::Unit => Ty::Unit,
+            Type::Tuple(items) => Ty::Tuple(items.iter().map(Ty::from_annotation).collect()),
+            Type::Fn(params, ret) => Ty::Fn(
+                params.iter().map(Ty::from_annotation).collect(),
+                Box::new(Ty::from_annotation(ret)),
+            ),
+        }
+    }
+}
+
+// A type with quantified variables, e.g. `fn('a) -> 'a` for all `'a`.
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<usize>,
+    ty: Ty,
+}
+
+impl Scheme {
+    fn mono(ty: Ty) -> Scheme {
+        Scheme {
+            vars: Vec::new(),
+            ty,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TypeVar {
+    binding: Option<Ty>,
+    kind: Kind,
+}
+
+// What an error propagated by `?` reaches first.
+enum Catcher {
+    // A function, which returns the error; holds its return type.
+    Function(Ty),
+    Try,
+}
+
+struct Checker {
+    vars: Vec<TypeVar>,
+    scopes: Vec<HashMap<String, Scheme>>,
+    catchers: Vec<Catcher>,
+    errors: Vec<TypeError>,
+}
+
+impl Checker {
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(TypeError { message, span });
+    }
+
+    fn fresh(&mut self, kind: Kind) -> Ty {
+        self.vars.push(TypeVar {
+            binding: None,
+            kind,
+        });
+        Ty::Var(self.vars.len() - 1)
+    }
+
+    // Follows variable bindings until reaching a concrete type or an
+    // unbound variable.
+    fn resolve(&self, ty: &Ty) -> Ty {
+        match ty {
+            Ty::Var(var) => match &self.vars[*var].binding {
+                Some(bound) => self.resolve(bound),
+                None => ty.clone(),
+            },
+            _ => ty.clone(),
+        }
+    }
+
+    // Resolves a type all the way down, for display.
+    fn zonk(&self, ty: &Ty) -> Ty {
+        match self.resolve(ty) {
+            Ty::Fn(params, ret) => Ty::Fn(
+                params.iter().map(|p| self.zonk(p)).collect(),
+                Box::new(self.zonk(&ret)),
+            ),
+            Ty::Result(ok, err) => Ty::Result(Box::new(self.zonk(&ok)), Box::new(self.zonk(&err))),
+            Ty::List(item) => Ty::List(Box::new(self.zonk(&item))),
+            Ty::Tuple(items) => Ty::Tuple(items.iter().map(|item| self.zonk(item)).collect()),
+            Ty::Variadic(ret) => Ty::Variadic(Box::new(self.zonk(&ret))),
+            ty => ty,
+        }
+    }
+
+    fn display(&self, ty: &Ty) -> String {
+        let mut names = Vec::new();
+        display(&self.zonk(ty), &mut names)
+    }
+
+    fn occurs(&self, var: usize, ty: &Ty) -> bool {
+        match self.resolve(ty) {
+            Ty::Var(other) => other == var,
+            Ty::Fn(params, ret) => {
+                params.iter().any(|p| self.occurs(var, p)) || self.occurs(var, &ret)
+            }
+            Ty::Result(ok, err) => self.occurs(var, &ok) || self.occurs(var, &err),
+            Ty::List(item) => self.occurs(var, &item),
+            Ty::Tuple(items) => items.iter().any(|item| self.occurs(var, item)),
+            Ty::Variadic(ret) => self.occurs(var, &ret),
+            _ => false,
+        }
+    }
+
+    fn unify(&mut self, expected: &Ty, found: &Ty) -> Result<(), ()> {
+        let (expected, found) = (self.resolve(expected), self.resolve(found));
+        match (&expected, &found) {
+            (Ty::Var(a), Ty::Var(b)) if a == b => Ok(()),
+            (Ty::Var(var), other) | (other, Ty::Var(var)) => self.bind_var(*var, other),
+            (Ty::Fn(p1, r1), Ty::Fn(p2, r2)) if p1.len() == p2.len() => {
+                for (a, b) in p1.iter().zip(p2) {
+                    self.unify(a, b)?;
+                }
+                self.unify(r1, r2)
+            }
+            (Ty::Result(o1, e1), Ty::Result(o2, e2)) => {
+                self.unify(o1, o2)?;
+                self.unify(e1, e2)
+            }
+            (Ty::List(i1), Ty::List(i2)) => self.unify(i1, i2),
+            (Ty::Tuple(i1), Ty::Tuple(i2)) if i1.len() == i2.len() => {
+                for (a, b) in i1.iter().zip(i2) {
+                    self.unify(a, b)?;
+                }
+                Ok(())
+            }
+            (Ty::Variadic(r1), Ty::Variadic(r2)) => self.unify(r1, r2),
+            _ if expected == found => Ok(()),
+            _ => Err(()),
+        }
+    }
+
+    fn bind_var(&mut self, var: usize, ty: &Ty) -> Result<(), ()> {
+        let kind = self.vars[var].kind;
+        if let Ty::Var(other) = ty {
+            self.vars[*other].kind = self.vars[*other].kind.meet(kind);
+        } else if !kind.allows(ty) || self.occurs(var, ty) {
+            return Err(());
+        }
+        self.vars[var].binding = Some(ty.clone());
+        Ok(())
+    }
+
+    // Unifies and reports a mismatch at `span`.
+    fn expect(&mut self, expected: &Ty, found: &Ty, span: Span) {
+        if self.unify(expected, found).is_err() {
+            let message = format!(
+                "expected {}, found {}",
+                self.describe(expected),
+                self.display(found)
+            );
+            self.error(span, message);
+        }
+    }
+
+    fn describe(&self, ty: &Ty) -> String {
+        match self.resolve(ty) {
+            Ty::Var(var) if self.vars[var].kind == Kind::Number => "a number".to_string(),
+            Ty::Var(var) if self.vars[var].kind == Kind::Addable => {
+                "a number or string".to_string()
+            }
+            _ => self.display(ty),
+        }
+    }
+
+    // Restricts what a type may become, e.g. to numbers for `-x`.
+    fn constrain(&mut self, ty: &Ty, kind: Kind) -> bool {
+        match self.resolve(ty) {
+            Ty::Var(var) => {
+                self.vars[var].kind = self.vars[var].kind.meet(kind);
+                true
+            }
+            ty => kind.allows(&ty),
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<Scheme> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+            .or_else(|| self.builtin(name))
+    }
+
+    // The types of the builtins registered in `Environment::new`.
+    fn builtin(&mut self, name: &str) -> Option<Scheme> {
+        let (a, b) = (self.fresh(Kind::Any), self.fresh(Kind::Any));
+        let result = Ty::Result(Box::new(a.clone()), Box::new(b.clone()));
+        let ty = match name {
+            "print" | "println" => Ty::Variadic(Box::new(Ty::Unit)),
+            "wrapping_add" | "wrapping_sub" | "wrapping_mul" | "saturating_add"
+            | "saturating_sub" | "saturating_mul" => {
+                Ty::Fn(vec![Ty::Int, Ty::Int], Box::new(Ty::Int))
+            }
+            "Ok" => Ty::Fn(vec![a], Box::new(result)),
+            "Err" => Ty::Fn(vec![b], Box::new(result)),
+            // `error` never returns, so its result can be used as anything.
+            "error" => Ty::Fn(vec![a], Box::new(b)),
+            "is_ok" | "is_err" => Ty::Fn(vec![result], Box::new(Ty::Bool)),
+            "unwrap" => Ty::Fn(vec![result], Box::new(a)),
+            "parse_int" => Ty::Fn(vec![Ty::Str], Box::new(io_result(Ty::Int))),
+            "parse_float" => Ty::Fn(vec![Ty::Str], Box::new(io_result(Ty::Float))),
+            "str" => Ty::Fn(vec![a], Box::new(Ty::Str)),
+            "list" => Ty::Variadic(Box::new(Ty::List(Box::new(a)))),
+            "push" => Ty::Fn(
+                vec![Ty::List(Box::new(a.clone())), a.clone()],
+                Box::new(Ty::List(Box::new(a))),
+            ),
+            "map" | "flat_map" => {
+                let list_b = Ty::List(Box::new(b.clone()));
+                let ret = if name == "map" { b } else { list_b.clone() };
+                Ty::Fn(
+                    vec![
+                        Ty::List(Box::new(a.clone())),
+                        Ty::Fn(vec![a], Box::new(ret)),
+                    ],
+                    Box::new(list_b),
+                )
+            }
+            "filter" => Ty::Fn(
+                vec![
+                    Ty::List(Box::new(a.clone())),
+                    Ty::Fn(vec![a.clone()], Box::new(Ty::Bool)),
+                ],
+                Box::new(Ty::List(Box::new(a))),
+            ),
+            "any" | "all" => Ty::Fn(
+                vec![
+                    Ty::List(Box::new(a.clone())),
+                    Ty::Fn(vec![a], Box::new(Ty::Bool)),
+                ],
+                Box::new(Ty::Bool),
+            ),
+            "find" => Ty::Fn(
+                vec![
+                    Ty::List(Box::new(a.clone())),
+                    Ty::Fn(vec![a.clone()], Box::new(Ty::Bool)),
+                ],
+                Box::new(io_result(a)),
+            ),
+            "reduce" => Ty::Fn(
+                vec![
+                    Ty::List(Box::new(a.clone())),
+                    b.clone(),
+                    Ty::Fn(vec![b.clone(), a], Box::new(b.clone())),
+                ],
+                Box::new(b),
+            ),
+            // Sort keys are numbers or strings.
+            "sort_by" => {
+                let key = self.fresh(Kind::Addable);
+                Ty::Fn(
+                    vec![
+                        Ty::List(Box::new(a.clone())),
+                        Ty::Fn(vec![a.clone()], Box::new(key)),
+                    ],
+                    Box::new(Ty::List(Box::new(a))),
+                )
+            }
+            "zip" => Ty::Fn(
+                vec![Ty::List(Box::new(a.clone())), Ty::List(Box::new(b.clone()))],
+                Box::new(Ty::List(Box::new(Ty::Tuple(vec![a, b])))),
+            ),
+            "enumerate" => Ty::Fn(
+                vec![Ty::List(Box::new(a.clone()))],
+                Box::new(Ty::List(Box::new(Ty::Tuple(vec![Ty::Int, a])))),
+            ),
+            "group_by" => Ty::Fn(
+                vec![
+                    Ty::List(Box::new(a.clone())),
+                    Ty::Fn(vec![a.clone()], Box::new(b.clone())),
+                ],
+                Box::new(Ty::List(Box::new(Ty::Tuple(vec![
+                    b,
+                    Ty::List(Box::new(a)),
+                ])))),
+            ),
+            // The iterator builtins are left unchecked: iterators have no
+            // type, and an unknown type can stand in for the lists the
+            // builtins above take.
+            "len" => Ty::Fn(vec![Ty::List(Box::new(a))], Box::new(Ty::Int)),
+            "get" => Ty::Fn(vec![Ty::List(Box::new(a.clone())), Ty::Int], Box::new(a)),
+            "clock" => Ty::Fn(vec![], Box::new(Ty::Float)),
+            "args" => Ty::Fn(vec![], Box::new(Ty::List(Box::new(Ty::Str)))),
+            // Like `error`, `exit` never returns.
+            "exit" => Ty::Fn(vec![Ty::Int], Box::new(a)),
+            "env_var" => Ty::Fn(vec![Ty::Str], Box::new(io_result(Ty::Str))),
+            "set_env_var" => Ty::Fn(vec![Ty::Str, Ty::Str], Box::new(Ty::Unit)),
+            "read_line" | "read_all" => Ty::Fn(vec![], Box::new(io_result(Ty::Str))),
+            "read_file" => Ty::Fn(vec![Ty::Str], Box::new(io_result(Ty::Str))),
+            "read_lines" | "list_dir" => Ty::Fn(
+                vec![Ty::Str],
+                Box::new(io_result(Ty::List(Box::new(Ty::Str)))),
+            ),
+            "file_exists" => Ty::Fn(vec![Ty::Str], Box::new(Ty::Bool)),
+            "file_size" => Ty::Fn(vec![Ty::Str], Box::new(io_result(Ty::Int))),
+            "write_file" | "append_file" => {
+                Ty::Fn(vec![Ty::Str, Ty::Str], Box::new(io_result(Ty::Unit)))
+            }
+            "remove_file" | "mkdir" => Ty::Fn(vec![Ty::Str], Box::new(io_result(Ty::Unit))),
+            _ => return None,
+        };
+        let mut vars = Vec::new();
+        self.free_vars(&ty, &mut vars);
+        Some(Scheme { vars, ty })
+    }
+
+    // Defines the names in `pattern` with the types of the parts of `ty`
+    // they match, reporting a value of `ty` that cannot match at `span`.
+    fn bind(&mut self, pattern: &Pattern, ty: &Ty, span: Span) {
+        let patterns = match pattern {
+            Pattern::Name(name) => return self.define(name, Scheme::mono(ty.clone())),
+            Pattern::Tuple(patterns) => patterns,
+        };
+        let items: Vec<Ty> = patterns.iter().map(|_| self.fresh(Kind::Any)).collect();
+        let expected = if items.is_empty() {
+            Ty::Unit
+        } else {
+            Ty::Tuple(items.clone())
+        };
+        if self.unify(&expected, ty).is_err() {
+            let message = format!(
+                "cannot take {} apart as a tuple of {} item{}",
+                self.display(ty),
+                patterns.len(),
+                if patterns.len() == 1 { "" } else { "s" }
+            );
+            self.error(span, message);
+        }
+        for (pattern, item) in patterns.iter().zip(&items) {
+            self.bind(pattern, item, span);
+        }
+    }
+
+    fn define(&mut self, name: &str, scheme: Scheme) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), scheme);
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Ty {
+        let mut mapping = HashMap::new();
+        for &var in &scheme.vars {
+            let kind = self.vars[var].kind;
+            mapping.insert(var, self.fresh(kind));
+        }
+        self.substitute(&scheme.ty, &mapping)
+    }
+
+    fn substitute(&self, ty: &Ty, mapping: &HashMap<usize, Ty>) -> Ty {
+        match self.resolve(ty) {
+            Ty::Var(var) => mapping.get(&var).cloned().unwrap_or(Ty::Var(var)),
+            Ty::Fn(params, ret) => Ty::Fn(
+                params.iter().map(|p| self.substitute(p, mapping)).collect(),
+                Box::new(self.substitute(&ret, mapping)),
+            ),
+            Ty::Result(ok, err) => Ty::Result(
+                Box::new(self.substitute(&ok, mapping)),
+                Box::new(self.substitute(&err, mapping)),
+            ),
+            Ty::List(item) => Ty::List(Box::new(self.substitute(&item, mapping))),
+            Ty::Tuple(items) => Ty::Tuple(
+                items
+                    .iter()
+                    .map(|item| self.substitute(item, mapping))
+                    .collect(),
+            ),
+            Ty::Variadic(ret) => Ty::Variadic(Box::new(self.substitute(&ret, mapping))),
+            ty => ty,
+        }
+    }
+
+    fn free_vars(&self, ty: &Ty, out: &mut Vec<usize>) {
+        match self.resolve(ty) {
+            Ty::Var(var) if !out.contains(&var) => out.push(var),
+            Ty::Fn(params, ret) => {
+                for param in &params {
+                    self.free_vars(param, out);
+                }
+                self.free_vars(&ret, out);
+            }
+            Ty::Result(ok, err) => {
+                self.free_vars(&ok, out);
+                self.free_vars(&err, out);
+            }
+            Ty::List(item) => self.free_vars(&item, out),
+            Ty::Tuple(items) => {
+                for item in &items {
+                    self.free_vars(item, out);
+                }
+            }
+            Ty::Variadic(ret) => self.free_vars(&ret, out),
+            _ => {}
+        }
+    }
+
+    // Quantifies the variables of `ty` that no enclosing binding mentions.
+    fn generalize(&self, ty: &Ty) -> Scheme {
+        let mut in_scope = Vec::new();
+        for scheme in self.scopes.iter().flat_map(|scope| scope.values()) {
+            let mut vars = Vec::new();
+            self.free_vars(&scheme.ty, &mut vars);
+            in_scope.extend(vars.into_iter().filter(|v| !scheme.vars.contains(v)));
+        }
+        let mut vars = Vec::new();
+        self.free_vars(ty, &mut vars);
+        vars.retain(|var| !in_scope.contains(var));
+        Scheme {
+            vars,
+            ty: self.zonk(ty),
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Ty {
+        // Functions may be called before their declaration, e.g. by each
+        // other, so declare them all up front.
+        for stmt in stmts {
+            if let Stmt::Fn(name, ..) = stmt {
+                let ty = self.fresh(Kind::Any);
+                self.define(name, Scheme::mono(ty));
+            }
+        }
+        let mut last = Ty::Unit;
+        for stmt in stmts {
+            last = self.stmt(stmt);
+        }
+        last
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Ty {
+        match stmt {
+            Stmt::Let(name, ty, value, ..) => {
+                let found = self.expr(value);
+                if let Some(ty) = ty {
+                    let annotated = Ty::from_annotation(ty);
+                    self.expect(&annotated, &found, value.span());
+                }
+                self.define(name, Scheme::mono(found));
+                Ty::Unit
+            }
+            Stmt::LetTuple(patterns, ty, value, _) => {
+                let found = self.expr(value);
+                if let Some(ty) = ty {
+                    let annotated = Ty::from_annotation(ty);
+                    self.expect(&annotated, &found, value.span());
+                }
+                self.bind(&Pattern::Tuple(patterns.clone()), &found, value.span());
+                Ty::Unit
+            }
+            Stmt::Fn(name, params, ret, body, _, _, span) => {
+                // Declared up front by `stmts`, so it is in the current scope.
+                let declared = match self.scopes.last_mut().unwrap().remove(name) {
+                    Some(scheme) if scheme.vars.is_empty() => scheme.ty,
+                    _ => self.fresh(Kind::Any),
+                };
+                self.define(name, Scheme::mono(declared.clone()));
+                self.scopes.push(HashMap::new());
+                let mut param_types = Vec::new();
+                for param in params {
+                    let ty = match &param.ty {
+                        Some(ty) => Ty::from_annotation(ty),
+                        None => self.fresh(Kind::Any),
+                    };
+                    self.bind(&param.pattern, &ty, *span);
+                    param_types.push(ty);
+                }
+                let ret_type = match ret {
+                    Some(ty) => Ty::from_annotation(ty),
+                    None => self.fresh(Kind::Any),
+                };
+                let fn_type = Ty::Fn(param_types, Box::new(ret_type.clone()));
+                // Recursive calls see the function at a single type.
+                self.expect(&declared, &fn_type, *span);
+                self.catchers.push(Catcher::Function(ret_type.clone()));
+                let body_type = self.expr(body);
+                self.catchers.pop();
+                // A generator returns an iterator, which has no type here.
+                if !body.contains_yield() {
+                    self.expect(&ret_type, &body_type, tail_span(body));
+                }
+                self.scopes.pop();
+                // The function's own monomorphic binding must not keep its
+                // type variables from being generalized.
+                self.scopes.last_mut().unwrap().remove(name);
+                let scheme = self.generalize(&fn_type);
+                self.define(name, scheme);
+                Ty::Unit
+            }
+            Stmt::Assign(name, value, _) => {
+                let found = self.expr(value);
+                if let Some(scheme) = self.lookup(name) {
+                    let ty = self.instantiate(&scheme);
+                    // Arithmetic freely mixes integers and floats, so e.g.
+                    // `x += 0.5` may turn an integer variable into a float.
+                    let numbers = [self.resolve(&ty), self.resolve(&found)]
+                        .iter()
+                        .all(|ty| matches!(ty, Ty::Int | Ty::Float));
+                    if !numbers {
+                        self.expect(&ty, &found, value.span());
+                    }
+                }
+                Ty::Unit
+            }
+            Stmt::While(condition, body, _) => {
+                let found = self.expr(condition);
+                self.expect(&Ty::Bool, &found, condition.span());
+                self.expr(body);
+                Ty::Unit
+            }
+            Stmt::For(pattern, iterable, body, span) => {
+                let found = self.expr(iterable);
+                // Only lists give the loop variable a type; iterators have none.
+                let item = match self.resolve(&found) {
+                    Ty::List(item) => *item,
+                    _ => self.fresh(Kind::Any),
+                };
+                self.scopes.push(HashMap::new());
+                self.bind(pattern, &item, *span);
+                self.expr(body);
+                self.scopes.pop();
+                Ty::Unit
+            }
+            Stmt::Yield(value, _) => {
+                self.expr(value);
+                Ty::Unit
+            }
+            Stmt::Import(import, _) => {
+                // Imported files are not checked, so their definitions can
+                // be used at any type, like undefined names.
+                for name in import.names() {
+                    let ty = self.fresh(Kind::Any);
+                    let scheme = self.generalize(&ty);
+                    self.define(name, scheme);
+                }
+                Ty::Unit
+            }
+            Stmt::Expression(expr) => {
+                self.expr(expr);
+                Ty::Unit
+            }
+            Stmt::ImplicitReturn(expr) => self.expr(expr),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Ty {
+        match expr {
+            Expr::Literal(literal, _) => match literal {
+                Literal::Int(_) | Literal::BigInt(_) => Ty::Int,
+                Literal::Float(_) => Ty::Float,
+                Literal::Bool(_) => Ty::Bool,
+                Literal::Str(_) => Ty::Str,
+            },
+            Expr::Variable(name, _) => match self.lookup(name) {
+                Some(scheme) => self.instantiate(&scheme),
+                None => self.fresh(Kind::Any),
+            },
+            Expr::Unary(op, operand, span) => {
+                let ty = self.expr(operand);
+                match op {
+                    UnaryOp::Not => {
+                        self.expect(&Ty::Bool, &ty, operand.span());
+                        Ty::Bool
+                    }
+                    UnaryOp::Neg => {
+                        if !self.constrain(&ty, Kind::Number) {
+                            let message = format!("cannot negate {}", self.display(&ty));
+                            self.error(*span, message);
+                        }
+                        ty
+                    }
+                }
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                let left = self.expr(lhs);
+                let right = self.expr(rhs);
+                self.binary(op, &left, &right, expr.span())
+            }
+            Expr::Call(name, args, span) | Expr::MethodCall(name, args, span) => {
+                self.call(name, args, *span)
+            }
+            Expr::Tuple(items, _) => {
+                let items: Vec<Ty> = items.iter().map(|item| self.expr(item)).collect();
+                if items.is_empty() {
+                    Ty::Unit
+                } else {
+                    Ty::Tuple(items)
+                }
+            }
+            Expr::TupleIndex(tuple, index, span) => {
+                let found = self.expr(tuple);
+                match self.resolve(&found) {
+                    Ty::Tuple(items) if *index < items.len() => items[*index].clone(),
+                    // The length of an unknown tuple is not tracked, so
+                    // neither is the type of its items.
+                    Ty::Var(_) => self.fresh(Kind::Any),
+                    other => {
+                        let message =
+                            format!("{} has no item at index {}", self.display(&other), index);
+                        self.error(*span, message);
+                        self.fresh(Kind::Any)
+                    }
+                }
+            }
+            Expr::Block(stmts, tail, _) => {
+                self.scopes.push(HashMap::new());
+                self.stmts(stmts);
+                let ty = match tail {
+                    Some(tail) => self.expr(tail),
+                    None => Ty::Unit,
+                };
+                self.scopes.pop();
+                ty
+            }
+            Expr::If(condition, then_branch, else_branch, _) => {
+                let found = self.expr(condition);
+                self.expect(&Ty::Bool, &found, condition.span());
+                let then_type = self.expr(then_branch);
+                match else_branch {
+                    Some(else_branch) => {
+                        let else_type = self.expr(else_branch);
+                        if self.unify(&then_type, &else_type).is_err() {
+                            let message = format!(
+                                "`if` and `else` have different types: {} and {}",
+                                self.display(&then_type),
+                                self.display(&else_type)
+                            );
+                            self.error(tail_span(else_branch), message);
+                        }
+                        then_type
+                    }
+                    // Without an `else`, a false condition yields `()`.
+                    None => Ty::Unit,
+                }
+            }
+            Expr::Propagate(operand, span) => {
+                let found = self.expr(operand);
+                let ok = self.fresh(Kind::Any);
+                let err = self.fresh(Kind::Any);
+                let expected = Ty::Result(Box::new(ok.clone()), Box::new(err.clone()));
+                if self.unify(&expected, &found).is_err() {
+                    let message = format!(
+                        "the `?` operator expects a Result, found {}",
+                        self.display(&found)
+                    );
+                    self.error(*span, message);
+                }
+                // An error that no `try` catches makes the function return it.
+                if let Some(Catcher::Function(ret)) = self.catchers.last() {
+                    let ret = ret.clone();
+                    let value = self.fresh(Kind::Any);
+                    let returned = Ty::Result(Box::new(value), Box::new(err));
+                    if self.unify(&ret, &returned).is_err() {
+                        let message = format!(
+                            "the `?` operator returns a Result from a function that returns {}",
+                            self.display(&ret)
+                        );
+                        self.error(*span, message);
+                    }
+                }
+                ok
+            }
+            Expr::Try(body, name, handler, _) => {
+                self.catchers.push(Catcher::Try);
+                let body_type = self.expr(body);
+                self.catchers.pop();
+                // Anything can be raised, so the caught value is unconstrained.
+                self.scopes.push(HashMap::new());
+                let caught = self.fresh(Kind::Any);
+                self.define(name, Scheme::mono(caught));
+                let handler_type = self.expr(handler);
+                self.scopes.pop();
+                if self.unify(&body_type, &handler_type).is_err() {
+                    let message = format!(
+                        "`try` and `catch` have different types: {} and {}",
+                        self.display(&body_type),
+                        self.display(&handler_type)
+                    );
+                    self.error(tail_span(handler), message);
+                }
+                body_type
+            }
+        }
+    }
+
+    fn binary(&mut self, op: &BinaryOp, left: &Ty, right: &Ty, span: Span) -> Ty {
+        let (l, r) = (self.resolve(left), self.resolve(right));
+        let result = match op {
+            BinaryOp::And | BinaryOp::Or => (self.unify(&Ty::Bool, &l).is_ok()
+                && self.unify(&Ty::Bool, &r).is_ok())
+            .then_some(Ty::Bool),
+            // Tuples, lists and `()` are compared item by item.
+            BinaryOp::Eq if [&l, &r].iter().any(|ty| is_compound(ty)) => {
+                self.unify(&l, &r).is_ok().then_some(Ty::Bool)
+            }
+            // Other comparisons need two numbers of the same type.
+            BinaryOp::Eq | BinaryOp::Lt | BinaryOp::Gt => {
+                (self.constrain(&l, Kind::Number) && self.unify(&l, &r).is_ok()).then_some(Ty::Bool)
+            }
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
+                let kind = if *op == BinaryOp::Add {
+                    Kind::Addable
+                } else {
+                    Kind::Number
+                };
+                match (&l, &r) {
+                    (Ty::Str, _) | (_, Ty::Str) if kind == Kind::Addable => {
+                        (self.unify(&l, &r).is_ok()).then_some(Ty::Str)
+                    }
+                    // Mixing integers and floats gives a float.
+                    (Ty::Int, Ty::Int) => Some(Ty::Int),
+                    (Ty::Float | Ty::Int, Ty::Float) | (Ty::Float, Ty::Int) => Some(Ty::Float),
+                    (Ty::Var(_), Ty::Int | Ty::Float) => self
+                        .constrain(&l, Kind::Number)
+                        .then(|| self.arith_result(&l, &r)),
+                    (Ty::Int | Ty::Float, Ty::Var(_)) => self
+                        .constrain(&r, Kind::Number)
+                        .then(|| self.arith_result(&r, &l)),
+                    // Two strings or two numbers can be added, so `+` keeps
+                    // its operands alike. Other operators accept any pair of
+                    // numbers, and the result is a float if either is one.
+                    (Ty::Var(_), Ty::Var(_)) if kind == Kind::Addable => (self.constrain(&l, kind)
+                        && self.unify(&l, &r).is_ok())
+                    .then_some(l.clone()),
+                    (Ty::Var(_), Ty::Var(_)) => {
+                        self.constrain(&l, kind);
+                        self.constrain(&r, kind);
+                        Some(self.fresh(Kind::Number))
+                    }
+                    _ => None,
+                }
+            }
+        };
+        result.unwrap_or_else(|| {
+            let message = format!(
+                "cannot apply '{}' to {} and {}",
+                op_symbol(op),
+                self.display(left),
+                self.display(right)
+            );
+            self.error(span, message);
+            self.fresh(Kind::Any)
+        })
+    }
+
+    // The result of arithmetic between an unknown number and a known one:
+    // floats win, otherwise it has the unknown operand's type.
+    fn arith_result(&mut self, unknown: &Ty, known: &Ty) -> Ty {
+        match known {
+            Ty::Float => Ty::Float,
+            _ => unknown.clone(),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], span: Span) -> Ty {
+        let arg_types: Vec<Ty> = args.iter().map(|arg| self.expr(arg)).collect();
+        let Some(scheme) = self.lookup(name) else {
+            return self.fresh(Kind::Any);
+        };
+        let callee = self.instantiate(&scheme);
+        match self.resolve(&callee) {
+            Ty::Variadic(ret) => *ret,
+            Ty::Fn(params, ret) => {
+                if params.len() != args.len() {
+                    self.error(
+                        span,
+                        format!(
+                            "'{}' expects {} argument{}, found {}",
+                            name,
+                            params.len(),
+                            if params.len() == 1 { "" } else { "s" },
+                            args.len()
+                        ),
+                    );
+                    return *ret;
+                }
+                for ((param, arg_type), arg) in params.iter().zip(&arg_types).zip(args) {
+                    self.expect(param, arg_type, arg.span());
+                }
+                *ret
+            }
+            Ty::Var(_) => {
+                let ret = self.fresh(Kind::Any);
+                let fn_type = Ty::Fn(arg_types, Box::new(ret.clone()));
+                self.expect(&callee, &fn_type, span);
+                ret
+            }
+            other => {
+                let message = format!("'{}' is not a function but {}", name, self.display(&other));
+                self.error(span, message);
+                self.fresh(Kind::Any)
+            }
+        }
+    }
+}
+
+// Where to report a mismatch in a block's value: its tail expression.
+fn tail_span(expr: &Expr) -> Span {
+    match expr {
+        Expr::Block(_, Some(tail), _) => tail_span(tail),
+        _ => expr.span(),
+    }
+}
+
+fn op_symbol(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Lt => "<",
+        BinaryOp::Gt => ">",
+        BinaryOp::Eq => "==",
+        BinaryOp::And => "&&",
+        BinaryOp::Or => "||",
+    }
+}
+
+/// Whether `ty` is a tuple, a list or `()`, which `==` compares by their
+/// items.
+fn is_compound(ty: &Ty) -> bool {
+    matches!(ty, Ty::Tuple(_) | Ty::List(_) | Ty::Unit)
+}
+
+// Names type variables 'a, 'b, ... in order of appearance.
+/// The type of a builtin's result that fails with a message.
+fn io_result(ok: Ty) -> Ty {
+    Ty::Result(Box::new(ok), Box::new(Ty::Str))
+}
+
+fn display(ty: &Ty, names: &mut Vec<usize>) -> String {
+    match ty {
+        Ty::Int => "int".to_string(),
+        Ty::Float => "float".to_string(),
+        Ty::Bool => "bool".to_string(),
+        Ty::Str => "str".to_string(),
+        Ty::Unit => "()".to_string(),
+        Ty::Var(var) => {
+            let index = names.iter().position(|v| v == var).unwrap_or_else(|| {
+                names.push(*var);
+                names.len() - 1
+            });
+            format!("'{}", (b'a' + (index % 26) as u8) as char)
+        }
+        Ty::Fn(params, ret) => {
+            let params: Vec<String> = params.iter().map(|p| display(p, names)).collect();
+            format!("fn({}) -> {}", params.join(", "), display(ret, names))
+        }
+        Ty::Result(ok, err) => {
+            format!("Result<{}, {}>", display(ok, names), display(err, names))
+        }
+        Ty::List(item) => format!("[{}]", display(item, names)),
+        Ty::Tuple(items) => {
+            let items: Vec<String> = items.iter().map(|item| display(item, names)).collect();
+            crate::ast::tuple(&items)
+        }
+        Ty::Variadic(ret) => format!("fn(..) -> {}", display(ret, names)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn errors(input: &str) -> Vec<String> {
+        let program = Parser::new(Lexer::new(input))
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        check(&program).iter().map(|err| err.to_string()).collect()
+    }
+
+    #[test]
+    fn test_well_typed_programs() {
+        let input = r#"
+            fn id(x) { x }
+            fn half(n) { n / 2 }
+            fn fib(n: int) -> int {
+                if n < 2 { n } else { fib(n - 1) + fib(n - 2) }
+            }
+            fn greet(name) { "Hello, " + name }
+            fn is_even(n) { if n == 0 { true } else { is_odd(n - 1) } }
+            fn is_odd(n) { if n == 0 { false } else { is_even(n - 1) } }
+            let a = id(1) + half(3);
+            let b: float = half(3.0) * 1.5;
+            let c = id("s") + greet("toy");
+            let d = is_even(10) && !is_odd(3);
+            let e = -a + wrapping_add(1, 2);
+            fn scale(x, y, factor) { (x + y) * factor / 2.0 }
+            let f: float = scale(1, 2, 1.5);
+            f += 1;
+            println(a, b, c, d, e);
+            fib(10)
+        "#;
+        assert_eq!(errors(input), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_results() {
+        let input = r#"
+fn sum(a, b) { Ok(parse_int(a)? + parse_int(b)?) }
+let total: int = unwrap(sum("1", "2"));
+let fallback = try { sum("x", "1")? } catch e { 0 };
+let flag = is_ok(parse_float("1.5")) || is_err(Ok(1));
+fn bad(s) { parse_int(s)? }
+let x = 5?;
+let y = try { 1 } catch e { "none" };
+"#;
+        assert_eq!(
+            errors(input),
+            vec![
+                "6:13: expected Result<'a, str>, found int",
+                "7:10: the `?` operator expects a Result, found int",
+                "8:29: `try` and `catch` have different types: int and str",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operator_errors() {
+        let input = r#"
+let a = "a" + 1;
+let b = 1 == 1.0;
+let c = !5;
+let d = -"x";
+let e = true && 1;
+"#;
+        assert_eq!(
+            errors(input),
+            vec![
+                "2:9: cannot apply '+' to str and int",
+                "3:9: cannot apply '==' to int and float",
+                "4:10: expected bool, found int",
+                "5:9: cannot negate str",
+                "6:9: cannot apply '&&' to bool and int",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inferred_parameter_types() {
+        let input = r#"
+fn shout(s) { s + "!" }
+shout(3);
+fn add(a, b) { a + b }
+add(1, 2, 3);
+add("x", 2);
+"#;
+        assert_eq!(
+            errors(input),
+            vec![
+                "3:7: expected str, found int",
+                "5:1: 'add' expects 2 arguments, found 3",
+                "6:10: expected str, found int",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotations() {
+        let input = r#"
+let x: int = "five";
+fn f(a: str) -> int { a }
+fn apply(g: fn(int) -> int, n) { g(n) }
+apply(f, 1);
+let y = 1;
+y = "one";
+if 1 { 2 } else { "three" };
+"#;
+        assert_eq!(
+            errors(input),
+            vec![
+                "2:14: expected int, found str",
+                "3:23: expected int, found str",
+                "5:7: expected fn(int) -> int, found fn(str) -> int",
+                "7:5: expected int, found str",
+                "8:4: expected bool, found int",
+                "8:19: `if` and `else` have different types: int and str",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loops_and_generators() {
+        let input = r#"
+fn evens(xs) { for x in xs { if x / 2 * 2 == x { yield x; } } }
+for n in evens(list(1, 2)) { println(n + 1); }
+for s in args() { s + 1; }
+list(1, 2).map(str).len(2);
+"#;
+        assert_eq!(
+            errors(input),
+            vec![
+                "4:19: cannot apply '+' to str and int",
+                "5:21: 'len' expects 1 argument, found 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tuples() {
+        let input = r#"
+fn divmod(a: int, b: int) { (a / b, a - a / b * b) }
+let (q, r) = divmod(7, 2);
+let s: str = q;
+let (x, y, z) = divmod(1, 1);
+let pair: (str, int) = ("a", 1);
+pair.1 + pair.0;
+pair.2;
+for (i, name) in enumerate(list("a")) { let s: str = i; }
+assert_eq(divmod(7, 2), (3, 1));
+(1, 2) == (1, "a");
+fn swap((a, b)) { (b, a) }
+swap(1);
+"#;
+        assert_eq!(
+            errors(input),
+            vec![
+                "4:14: expected str, found int",
+                "5:17: cannot take (int, int) apart as a tuple of 3 items",
+                "7:1: cannot apply '+' to int and str",
+                "8:6: (str, int) has no item at index 2",
+                "9:54: expected str, found int",
+                "11:1: cannot apply '==' to (int, int) and (int, str)",
+                "13:6: expected ('a, 'b), found int",
+            ]
+        );
+    }
+}
@@ -0,0 +1,411 @@
+//! Builtins for working with lists through functions.
+//!
+//! The functions a script passes in are called back through
+//! `Environment::call`, so errors raised inside them are traced through the
+//! builtin's call site. Pairs, such as the items of `zip`, are tuples of
+//! two items.
+//!
+//! Each builtin also accepts an iterator in place of a list. Those that
+//! return a list of the same length or shorter, such as `map` and `filter`,
+//! then return an iterator that does the work as items are asked for; the
+//! others pull only the items they need.
+
+use crate::bigint::BigInt;
+use crate::interpreter::{Environment, RuntimeError, Value};
+use crate::iterators::Iter;
+use std::cmp::Ordering;
+
+/// The items a builtin works on.
+enum Items {
+    List(Vec<Value>),
+    Iter(Iter),
+}
+
+impl Items {
+    fn into_iter(self) -> Iter {
+        match self {
+            Items::List(items) => Iter::list(items),
+            Items::Iter(iter) => iter,
+        }
+    }
+
+    fn collect(self, env: &Environment) -> Result<Vec<Value>, RuntimeError> {
+        match self {
+            Items::List(items) => Ok(items),
+            Items::Iter(iter) => iter.collect(env),
+        }
+    }
+}
+
+/// Returns `f(item)` for each item of a list.
+pub fn map(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (items, f) = items_and_function("map", args)?;
+    let items = match items {
+        Items::List(items) => items,
+        Items::Iter(iter) => return Ok(iter.map(f)),
+    };
+    let mapped = items
+        .into_iter()
+        .map(|item| env.call("map", f.clone(), vec![item]))
+        .collect::<Result<_, _>>()?;
+    Ok(Value::List(mapped))
+}
+
+/// Returns the items of a list for which `keep(item)` is true.
+pub fn filter(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (items, keep) = items_and_function("filter", args)?;
+    let items = match items {
+        Items::List(items) => items,
+        Items::Iter(iter) => return Ok(iter.filter(keep)),
+    };
+    let mut kept = Vec::new();
+    for item in items {
+        if test("filter", &keep, item.clone(), env)? {
+            kept.push(item);
+        }
+    }
+    Ok(Value::List(kept))
+}
+
+/// Folds the items of a list into an initial value with
+/// `f(accumulator, item)`.
+pub fn reduce(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (items, init, f) = match <[Value; 3]>::try_from(args) {
+        Ok([items @ (Value::List(_) | Value::Iterator(_)), init, f]) if is_callable(&f) => {
+            (Iter::of("reduce", items)?, init, f)
+        }
+        Ok(args) => {
+            return Err(RuntimeError::TypeMismatch(format!(
+                "reduce expects a list or an iterator, a value and a function, got {:?}",
+                args
+            )));
+        }
+        Err(args) => return Err(RuntimeError::ArgumentMismatch(3, args.len())),
+    };
+    let mut acc = init;
+    while let Some(item) = items.next(env)? {
+        acc = env.call("reduce", f.clone(), vec![acc, item])?;
+    }
+    Ok(acc)
+}
+
+/// Returns whether `test(item)` is true for any item of a list, stopping at
+/// the first one.
+pub fn any(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (items, f) = items_and_function("any", args)?;
+    let items = items.into_iter();
+    while let Some(item) = items.next(env)? {
+        if test("any", &f, item, env)? {
+            return Ok(Value::Bool(true));
+        }
+    }
+    Ok(Value::Bool(false))
+}
+
+/// Returns whether `test(item)` is true for every item of a list, stopping
+/// at the first one for which it is not.
+pub fn all(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (items, f) = items_and_function("all", args)?;
+    let items = items.into_iter();
+    while let Some(item) = items.next(env)? {
+        if !test("all", &f, item, env)? {
+            return Ok(Value::Bool(false));
+        }
+    }
+    Ok(Value::Bool(true))
+}
+
+/// Returns `Ok` holding the first item of a list for which `test(item)` is
+/// true, or an `Err` if there is none.
+pub fn find(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (items, f) = items_and_function("find", args)?;
+    let items = items.into_iter();
+    while let Some(item) = items.next(env)? {
+        if test("find", &f, item.clone(), env)? {
+            return Ok(Value::Ok(Box::new(item)));
+        }
+    }
+    Ok(Value::Err(Box::new(Value::Str(
+        "no item matches".to_string(),
+    ))))
+}
+
+/// Returns the items of a list ordered by `key(item)`. Keys must all be
+/// numbers or all be strings; items with equal keys keep their order.
+pub fn sort_by(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (items, key) = items_and_function("sort_by", args)?;
+    let mut keyed = items
+        .collect(env)?
+        .into_iter()
+        .map(|item| Ok((env.call("sort_by", key.clone(), vec![item.clone()])?, item)))
+        .collect::<Result<Vec<_>, RuntimeError>>()?;
+    let mut error = None;
+    keyed.sort_by(|(l, _), (r, _)| {
+        compare(l, r).unwrap_or_else(|err| {
+            error.get_or_insert(err);
+            Ordering::Equal
+        })
+    });
+    match error {
+        Some(err) => Err(err),
+        None => Ok(Value::List(
+            keyed.into_iter().map(|(_, item)| item).collect(),
+        )),
+    }
+}
+
+/// Pairs up the items of two lists, stopping at the end of the shorter one.
+pub fn zip(args: Vec<Value>, _: &Environment) -> Result<Value, RuntimeError> {
+    match <[Value; 2]>::try_from(args) {
+        Ok([Value::List(left), Value::List(right)]) => Ok(Value::List(
+            left.into_iter()
+                .zip(right)
+                .map(|(l, r)| Value::Tuple(vec![l, r]))
+                .collect(),
+        )),
+        Ok(
+            [
+                left @ (Value::List(_) | Value::Iterator(_)),
+                right @ (Value::List(_) | Value::Iterator(_)),
+            ],
+        ) => Ok(Iter::of("zip", left)?.zip(Iter::of("zip", right)?)),
+        Ok(args) => Err(RuntimeError::TypeMismatch(format!(
+            "zip expects two lists or iterators, got {:?}",
+            args
+        ))),
+        Err(args) => Err(RuntimeError::ArgumentMismatch(2, args.len())),
+    }
+}
+
+/// Pairs each item of a list with its index.
+pub fn enumerate(args: Vec<Value>, _: &Environment) -> Result<Value, RuntimeError> {
+    let count = args.len();
+    match <[Value; 1]>::try_from(args) {
+        Ok([Value::List(items)]) => Ok(Value::List(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| Value::Tuple(vec![Value::Int(i as i64), item]))
+                .collect(),
+        )),
+        Ok([Value::Iterator(iter)]) => Ok(iter.enumerate()),
+        Ok([other]) => Err(RuntimeError::TypeMismatch(format!(
+            "enumerate expects a list or an iterator, got {:?}",
+            other
+        ))),
+        Err(_) => Err(RuntimeError::ArgumentMismatch(1, count)),
+    }
+}
+
+/// Calls `f(item)`, which must return a list, for each item of a list and
+/// joins the results.
+pub fn flat_map(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (items, f) = items_and_function("flat_map", args)?;
+    let items = match items {
+        Items::List(items) => items,
+        Items::Iter(iter) => return Ok(iter.flat_map(f)),
+    };
+    let mut joined = Vec::new();
+    for item in items {
+        match env.call("flat_map", f.clone(), vec![item])? {
+            Value::List(results) => joined.extend(results),
+            other => return Err(not_a_list("flat_map", other)),
+        }
+    }
+    Ok(Value::List(joined))
+}
+
+/// Groups the items of a list by `key(item)`. Returns a pair of the key and
+/// its items for each distinct key, in the order the keys first appear.
+pub fn group_by(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (items, key) = items_and_function("group_by", args)?;
+    let mut groups: Vec<(Value, Vec<Value>)> = Vec::new();
+    for item in items.collect(env)? {
+        let key = env.call("group_by", key.clone(), vec![item.clone()])?;
+        match groups.iter_mut().find(|(other, _)| *other == key) {
+            Some((_, group)) => group.push(item),
+            None => groups.push((key, vec![item])),
+        }
+    }
+    Ok(Value::List(
+        groups
+            .into_iter()
+            .map(|(key, group)| Value::Tuple(vec![key, Value::List(group)]))
+            .collect(),
+    ))
+}
+
+fn is_callable(value: &Value) -> bool {
+    matches!(value, Value::Function(..) | Value::NativeFunc(_))
+}
+
+/// Takes the list or iterator and the function arguments of a builtin.
+fn items_and_function(name: &str, args: Vec<Value>) -> Result<(Items, Value), RuntimeError> {
+    match <[Value; 2]>::try_from(args) {
+        Ok([Value::List(items), f]) if is_callable(&f) => Ok((Items::List(items), f)),
+        Ok([Value::Iterator(iter), f]) if is_callable(&f) => Ok((Items::Iter(iter), f)),
+        Ok(args) => Err(RuntimeError::TypeMismatch(format!(
+            "{} expects a list or an iterator and a function, got {:?}",
+            name, args
+        ))),
+        Err(args) => Err(RuntimeError::ArgumentMismatch(2, args.len())),
+    }
+}
+
+/// Calls a function that must return a boolean.
+pub(crate) fn test(
+    name: &str,
+    f: &Value,
+    item: Value,
+    env: &Environment,
+) -> Result<bool, RuntimeError> {
+    match env.call(name, f.clone(), vec![item])? {
+        Value::Bool(b) => Ok(b),
+        other => Err(RuntimeError::TypeMismatch(format!(
+            "{} expects the function to return a boolean, got {:?}",
+            name, other
+        ))),
+    }
+}
+
+/// The error for a callback of `name` that should have returned a list.
+pub(crate) fn not_a_list(name: &str, value: Value) -> RuntimeError {
+    RuntimeError::TypeMismatch(format!(
+        "{} expects the function to return a list, got {:?}",
+        name, value
+    ))
+}
+
+/// Orders two sort keys: numbers by value and strings alphabetically.
+fn compare(left: &Value, right: &Value) -> Result<Ordering, RuntimeError> {
+    let big = |value: &Value| match value {
+        Value::Int(i) => Some(BigInt::from_i64(*i)),
+        Value::BigInt(i) => Some(i.clone()),
+        _ => None,
+    };
+    let float = |value: &Value| match value {
+        Value::Float(f) => Some(*f),
+        _ => big(value).map(|i| i.to_f64()),
+    };
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => Ok(l.cmp(r)),
+        (Value::Str(l), Value::Str(r)) => Ok(l.cmp(r)),
+        _ => {
+            if let (Some(l), Some(r)) = (big(left), big(right)) {
+                return Ok(l.cmp(&r));
+            }
+            match (float(left), float(right)) {
+                (Some(l), Some(r)) => Ok(l.total_cmp(&r)),
+                _ => Err(RuntimeError::TypeMismatch(format!(
+                    "sort_by cannot compare {:?} with {:?}",
+                    left, right
+                ))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{Environment, Frame, RuntimeError, Value, eval_in};
+    use crate::lexer::Span;
+
+    fn eval(input: &str) -> Result<Value, RuntimeError> {
+        eval_in(input, &mut Environment::new())
+    }
+
+    fn eval_str(input: &str) -> String {
+        eval(input)
+            .unwrap_or_else(|err| panic!("{}", err))
+            .to_string()
+    }
+
+    #[test]
+    fn test_callbacks() {
+        let functions = "
+            fn double(n) { n * 2 }
+            fn even(n) { n / 2 * 2 == n }
+            fn add(a, b) { a + b }
+            let xs = list(3, 1, 4, 1, 5);
+        ";
+        for (input, expected) in [
+            ("map(xs, double)", "[6, 2, 8, 2, 10]"),
+            ("filter(xs, even)", "[4]"),
+            ("reduce(xs, 0, add)", "14"),
+            ("any(xs, even)", "true"),
+            ("all(xs, even)", "false"),
+            ("find(xs, even)", "Ok(4)"),
+            ("find(list(), even)", "Err(no item matches)"),
+            ("map(list(1, 2), str)", "[\"1\", \"2\"]"),
+        ] {
+            assert_eq!(eval_str(&format!("{} {}", functions, input)), expected);
+        }
+    }
+
+    #[test]
+    fn test_sort_by() {
+        let input = "
+            fn key(n) { 0 - n }
+            sort_by(list(3, 1.5, 100000000000000000000, 2), key)
+        ";
+        assert_eq!(eval_str(input), "[100000000000000000000, 3, 2, 1.5]");
+        let input = "
+            fn first(pair) { get(pair, 0) }
+            sort_by(list(list(\"b\", 1), list(\"a\", 2), list(\"b\", 0)), first)
+        ";
+        // Items with equal keys keep their order.
+        assert_eq!(eval_str(input), "[[\"a\", 2], [\"b\", 1], [\"b\", 0]]");
+        assert!(matches!(
+            eval("fn id(x) { x } sort_by(list(1, \"a\"), id)"),
+            Err(RuntimeError::TypeMismatch(msg)) if msg.starts_with("sort_by cannot compare")
+        ));
+    }
+
+    #[test]
+    fn test_pairs_and_groups() {
+        assert_eq!(
+            eval_str("zip(list(1, 2, 3), list(\"a\", \"b\"))"),
+            "[(1, \"a\"), (2, \"b\")]"
+        );
+        assert_eq!(
+            eval_str("enumerate(list(\"x\", \"y\"))"),
+            "[(0, \"x\"), (1, \"y\")]"
+        );
+        assert_eq!(
+            eval_str("fn twice(n) { list(n, n) } flat_map(list(1, 2), twice)"),
+            "[1, 1, 2, 2]"
+        );
+        assert_eq!(
+            eval_str("fn parity(n) { n - n / 2 * 2 } group_by(list(1, 2, 3, 4, 5), parity)"),
+            "[(1, [1, 3, 5]), (0, [2, 4])]"
+        );
+    }
+
+    #[test]
+    fn test_callback_errors() {
+        assert_eq!(
+            eval("map(list(1), 2)"),
+            Err(RuntimeError::TypeMismatch(
+                "map expects a list or an iterator and a function, got [List([Int(1)]), Int(2)]"
+                    .to_string()
+            ))
+        );
+        assert_eq!(
+            eval("fn id(n) { n } filter(list(1), id)"),
+            Err(RuntimeError::TypeMismatch(
+                "filter expects the function to return a boolean, got Int(1)".to_string()
+            ))
+        );
+        // Errors inside a callback are traced through the builtin's call site.
+        let err = eval("fn check(n) { 10 / n }\nmap(list(1, 0), check)").unwrap_err();
+        assert_eq!(err.cause(), &RuntimeError::DivisionByZero);
+        assert_eq!(
+            err.trace(),
+            [Frame {
+                function: "map".to_string(),
+                call_site: Span { line: 2, col: 1 },
+                module: None,
+            }]
+        );
+    }
+}
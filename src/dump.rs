@@ -0,0 +1,536 @@
+use crate::ast::{Expr, Import, Literal, Pattern, Stmt, Type, Visibility, signature, tuple};
+use crate::json::Json;
+use crate::lexer::{Span, Token};
+
+pub fn tokens_text(tokens: &[(Token, Span)]) -> String {
+    let mut out = String::new();
+    for (token, span) in tokens {
+        let (kind, value) = token_parts(token);
+        let position = span.to_string();
+        match value {
+            Some(value) => out.push_str(&format!("{:<8}{} {}\n", position, kind, value)),
+            None => out.push_str(&format!("{:<8}{}\n", position, kind)),
+        }
+    }
+    out
+}
+
+pub fn tokens_json(tokens: &[(Token, Span)]) -> Json {
+    Json::Array(
+        tokens
+            .iter()
+            .map(|(token, span)| {
+                let (kind, value) = token_parts(token);
+                let mut fields = vec![
+                    ("kind", Json::str(kind)),
+                    ("line", Json::Int(span.line as i64)),
+                    ("col", Json::Int(span.col as i64)),
+                ];
+                if let Some(value) = value {
+                    fields.push(("value", value));
+                }
+                Json::object(fields)
+            })
+            .collect(),
+    )
+}
+
+fn token_parts(token: &Token) -> (&'static str, Option<Json>) {
+    match token {
+        Token::Int(i) => ("Int", Some(Json::Int(*i))),
+        // Big integers are strings so that consumers don't lose precision.
+        Token::BigInt(i) => ("BigInt", Some(Json::str(i.to_string()))),
+        Token::Float(f) => ("Float", Some(Json::Float(*f))),
+        Token::Str(s) => ("Str", Some(Json::str(s.as_str()))),
+        Token::Identifier(name) => ("Identifier", Some(Json::str(name.as_str()))),
+        Token::Comment(text) => ("Comment", Some(Json::str(text.as_str()))),
+        Token::DocComment(text) => ("DocComment", Some(Json::str(text.as_str()))),
+        Token::InnerDocComment(text) => ("InnerDocComment", Some(Json::str(text.as_str()))),
+        Token::Let => ("Let", None),
+        Token::Fn => ("Fn", None),
+        Token::If => ("If", None),
+        Token::Else => ("Else", None),
+        Token::While => ("While", None),
+        Token::For => ("For", None),
+        Token::In => ("In", None),
+        Token::Yield => ("Yield", None),
+        Token::Try => ("Try", None),
+        Token::Catch => ("Catch", None),
+        Token::Import => ("Import", None),
+        Token::Pub => ("Pub", None),
+        Token::True => ("True", None),
+        Token::False => ("False", None),
+        Token::Plus => ("Plus", None),
+        Token::Minus => ("Minus", None),
+        Token::Star => ("Star", None),
+        Token::Slash => ("Slash", None),
+        Token::PlusEq => ("PlusEq", None),
+        Token::MinusEq => ("MinusEq", None),
+        Token::StarEq => ("StarEq", None),
+        Token::SlashEq => ("SlashEq", None),
+        Token::Eq => ("Eq", None),
+        Token::EqEq => ("EqEq", None),
+        Token::Lt => ("Lt", None),
+        Token::Gt => ("Gt", None),
+        Token::Bang => ("Bang", None),
+        Token::And => ("And", None),
+        Token::Or => ("Or", None),
+        Token::LParen => ("LParen", None),
+        Token::RParen => ("RParen", None),
+        Token::LBrace => ("LBrace", None),
+        Token::RBrace => ("RBrace", None),
+        Token::SemiColon => ("SemiColon", None),
+        Token::Comma => ("Comma", None),
+        Token::Colon => ("Colon", None),
+        Token::ColonColon => ("ColonColon", None),
+        Token::Arrow => ("Arrow", None),
+        Token::Question => ("Question", None),
+        Token::Dot => ("Dot", None),
+        Token::Eof => ("Eof", None),
+    }
+}
+
+/// Renders statements as an indented tree, one node per line.
+pub fn ast_text(program: &[Stmt]) -> String {
+    let mut out = String::new();
+    for stmt in program {
+        write_stmt(&mut out, stmt, 0);
+    }
+    out
+}
+
+fn line(out: &mut String, depth: usize, text: &str) {
+    out.push_str(&"  ".repeat(depth));
+    out.push_str(text);
+    out.push('\n');
+}
+
+fn write_stmt(out: &mut String, stmt: &Stmt, depth: usize) {
+    match stmt {
+        Stmt::Let(name, ty, value, doc, vis, _) => {
+            match ty {
+                Some(ty) => line(out, depth, &format!("Let {}: {}", name, ty)),
+                None => line(out, depth, &format!("Let {}", name)),
+            }
+            write_visibility(out, *vis, depth + 1);
+            write_doc(out, doc, depth + 1);
+            write_expr(out, value, depth + 1);
+        }
+        Stmt::LetTuple(patterns, ty, value, _) => {
+            let patterns = tuple(patterns);
+            match ty {
+                Some(ty) => line(out, depth, &format!("LetTuple {}: {}", patterns, ty)),
+                None => line(out, depth, &format!("LetTuple {}", patterns)),
+            }
+            write_expr(out, value, depth + 1);
+        }
+        Stmt::Fn(name, params, ret, body, doc, vis, _) => {
+            let signature = signature(name, params, ret.as_ref());
+            line(out, depth, &format!("Fn {}", signature));
+            write_visibility(out, *vis, depth + 1);
+            write_doc(out, doc, depth + 1);
+            write_expr(out, body, depth + 1);
+        }
+        Stmt::Expression(expr) => {
+            line(out, depth, "Expression");
+            write_expr(out, expr, depth + 1);
+        }
+        Stmt::ImplicitReturn(expr) => {
+            line(out, depth, "ImplicitReturn");
+            write_expr(out, expr, depth + 1);
+        }
+        Stmt::Assign(name, value, _) => {
+            line(out, depth, &format!("Assign {}", name));
+            write_expr(out, value, depth + 1);
+        }
+        Stmt::While(condition, body, _) => {
+            line(out, depth, "While");
+            write_expr(out, condition, depth + 1);
+            write_expr(out, body, depth + 1);
+        }
+        Stmt::For(pattern, iterable, body, _) => {
+            line(out, depth, &format!("For {}", pattern));
+            write_expr(out, iterable, depth + 1);
+            write_expr(out, body, depth + 1);
+        }
+        Stmt::Yield(value, _) => {
+            line(out, depth, "Yield");
+            write_expr(out, value, depth + 1);
+        }
+        Stmt::Import(import, _) => {
+            let what = match import {
+                Import::File(path) => Json::str(path.as_str()).to_string(),
+                Import::Names(module, names) => {
+                    format!("{}::{{{}}}", module.join("::"), names.join(", "))
+                }
+            };
+            line(out, depth, &format!("Import {}", what));
+        }
+    }
+}
+
+fn write_visibility(out: &mut String, vis: Visibility, depth: usize) {
+    if vis == Visibility::Public {
+        line(out, depth, "Public");
+    }
+}
+
+fn write_doc(out: &mut String, doc: &Option<String>, depth: usize) {
+    if let Some(doc) = doc {
+        line(out, depth, &format!("Doc {}", Json::str(doc.as_str())));
+    }
+}
+
+fn write_expr(out: &mut String, expr: &Expr, depth: usize) {
+    match expr {
+        Expr::Literal(literal, _) => {
+            let (kind, value) = literal_parts(literal);
+            line(out, depth, &format!("Literal {} {}", kind, value));
+        }
+        Expr::Binary(lhs, op, rhs) => {
+            line(out, depth, &format!("Binary {:?}", op));
+            write_expr(out, lhs, depth + 1);
+            write_expr(out, rhs, depth + 1);
+        }
+        Expr::Unary(op, operand, _) => {
+            line(out, depth, &format!("Unary {:?}", op));
+            write_expr(out, operand, depth + 1);
+        }
+        Expr::Variable(name, _) => line(out, depth, &format!("Variable {}", name)),
+        Expr::Call(name, args, _) => {
+            line(out, depth, &format!("Call {}", name));
+            for arg in args {
+                write_expr(out, arg, depth + 1);
+            }
+        }
+        Expr::MethodCall(name, args, _) => {
+            line(out, depth, &format!("MethodCall {}", name));
+            for arg in args {
+                write_expr(out, arg, depth + 1);
+            }
+        }
+        Expr::Block(statements, tail, _) => {
+            line(out, depth, "Block");
+            for stmt in statements {
+                write_stmt(out, stmt, depth + 1);
+            }
+            if let Some(tail) = tail {
+                line(out, depth + 1, "Tail");
+                write_expr(out, tail, depth + 2);
+            }
+        }
+        Expr::If(condition, then_branch, else_branch, _) => {
+            line(out, depth, "If");
+            write_expr(out, condition, depth + 1);
+            write_expr(out, then_branch, depth + 1);
+            if let Some(else_branch) = else_branch {
+                line(out, depth + 1, "Else");
+                write_expr(out, else_branch, depth + 2);
+            }
+        }
+        Expr::Propagate(operand, _) => {
+            line(out, depth, "Propagate");
+            write_expr(out, operand, depth + 1);
+        }
+        Expr::Tuple(items, _) => {
+            line(out, depth, "Tuple");
+            for item in items {
+                write_expr(out, item, depth + 1);
+            }
+        }
+        Expr::TupleIndex(tuple, index, _) => {
+            line(out, depth, &format!("TupleIndex {}", index));
+            write_expr(out, tuple, depth + 1);
+        }
+        Expr::Try(body, name, handler, _) => {
+            line(out, depth, "Try");
+            write_expr(out, body, depth + 1);
+            line(out, depth + 1, &format!("Catch {}", name));
+            write_expr(out, handler, depth + 2);
+        }
+    }
+}
+
+fn literal_parts(literal: &Literal) -> (&'static str, Json) {
+    match literal {
+        Literal::Int(i) => ("Int", Json::Int(*i)),
+        Literal::BigInt(i) => ("BigInt", Json::str(i.to_string())),
+        Literal::Float(f) => ("Float", Json::Float(*f)),
+        Literal::Bool(b) => ("Bool", Json::Bool(*b)),
+        Literal::Str(s) => ("Str", Json::str(s.as_str())),
+    }
+}
+
+pub fn ast_json(program: &[Stmt]) -> Json {
+    Json::Array(program.iter().map(stmt_json).collect())
+}
+
+fn doc_json(doc: &Option<String>) -> Json {
+    match doc {
+        Some(doc) => Json::str(doc.as_str()),
+        None => Json::Null,
+    }
+}
+
+fn type_json(ty: &Option<Type>) -> Json {
+    match ty {
+        Some(ty) => Json::str(ty.to_string()),
+        None => Json::Null,
+    }
+}
+
+// A name is a string and a tuple pattern an array of patterns.
+fn pattern_json(pattern: &Pattern) -> Json {
+    match pattern {
+        Pattern::Name(name) => Json::str(name.as_str()),
+        Pattern::Tuple(items) => Json::Array(items.iter().map(pattern_json).collect()),
+    }
+}
+
+fn stmt_json(stmt: &Stmt) -> Json {
+    match stmt {
+        Stmt::Let(name, ty, value, doc, vis, _) => Json::object(vec![
+            ("node", Json::str("Let")),
+            ("name", Json::str(name.as_str())),
+            ("public", Json::Bool(*vis == Visibility::Public)),
+            ("type", type_json(ty)),
+            ("doc", doc_json(doc)),
+            ("value", expr_json(value)),
+        ]),
+        Stmt::LetTuple(patterns, ty, value, _) => Json::object(vec![
+            ("node", Json::str("LetTuple")),
+            (
+                "names",
+                Json::Array(patterns.iter().map(pattern_json).collect()),
+            ),
+            ("type", type_json(ty)),
+            ("value", expr_json(value)),
+        ]),
+        Stmt::Fn(name, params, ret, body, doc, vis, _) => Json::object(vec![
+            ("node", Json::str("Fn")),
+            ("name", Json::str(name.as_str())),
+            ("public", Json::Bool(*vis == Visibility::Public)),
+            (
+                "params",
+                Json::Array(
+                    params
+                        .iter()
+                        .map(|p| {
+                            Json::object(vec![
+                                ("name", pattern_json(&p.pattern)),
+                                ("type", type_json(&p.ty)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            ("return_type", type_json(ret)),
+            ("doc", doc_json(doc)),
+            ("body", expr_json(body)),
+        ]),
+        Stmt::Expression(expr) => Json::object(vec![
+            ("node", Json::str("Expression")),
+            ("expr", expr_json(expr)),
+        ]),
+        Stmt::ImplicitReturn(expr) => Json::object(vec![
+            ("node", Json::str("ImplicitReturn")),
+            ("expr", expr_json(expr)),
+        ]),
+        Stmt::Assign(name, value, _) => Json::object(vec![
+            ("node", Json::str("Assign")),
+            ("name", Json::str(name.as_str())),
+            ("value", expr_json(value)),
+        ]),
+        Stmt::While(condition, body, _) => Json::object(vec![
+            ("node", Json::str("While")),
+            ("condition", expr_json(condition)),
+            ("body", expr_json(body)),
+        ]),
+        Stmt::For(pattern, iterable, body, _) => Json::object(vec![
+            ("node", Json::str("For")),
+            ("name", pattern_json(pattern)),
+            ("iterable", expr_json(iterable)),
+            ("body", expr_json(body)),
+        ]),
+        Stmt::Yield(value, _) => Json::object(vec![
+            ("node", Json::str("Yield")),
+            ("value", expr_json(value)),
+        ]),
+        Stmt::Import(import, _) => {
+            let strings = |items: &[String]| {
+                Json::Array(items.iter().map(|item| Json::str(item.as_str())).collect())
+            };
+            match import {
+                Import::File(path) => Json::object(vec![
+                    ("node", Json::str("Import")),
+                    ("path", Json::str(path.as_str())),
+                ]),
+                Import::Names(module, names) => Json::object(vec![
+                    ("node", Json::str("Import")),
+                    ("module", strings(module)),
+                    ("names", strings(names)),
+                ]),
+            }
+        }
+    }
+}
+
+fn expr_json(expr: &Expr) -> Json {
+    match expr {
+        Expr::Literal(literal, _) => {
+            let (kind, value) = literal_parts(literal);
+            Json::object(vec![
+                ("node", Json::str("Literal")),
+                ("type", Json::str(kind)),
+                ("value", value),
+            ])
+        }
+        Expr::Binary(lhs, op, rhs) => Json::object(vec![
+            ("node", Json::str("Binary")),
+            ("op", Json::str(format!("{:?}", op))),
+            ("lhs", expr_json(lhs)),
+            ("rhs", expr_json(rhs)),
+        ]),
+        Expr::Unary(op, operand, _) => Json::object(vec![
+            ("node", Json::str("Unary")),
+            ("op", Json::str(format!("{:?}", op))),
+            ("operand", expr_json(operand)),
+        ]),
+        Expr::Variable(name, _) => Json::object(vec![
+            ("node", Json::str("Variable")),
+            ("name", Json::str(name.as_str())),
+        ]),
+        Expr::Call(name, args, _) => Json::object(vec![
+            ("node", Json::str("Call")),
+            ("name", Json::str(name.as_str())),
+            ("args", Json::Array(args.iter().map(expr_json).collect())),
+        ]),
+        Expr::MethodCall(name, args, _) => Json::object(vec![
+            ("node", Json::str("MethodCall")),
+            ("name", Json::str(name.as_str())),
+            ("args", Json::Array(args.iter().map(expr_json).collect())),
+        ]),
+        Expr::Block(statements, tail, _) => Json::object(vec![
+            ("node", Json::str("Block")),
+            (
+                "statements",
+                Json::Array(statements.iter().map(stmt_json).collect()),
+            ),
+            (
+                "tail",
+                tail.as_ref().map_or(Json::Null, |tail| expr_json(tail)),
+            ),
+        ]),
+        Expr::If(condition, then_branch, else_branch, _) => Json::object(vec![
+            ("node", Json::str("If")),
+            ("condition", expr_json(condition)),
+            ("then", expr_json(then_branch)),
+            (
+                "else",
+                else_branch
+                    .as_ref()
+                    .map_or(Json::Null, |else_branch| expr_json(else_branch)),
+            ),
+        ]),
+        Expr::Propagate(operand, _) => Json::object(vec![
+            ("node", Json::str("Propagate")),
+            ("operand", expr_json(operand)),
+        ]),
+        Expr::Try(body, name, handler, _) => Json::object(vec![
+            ("node", Json::str("Try")),
+            ("body", expr_json(body)),
+            ("name", Json::str(name.as_str())),
+            ("catch", expr_json(handler)),
+        ]),
+        Expr::Tuple(items, _) => Json::object(vec![
+            ("node", Json::str("Tuple")),
+            ("items", Json::Array(items.iter().map(expr_json).collect())),
+        ]),
+        Expr::TupleIndex(tuple, index, _) => Json::object(vec![
+            ("node", Json::str("TupleIndex")),
+            ("tuple", expr_json(tuple)),
+            ("index", Json::Int(*index as i64)),
+        ]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, tokenize};
+    use crate::parser::Parser;
+
+    fn parse_helper(input: &str) -> Vec<Stmt> {
+        Parser::new(Lexer::new(input))
+            .unwrap()
+            .parse_program()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_tokens_text() {
+        let tokens = tokenize("let x = \"hi\"; // done\n99999999999999999999").unwrap();
+        assert_eq!(
+            tokens_text(&tokens),
+            "1:1     Let\n\
+             1:5     Identifier \"x\"\n\
+             1:7     Eq\n\
+             1:9     Str \"hi\"\n\
+             1:13    SemiColon\n\
+             1:15    Comment \"// done\"\n\
+             2:1     BigInt \"99999999999999999999\"\n\
+             2:21    Eof\n"
+        );
+    }
+
+    #[test]
+    fn test_tokens_json() {
+        let tokens = tokenize("x 1.5").unwrap();
+        assert_eq!(
+            tokens_json(&tokens).to_string(),
+            r#"[{"kind":"Identifier","line":1,"col":1,"value":"x"},{"kind":"Float","line":1,"col":3,"value":1.5},{"kind":"Eof","line":1,"col":6}]"#
+        );
+    }
+
+    #[test]
+    fn test_ast_text() {
+        let program = parse_helper(
+            "/// Doubles.\nfn double(n) { let two = 2; n * two }\nif double(2) > 3 { 1 } else { -1 }",
+        );
+        assert_eq!(
+            ast_text(&program),
+            "Fn double(n)\n\
+             \x20 Doc \"Doubles.\"\n\
+             \x20 Block\n\
+             \x20   Let two\n\
+             \x20     Literal Int 2\n\
+             \x20   Tail\n\
+             \x20     Binary Mul\n\
+             \x20       Variable n\n\
+             \x20       Variable two\n\
+             Expression\n\
+             \x20 If\n\
+             \x20   Binary Gt\n\
+             \x20     Call double\n\
+             \x20       Literal Int 2\n\
+             \x20     Literal Int 3\n\
+             \x20   Block\n\
+             \x20     Tail\n\
+             \x20       Literal Int 1\n\
+             \x20   Else\n\
+             \x20     Block\n\
+             \x20       Tail\n\
+             \x20         Unary Neg\n\
+             \x20           Literal Int 1\n"
+        );
+    }
+
+    #[test]
+    fn test_ast_json() {
+        let program = parse_helper("x += 1;");
+        assert_eq!(
+            ast_json(&program).to_string(),
+            r#"[{"node":"Assign","name":"x","value":{"node":"Binary","op":"Add","lhs":{"node":"Variable","name":"x"},"rhs":{"node":"Literal","type":"Int","value":1}}}]"#
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Builtins for reading and writing files.
+//!
+//! Paths outside the directories the host granted raise a permission error;
+//! everything that can go wrong on the file system itself, such as a missing
+//! file, is returned as an `Err` value holding a message.
+
+use crate::interpreter::{Environment, RuntimeError, Value, str_operand};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Returns the contents of a file as a string.
+pub fn read_file(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (path, resolved) = readable("read_file", args, env)?;
+    Ok(result(&path, fs::read_to_string(resolved).map(Value::Str)))
+}
+
+/// Returns the lines of a file, without their line endings.
+pub fn read_lines(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (path, resolved) = readable("read_lines", args, env)?;
+    let lines = fs::read_to_string(resolved).map(|text| {
+        Value::List(
+            text.lines()
+                .map(|line| Value::Str(line.to_string()))
+                .collect(),
+        )
+    });
+    Ok(result(&path, lines))
+}
+
+/// Replaces the contents of a file, creating it if needed.
+pub fn write_file(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (path, resolved, text) = writable("write_file", args, env)?;
+    Ok(result(
+        &path,
+        fs::write(resolved, text).map(|_| Value::Unit),
+    ))
+}
+
+/// Adds text to the end of a file, creating it if needed.
+pub fn append_file(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (path, resolved, text) = writable("append_file", args, env)?;
+    let appended = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(resolved)
+        .and_then(|mut file| file.write_all(text.as_bytes()));
+    Ok(result(&path, appended.map(|_| Value::Unit)))
+}
+
+/// Returns whether a file or directory exists.
+pub fn file_exists(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (_, resolved) = readable("file_exists", args, env)?;
+    Ok(Value::Bool(resolved.exists()))
+}
+
+/// Returns the size of a file in bytes.
+pub fn file_size(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (path, resolved) = readable("file_size", args, env)?;
+    let size = fs::metadata(resolved)
+        .map(|metadata| Value::Int(i64::try_from(metadata.len()).unwrap_or(i64::MAX)));
+    Ok(result(&path, size))
+}
+
+/// Returns the names of the entries of a directory in alphabetical order.
+pub fn list_dir(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let (path, resolved) = readable("list_dir", args, env)?;
+    let names = fs::read_dir(resolved).and_then(|entries| {
+        let mut names = entries
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<io::Result<Vec<_>>>()?;
+        names.sort();
+        Ok(Value::List(names.into_iter().map(Value::Str).collect()))
+    });
+    Ok(result(&path, names))
+}
+
+/// Deletes a file.
+pub fn remove_file(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let path = str_operand("remove_file", args)?;
+    let resolved = env.permissions().check_write(&path)?;
+    Ok(result(
+        &path,
+        fs::remove_file(resolved).map(|_| Value::Unit),
+    ))
+}
+
+/// Creates a directory and any missing parents.
+pub fn mkdir(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let path = str_operand("mkdir", args)?;
+    let resolved = env.permissions().check_write(&path)?;
+    Ok(result(
+        &path,
+        fs::create_dir_all(resolved).map(|_| Value::Unit),
+    ))
+}
+
+/// Takes the path argument of a builtin that reads.
+fn readable(
+    name: &str,
+    args: Vec<Value>,
+    env: &Environment,
+) -> Result<(String, PathBuf), RuntimeError> {
+    let path = str_operand(name, args)?;
+    let resolved = env.permissions().check_read(&path)?;
+    Ok((path, resolved))
+}
+
+/// Takes the path and text arguments of a builtin that writes.
+fn writable(
+    name: &str,
+    args: Vec<Value>,
+    env: &Environment,
+) -> Result<(String, PathBuf, String), RuntimeError> {
+    let (path, text) = match args.as_slice() {
+        [Value::Str(path), Value::Str(text)] => (path.clone(), text.clone()),
+        [_, _] => {
+            return Err(RuntimeError::TypeMismatch(format!(
+                "{} expects a path and a string, got {:?}",
+                name, args
+            )));
+        }
+        _ => return Err(RuntimeError::ArgumentMismatch(2, args.len())),
+    };
+    let resolved = env.permissions().check_write(&path)?;
+    Ok((path, resolved, text))
+}
+
+/// Turns the outcome of a file operation on `path` into an `Ok` or `Err`
+/// value.
+fn result(path: &str, outcome: io::Result<Value>) -> Value {
+    match outcome {
+        Ok(value) => Value::Ok(Box::new(value)),
+        Err(err) => Value::Err(Box::new(Value::Str(format!("{}: {}", path, err)))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::eval_in;
+    use crate::permissions::{Capability, Permissions};
+
+    /// A directory that is removed again when the test ends.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("toy-rs-files-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.0.join(name).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn run(input: &str, permissions: Permissions) -> Result<Value, RuntimeError> {
+        eval_in(input, &mut Environment::with_permissions(permissions))
+    }
+
+    fn within(dir: &TempDir) -> Permissions {
+        let mut permissions = Permissions::default();
+        permissions.allow(Capability::Read, Some(&dir.0));
+        permissions.allow(Capability::Write, Some(&dir.0));
+        permissions
+    }
+
+    fn ok(value: Value) -> Result<Value, RuntimeError> {
+        Ok(Value::Ok(Box::new(value)))
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let dir = TempDir::new("write");
+        let file = dir.path("notes.txt");
+        let input = format!(
+            "
+            unwrap(write_file(\"{0}\", \"one\n\"));
+            unwrap(append_file(\"{0}\", \"two\n\"));
+            read_file(\"{0}\")
+            ",
+            file
+        );
+        assert_eq!(
+            run(&input, within(&dir)),
+            ok(Value::Str("one\ntwo\n".to_string()))
+        );
+        let input = format!("read_lines(\"{}\")", file);
+        assert_eq!(
+            run(&input, within(&dir)),
+            ok(Value::List(vec![
+                Value::Str("one".to_string()),
+                Value::Str("two".to_string())
+            ]))
+        );
+        let input = format!("file_size(\"{}\")", file);
+        assert_eq!(run(&input, within(&dir)), ok(Value::Int(8)));
+    }
+
+    #[test]
+    fn test_directories() {
+        let dir = TempDir::new("dirs");
+        let input = format!(
+            "
+            unwrap(mkdir(\"{0}\"));
+            unwrap(write_file(\"{0}/b.txt\", \"\"));
+            unwrap(write_file(\"{0}/a.txt\", \"\"));
+            let existed = file_exists(\"{0}/b.txt\");
+            unwrap(remove_file(\"{0}/b.txt\"));
+            let listed = if existed && !file_exists(\"{0}/b.txt\") {{ list_dir(\"{0}\") }} else {{ Err(\"b.txt\") }};
+            listed
+            ",
+            dir.path("sub/dir")
+        );
+        assert_eq!(
+            run(&input, within(&dir)),
+            ok(Value::List(vec![Value::Str("a.txt".to_string())]))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = TempDir::new("errors");
+        let missing = dir.path("missing.txt");
+        let input = format!("read_file(\"{}\")", missing);
+        match run(&input, within(&dir)) {
+            Ok(Value::Err(message)) => {
+                assert!(message.to_string().starts_with(&format!("{}: ", missing)))
+            }
+            other => panic!("expected an error value, got {:?}", other),
+        }
+        let input = format!("remove_file(\"{}\")", missing);
+        assert!(matches!(run(&input, within(&dir)), Ok(Value::Err(_))));
+
+        // Paths outside the granted directory are refused.
+        let mut permissions = Permissions::default();
+        permissions.allow(Capability::Read, Some(&dir.0));
+        assert_eq!(
+            run("file_exists(\"/\")", permissions.clone()),
+            Err(RuntimeError::PermissionDenied(
+                "cannot read '/' (grant access with --allow-read)".to_string()
+            ))
+        );
+        let input = format!("write_file(\"{}\", \"x\")", dir.path("out.txt"));
+        assert_eq!(
+            run(&input, permissions),
+            Err(RuntimeError::PermissionDenied(
+                "write_file requires --allow-write".to_string()
+            ))
+        );
+    }
+}
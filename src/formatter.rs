@@ -0,0 +1,644 @@
+use crate::ast::{BinaryOp, Expr, Literal, Stmt, UnaryOp, signature, tuple};
+use crate::lexer::{Lexer, Span, Token, tokenize};
+use crate::parser::{ParseError, Parser};
+use std::collections::HashMap;
+
+const INDENT: &str = "    ";
+
+/// Reformats a program into the canonical style, keeping its comments.
+pub fn format_source(source: &str) -> Result<String, ParseError> {
+    let program = Parser::new(Lexer::new(source))?.parse_program()?;
+    let tokens = tokenize(source)?;
+    let mut formatter = Formatter::new(source, &tokens);
+    // The lexer skips a `#!` line, so copy it over unchanged.
+    if source.starts_with("#!") {
+        let shebang = source.lines().next().unwrap_or_default();
+        formatter.out.push_str(shebang.trim_end());
+        formatter.out.push('\n');
+    }
+    formatter.program(&program);
+    Ok(formatter.out)
+}
+
+struct Comment {
+    text: String,
+    span: Span,
+    // Whether code precedes the comment on its line.
+    trailing: bool,
+}
+
+struct Formatter<'a> {
+    out: String,
+    lines: Vec<&'a str>,
+    comments: Vec<Comment>,
+    next_comment: usize,
+    // The source spelling of number literals, e.g. `0xFF` or `1_000`.
+    numbers: HashMap<Span, String>,
+    // Maps the span of each `{` to the span of its matching `}`.
+    block_ends: HashMap<Span, Span>,
+    indent: usize,
+    // Set when the last output line ends in a `//` comment, so that nothing
+    // more can be appended to it.
+    line_comment: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: &'a str, tokens: &[(Token, Span)]) -> Self {
+        let lines: Vec<&str> = source.lines().collect();
+        let mut comments = Vec::new();
+        let mut numbers = HashMap::new();
+        let mut block_ends = HashMap::new();
+        let mut open_braces = Vec::new();
+        let mut last_code_line = None;
+        for (i, (token, span)) in tokens.iter().enumerate() {
+            let text = match token {
+                Token::Comment(text) => Some(text.clone()),
+                Token::DocComment(text) => Some(doc_comment("///", text)),
+                Token::InnerDocComment(text) => Some(doc_comment("//!", text)),
+                _ => None,
+            };
+            if let Some(text) = text {
+                comments.push(Comment {
+                    text,
+                    span: *span,
+                    trailing: last_code_line == Some(span.line),
+                });
+                continue;
+            }
+            match token {
+                Token::Int(_) | Token::BigInt(_) | Token::Float(_) => {
+                    let next = tokens.get(i + 1).map_or(*span, |(_, next)| *next);
+                    numbers.insert(*span, source_between(&lines, *span, next));
+                }
+                Token::LBrace => open_braces.push(*span),
+                Token::RBrace => {
+                    if let Some(open) = open_braces.pop() {
+                        block_ends.insert(open, *span);
+                    }
+                }
+                _ => {}
+            }
+            last_code_line = Some(span.line);
+        }
+        Formatter {
+            out: String::new(),
+            lines,
+            comments,
+            next_comment: 0,
+            numbers,
+            block_ends,
+            indent: 0,
+            line_comment: false,
+        }
+    }
+
+    fn program(&mut self, program: &[Stmt]) {
+        let mut first = true;
+        for (i, stmt) in program.iter().enumerate() {
+            self.item_start(stmt.span(), &mut first);
+            self.stmt(stmt);
+            // Block-like statements need no `;` at the top level, unless the
+            // next statement would otherwise continue them as `if .. {} - x`.
+            match stmt {
+                Stmt::Expression(expr @ (Expr::If(..) | Expr::Block(..) | Expr::Try(..))) => {
+                    self.expr(expr);
+                    if program.get(i + 1).is_some_and(starts_with_minus) {
+                        self.out.push(';');
+                    }
+                }
+                Stmt::Expression(expr) => {
+                    self.expr(expr);
+                    self.out.push(';');
+                }
+                _ => {}
+            }
+            self.end_line();
+        }
+        self.flush_comments(
+            Span {
+                line: usize::MAX,
+                col: 0,
+            },
+            &mut first,
+        );
+    }
+
+    /// Prints the comments that precede `span` and the indentation of the
+    /// item starting there, separated by a blank line if the source had one.
+    fn item_start(&mut self, span: Span, first: &mut bool) {
+        self.flush_comments(span, first);
+        self.blank_line_before(span.line, first);
+        self.write_indent();
+    }
+
+    fn flush_comments(&mut self, before: Span, first: &mut bool) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span >= before {
+                break;
+            }
+            let (text, span, trailing) = (comment.text.clone(), comment.span, comment.trailing);
+            self.next_comment += 1;
+            let is_line_comment = text.starts_with("//");
+            if trailing && self.out.ends_with('\n') && !self.line_comment {
+                self.out.pop();
+                self.out.push(' ');
+                self.out.push_str(&text);
+                self.end_line();
+                self.line_comment = is_line_comment;
+                continue;
+            }
+            self.blank_line_before(span.line, first);
+            self.write_indent();
+            // Continuation lines of a block comment are kept verbatim.
+            self.out.push_str(&text);
+            self.end_line();
+            self.line_comment = is_line_comment;
+        }
+    }
+
+    fn blank_line_before(&mut self, line: usize, first: &mut bool) {
+        let blank_above = line >= 2
+            && self
+                .lines
+                .get(line - 2)
+                .is_some_and(|above| above.trim().is_empty());
+        if blank_above && !*first {
+            self.out.push('\n');
+        }
+        *first = false;
+    }
+
+    fn write_indent(&mut self) {
+        self.line_comment = false;
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn end_line(&mut self) {
+        self.out.push('\n');
+    }
+
+    /// Prints a statement without its line break. Expression statements are
+    /// left to the caller, which knows whether they need a `;`.
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let(name, ty, value, _, vis, _) => {
+                self.out.push_str(vis.prefix());
+                match ty {
+                    Some(ty) => self.out.push_str(&format!("let {}: {} = ", name, ty)),
+                    None => self.out.push_str(&format!("let {} = ", name)),
+                }
+                self.expr(value);
+                self.out.push(';');
+            }
+            Stmt::LetTuple(patterns, ty, value, _) => {
+                match ty {
+                    Some(ty) => self
+                        .out
+                        .push_str(&format!("let {}: {} = ", tuple(patterns), ty)),
+                    None => self.out.push_str(&format!("let {} = ", tuple(patterns))),
+                }
+                self.expr(value);
+                self.out.push(';');
+            }
+            Stmt::Fn(name, params, ret, body, _, vis, _) => {
+                self.out.push_str(&format!(
+                    "{}fn {} ",
+                    vis.prefix(),
+                    signature(name, params, ret.as_ref())
+                ));
+                self.expr(body);
+            }
+            Stmt::Assign(name, value, span) => {
+                match value {
+                    // The parser desugars `x += e` into `x = x + e`, reusing
+                    // the span of `x` for the inner variable.
+                    Expr::Binary(lhs, op, rhs)
+                        if matches!(
+                            op,
+                            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div
+                        ) && **lhs == Expr::Variable(name.clone(), *span) =>
+                    {
+                        self.out
+                            .push_str(&format!("{} {}= ", name, binary_symbol(op)));
+                        self.expr(rhs);
+                    }
+                    _ => {
+                        self.out.push_str(&format!("{} = ", name));
+                        self.expr(value);
+                    }
+                }
+                self.out.push(';');
+            }
+            Stmt::While(condition, body, _) => {
+                self.out.push_str("while ");
+                self.expr(condition);
+                self.out.push(' ');
+                self.expr(body);
+            }
+            Stmt::For(pattern, iterable, body, _) => {
+                self.out.push_str(&format!("for {} in ", pattern));
+                self.expr(iterable);
+                self.out.push(' ');
+                self.expr(body);
+            }
+            Stmt::Yield(value, _) => {
+                self.out.push_str("yield ");
+                self.expr(value);
+                self.out.push(';');
+            }
+            Stmt::Import(import, _) => self.out.push_str(&import.to_string()),
+            Stmt::ImplicitReturn(expr) => self.expr(expr),
+            Stmt::Expression(_) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(literal, span) => self.literal(literal, *span),
+            Expr::Variable(name, _) => self.out.push_str(name),
+            Expr::Unary(op, operand, _) => {
+                self.out.push(match op {
+                    UnaryOp::Neg => '-',
+                    UnaryOp::Not => '!',
+                });
+                self.operand(operand, matches!(**operand, Expr::Binary(..)));
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                let precedence = precedence(op);
+                self.operand(lhs, binary_precedence(lhs).is_some_and(|p| p < precedence));
+                self.out.push_str(&format!(" {} ", binary_symbol(op)));
+                self.operand(rhs, binary_precedence(rhs).is_some_and(|p| p <= precedence));
+            }
+            Expr::Call(name, args, _) => {
+                self.out.push_str(name);
+                self.args(args);
+            }
+            Expr::MethodCall(name, args, _) => {
+                let (receiver, args) = args.split_first().expect("a method call has a receiver");
+                self.operand(
+                    receiver,
+                    matches!(receiver, Expr::Binary(..) | Expr::Unary(..)),
+                );
+                self.out.push('.');
+                self.out.push_str(name);
+                self.args(args);
+            }
+            Expr::Block(stmts, tail, span) => self.block(stmts, tail.as_deref(), *span),
+            Expr::If(condition, then_branch, else_branch, _) => {
+                self.out.push_str("if ");
+                self.expr(condition);
+                self.out.push(' ');
+                self.expr(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.out.push_str(" else ");
+                    self.expr(else_branch);
+                }
+            }
+            Expr::Propagate(operand, _) => {
+                self.operand(
+                    operand,
+                    matches!(**operand, Expr::Binary(..) | Expr::Unary(..)),
+                );
+                self.out.push('?');
+            }
+            Expr::Try(body, name, handler, _) => {
+                self.out.push_str("try ");
+                self.expr(body);
+                self.out.push_str(&format!(" catch {} ", name));
+                self.expr(handler);
+            }
+            Expr::Tuple(items, _) => {
+                self.args(items);
+                if items.len() == 1 {
+                    // Keep `(a,)` from becoming the parenthesized `(a)`.
+                    self.out.insert(self.out.len() - 1, ',');
+                }
+            }
+            Expr::TupleIndex(tuple, index, _) => {
+                self.operand(tuple, matches!(**tuple, Expr::Binary(..) | Expr::Unary(..)));
+                self.out.push_str(&format!(".{}", index));
+            }
+        }
+    }
+
+    fn args(&mut self, args: &[Expr]) {
+        self.out.push('(');
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expr(arg);
+        }
+        self.out.push(')');
+    }
+
+    fn operand(&mut self, expr: &Expr, parenthesize: bool) {
+        if parenthesize {
+            self.out.push('(');
+            self.expr(expr);
+            self.out.push(')');
+        } else {
+            self.expr(expr);
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal, span: Span) {
+        let text = match literal {
+            Literal::Bool(b) => b.to_string(),
+            Literal::Str(s) => format!("\"{}\"", s),
+            Literal::Int(_) | Literal::BigInt(_) | Literal::Float(_) => {
+                match self.numbers.get(&span) {
+                    Some(text) => text.clone(),
+                    None => match literal {
+                        Literal::Int(i) => i.to_string(),
+                        Literal::BigInt(big) => big.to_string(),
+                        Literal::Float(f) => format!("{:?}", f),
+                        _ => unreachable!(),
+                    },
+                }
+            }
+        };
+        self.out.push_str(&text);
+    }
+
+    fn block(&mut self, stmts: &[Stmt], tail: Option<&Expr>, span: Span) {
+        let end = self.block_ends.get(&span).copied().unwrap_or(span);
+        let has_comments = self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|comment| comment.span < end);
+        if stmts.is_empty() && tail.is_none() && !has_comments {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push('{');
+        self.end_line();
+        self.indent += 1;
+        let mut first = true;
+        for stmt in stmts {
+            self.item_start(stmt.span(), &mut first);
+            self.stmt(stmt);
+            if let Stmt::Expression(expr) = stmt {
+                self.expr(expr);
+                self.out.push(';');
+            }
+            self.end_line();
+        }
+        if let Some(tail) = tail {
+            self.item_start(tail.span(), &mut first);
+            self.expr(tail);
+            self.end_line();
+        }
+        self.flush_comments(end, &mut first);
+        self.indent -= 1;
+        self.write_indent();
+        self.out.push('}');
+    }
+}
+
+fn doc_comment(prefix: &str, text: &str) -> String {
+    if text.is_empty() {
+        prefix.to_string()
+    } else {
+        format!("{} {}", prefix, text)
+    }
+}
+
+// Returns the source text from `start` up to `end`, without trailing space.
+fn source_between(lines: &[&str], start: Span, end: Span) -> String {
+    let line = lines[start.line - 1];
+    let from: String = line.chars().skip(start.col - 1).collect();
+    let text = if end.line == start.line {
+        from.chars().take(end.col - start.col).collect()
+    } else {
+        from
+    };
+    text.trim_end().to_string()
+}
+
+fn starts_with_minus(stmt: &Stmt) -> bool {
+    fn leftmost(expr: &Expr) -> &Expr {
+        match expr {
+            Expr::Binary(lhs, _, _) => leftmost(lhs),
+            _ => expr,
+        }
+    }
+    match stmt {
+        Stmt::Expression(expr) | Stmt::ImplicitReturn(expr) => {
+            matches!(leftmost(expr), Expr::Unary(UnaryOp::Neg, ..))
+        }
+        _ => false,
+    }
+}
+
+// Mirrors the binding powers used by the parser.
+fn precedence(op: &BinaryOp) -> u8 {
+    match op {
+        BinaryOp::Mul | BinaryOp::Div => 20,
+        BinaryOp::Add | BinaryOp::Sub => 10,
+        BinaryOp::Eq | BinaryOp::Lt | BinaryOp::Gt => 5,
+        BinaryOp::And => 3,
+        BinaryOp::Or => 1,
+    }
+}
+
+fn binary_precedence(expr: &Expr) -> Option<u8> {
+    match expr {
+        Expr::Binary(_, op, _) => Some(precedence(op)),
+        _ => None,
+    }
+}
+
+fn binary_symbol(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Lt => "<",
+        BinaryOp::Gt => ">",
+        BinaryOp::Eq => "==",
+        BinaryOp::And => "&&",
+        BinaryOp::Or => "||",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump::ast_text;
+    use std::fs;
+
+    fn format(input: &str) -> String {
+        format_source(input).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn ast(input: &str) -> String {
+        ast_text(
+            &Parser::new(Lexer::new(input))
+                .unwrap()
+                .parse_program()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_canonical_layout() {
+        let input = "let x=1+2*3;fn add(a,b){a+b}\nif x>1{print(x);}else if x<0{x-=1;}else{}\n{let y=(1+2)*-(x-1);y}\nwhile x<10{x+=1;}\nadd(x,2)";
+        assert_eq!(
+            format(input),
+            "let x = 1 + 2 * 3;
+fn add(a, b) {
+    a + b
+}
+if x > 1 {
+    print(x);
+} else if x < 0 {
+    x -= 1;
+} else {}
+{
+    let y = (1 + 2) * -(x - 1);
+    y
+}
+while x < 10 {
+    x += 1;
+}
+add(x, 2)
+"
+        );
+    }
+
+    #[test]
+    fn test_parentheses() {
+        assert_eq!(format("(a - b) - c;"), "a - b - c;\n");
+        assert_eq!(format("a - (b - c);"), "a - (b - c);\n");
+        assert_eq!(format("(a || b) && !(c == d);"), "(a || b) && !(c == d);\n");
+        assert_eq!(format("x = x / (2);"), "x = x / 2;\n");
+        assert_eq!(format("x /= (2);"), "x /= 2;\n");
+        assert_eq!(format("x = y + 1;"), "x = y + 1;\n");
+        assert_eq!(format("-(f()?)? + (a + b)?;"), "-f()?? + (a + b)?;\n");
+    }
+
+    #[test]
+    fn test_try_catch() {
+        assert_eq!(
+            format("let r=try{f()?}catch e{println(e);0};"),
+            "let r = try {\n    f()?\n} catch e {\n    println(e);\n    0\n};\n"
+        );
+        assert_eq!(format("try {} catch e {}"), "try {} catch e {}\n");
+    }
+
+    #[test]
+    fn test_iteration() {
+        assert_eq!(
+            format("fn odds(){for n in range( 0 ){if !even(n){yield n*1;}}}"),
+            "fn odds() {\n    for n in range(0) {\n        if !even(n) {\n            yield n * 1;\n        }\n    }\n}\n"
+        );
+        assert_eq!(format("( -x ).abs ( ).map( f )?;"), "(-x).abs().map(f)?;\n");
+    }
+
+    #[test]
+    fn test_tuples() {
+        assert_eq!(
+            format("let ( q,(r ,) ):(int,( int , ))=( a,(b ,) );"),
+            "let (q, (r,)): (int, (int,)) = (a, (b,));\n"
+        );
+        assert_eq!(
+            format("fn swap( (a,b) ) { ( b,a ) }\nfor (i , x) in xs { ( ( a ) ) ; () ; }"),
+            "fn swap((a, b)) {\n    (b, a)\n}\nfor (i, x) in xs {\n    a;\n    ();\n}\n"
+        );
+        assert_eq!(format("(-t).0 + t.0 .1;"), "(-t).0 + t.0.1;\n");
+    }
+
+    #[test]
+    fn test_imports() {
+        assert_eq!(
+            format("import \"lib/a.toy\" ;import lib::b::{ f,g };a::x + f(b::y)"),
+            "import \"lib/a.toy\";\nimport lib::b::{f, g};\na::x + f(b::y)\n"
+        );
+    }
+
+    #[test]
+    fn test_visibility() {
+        assert_eq!(
+            format("/// Doubles.\npub   fn double( n ) { n*2 }\npub let  x=1;"),
+            "/// Doubles.\npub fn double(n) {\n    n * 2\n}\npub let x = 1;\n"
+        );
+    }
+
+    #[test]
+    fn test_shebang() {
+        assert_eq!(
+            format("#!/usr/bin/env toy-rs  \n\n// hi\nprintln( 1 )"),
+            "#!/usr/bin/env toy-rs\n// hi\nprintln(1)\n"
+        );
+    }
+
+    #[test]
+    fn test_keeps_literal_spelling() {
+        assert_eq!(
+            format("let a = 0xFF+1_000  ;let b = 2.50e3;"),
+            "let a = 0xFF + 1_000;\nlet b = 2.50e3;\n"
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "//! Module docs.
+
+///Adds.
+fn add(a, b) {   // trailing
+    // leading
+    a + /* inline */ b
+    // at the end
+}
+
+
+let x = add(1, 2); /* block
+   comment */
+// last
+";
+        assert_eq!(
+            format(input),
+            "//! Module docs.
+
+/// Adds.
+fn add(a, b) { // trailing
+    // leading
+    a + b /* inline */
+    // at the end
+}
+
+let x = add(1, 2); /* block
+   comment */
+// last
+"
+        );
+    }
+
+    #[test]
+    fn test_block_like_statement_before_negation() {
+        let input = "if a { 1 } else { 2 };\n-x;";
+        assert_eq!(format(input), "if a {\n    1\n} else {\n    2\n};\n-x;\n");
+        assert_eq!(ast(&format(input)), ast(input));
+    }
+
+    #[test]
+    fn test_round_trip_examples() {
+        for entry in fs::read_dir("tests").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "toy") {
+                continue;
+            }
+            let source = fs::read_to_string(&path).unwrap();
+            // Skip fixtures that intentionally fail to parse.
+            let Ok(formatted) = format_source(&source) else {
+                continue;
+            };
+            assert_eq!(ast(&formatted), ast(&source), "{}", path.display());
+            assert_eq!(format(&formatted), formatted, "{}", path.display());
+        }
+    }
+}
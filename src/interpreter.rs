@@ -1,29 +1,257 @@
 use crate::ast;
 use crate::ast::BinaryOp;
+use crate::bigint::BigInt;
+use crate::collections;
+use crate::files;
+use crate::iterators::{self, Iter};
+use crate::lexer::Span;
+use crate::modules::{self, Module, Modules};
+use crate::permissions::{Capability, Permissions};
+use crate::system;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::io::{self, BufRead, BufReader};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// The default limit on nested function calls. Calls in tail position do not
+/// count towards it.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1_000;
 
 #[derive(Debug, Clone)]
 pub enum Value {
     Int(i64),
+    // Only used for integers that don't fit in an `i64`.
+    BigInt(BigInt),
     Float(f64),
     Bool(bool),
     Str(String),
     Unit,
-    Function(Vec<String>, ast::Expr),
-    NativeFunc(fn(Vec<Value>) -> Value),
+    /// A function defined in a script, with the imported file it was
+    /// defined in, or `None` for the script itself.
+    Function(Vec<ast::Pattern>, ast::Expr, Option<Rc<Module>>),
+    NativeFunc(NativeFn),
+    /// The result of an operation that succeeded.
+    Ok(Box<Value>),
+    /// The result of an operation that failed, usually holding a message.
+    Err(Box<Value>),
+    List(Vec<Value>),
+    /// A tuple of at least one item. The empty tuple is `Value::Unit`.
+    Tuple(Vec<Value>),
+    /// Hands out items one at a time; see `iterators`.
+    Iterator(Iter),
+}
+
+/// A builtin function. It is given the environment of the call site.
+pub type NativeFn = fn(Vec<Value>, &Environment) -> Result<Value, RuntimeError>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    DivisionByZero,
+    UndefinedVariable(String),
+    UndefinedFunction(String),
+    AssignToUndefined(String),
+    TypeMismatch(String),
+    ArgumentMismatch(usize, usize),
+    NotCallable(String),
+    InvalidCondition,
+    /// More calls than the given limit were nested.
+    StackOverflow(usize),
+    /// One of the other sandbox limits was reached.
+    LimitExceeded(Limit),
+    /// A list was indexed past its end; holds the index and the length.
+    IndexOutOfBounds(i64, usize),
+    /// `value.index` on something that is not a tuple with that many items.
+    NoTupleIndex(String, usize),
+    /// A tuple pattern was matched against a value that is not a tuple of
+    /// its length; holds the value and the length of the pattern.
+    PatternMismatch(String, usize),
+    /// The script called `exit` with this status.
+    Exit(i32),
+    /// The script used a capability the host has not granted.
+    PermissionDenied(String),
+    /// A file could not be imported.
+    Import(String),
+    /// A definition that is not `pub` was used outside of its file; holds
+    /// its name and the file.
+    PrivateItem(String, String),
+    /// Raised by `error(value)` or by unwrapping an `Err(value)`.
+    Raised(Value),
+    /// An `Err(value)` passed on by `?` that has not yet reached a `try` or
+    /// the end of the enclosing function.
+    Propagated(Value),
+    /// A `yield` that a generator cannot suspend at.
+    MisplacedYield,
+    /// An iterator was asked for an item while producing one.
+    IteratorRunning,
+    /// An error raised inside function calls, with the calls that were
+    /// active when it happened, innermost first.
+    Traced(Box<RuntimeError>, Vec<Frame>),
+}
+
+impl RuntimeError {
+    /// The underlying error, without the stack trace.
+    pub fn cause(&self) -> &RuntimeError {
+        match self {
+            RuntimeError::Traced(cause, _) => cause,
+            _ => self,
+        }
+    }
+
+    /// The value bound by `catch` when this error is caught: the raised
+    /// value for script errors and the message for all others.
+    pub fn value(&self) -> Value {
+        match self.cause() {
+            RuntimeError::Raised(value) | RuntimeError::Propagated(value) => value.clone(),
+            cause => Value::Str(cause.to_string()),
+        }
+    }
+
+    /// The calls that were active when the error happened, innermost first.
+    pub fn trace(&self) -> &[Frame] {
+        match self {
+            RuntimeError::Traced(_, trace) => trace,
+            _ => &[],
+        }
+    }
+}
+
+/// A function call on the interpreter's call stack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    /// Where the function was called from.
+    pub call_site: Span,
+    /// The imported file the call is in, or `None` for the script itself.
+    pub module: Option<Rc<Module>>,
+}
+
+/// Renders a stack trace one frame per line, like `    at fib (script.toy:4:9)`.
+/// Recursion through the same call site is collapsed and the middle of very
+/// deep traces is left out.
+pub fn traceback(trace: &[Frame], filename: &str) -> String {
+    let mut lines = Vec::new();
+    let mut index = 0;
+    while index < trace.len() {
+        let frame = &trace[index];
+        let repeats = trace[index + 1..]
+            .iter()
+            .take_while(|other| *other == frame)
+            .count();
+        let filename = frame
+            .module
+            .as_ref()
+            .map_or(filename, |module| module.file.as_str());
+        let location = if filename.is_empty() {
+            frame.call_site.to_string()
+        } else {
+            format!("{}:{}", filename, frame.call_site)
+        };
+        // Private definitions of imported files are shown by the name they
+        // were written with.
+        let function = frame.function.replace("::#", "::");
+        lines.push(format!("    at {} ({})", function, location));
+        if repeats > 0 {
+            let times = if repeats == 1 { "time" } else { "times" };
+            lines.push(format!(
+                "    [previous frame repeated {} more {}]",
+                repeats, times
+            ));
+        }
+        index += repeats + 1;
+    }
+    if lines.len() > 20 {
+        let omitted = lines.len() - 20;
+        lines.splice(
+            10..10 + omitted,
+            [format!("    ... {} more frames ...", omitted)],
+        );
+    }
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::DivisionByZero => write!(f, "Division by zero"),
+            RuntimeError::UndefinedVariable(name) => write!(f, "Undefined variable: {}", name),
+            RuntimeError::UndefinedFunction(name) => write!(f, "Undefined function: {}", name),
+            RuntimeError::AssignToUndefined(name) => {
+                write!(f, "Cannot assign to undefined variable '{}'", name)
+            }
+            RuntimeError::TypeMismatch(msg) => write!(f, "Type mismatch: {}", msg),
+            RuntimeError::ArgumentMismatch(expected, got) => {
+                write!(
+                    f,
+                    "Mismatched arguments: expected {}, got {}",
+                    expected, got
+                )
+            }
+            RuntimeError::NotCallable(what) => write!(f, "Can only call functions, not {}", what),
+            RuntimeError::InvalidCondition => write!(f, "While loop condition must be a boolean!"),
+            RuntimeError::StackOverflow(limit) => {
+                write!(f, "Stack overflow: more than {} nested calls", limit)
+            }
+            RuntimeError::LimitExceeded(limit) => write!(f, "{}", limit),
+            RuntimeError::IndexOutOfBounds(index, len) => {
+                write!(
+                    f,
+                    "Index {} is out of bounds for a list of {} items",
+                    index, len
+                )
+            }
+            RuntimeError::NoTupleIndex(value, index) => {
+                write!(f, "{} has no item at index {}", value, index)
+            }
+            RuntimeError::PatternMismatch(value, len) => {
+                let items = if *len == 1 { "item" } else { "items" };
+                write!(
+                    f,
+                    "Cannot take {} apart as a tuple of {} {}",
+                    value, len, items
+                )
+            }
+            RuntimeError::Exit(code) => write!(f, "Exited with status {}", code),
+            RuntimeError::PermissionDenied(msg) => write!(f, "Permission denied: {}", msg),
+            RuntimeError::Import(msg) => write!(f, "Import error: {}", msg),
+            RuntimeError::PrivateItem(name, file) => {
+                write!(f, "'{}' is private to {}", name, file)
+            }
+            RuntimeError::Raised(value) => write!(f, "{}", value),
+            RuntimeError::Propagated(value) => write!(f, "Unhandled error: {}", value),
+            RuntimeError::MisplacedYield => write!(
+                f,
+                "'yield' can only be a statement of a generator's body, or of the blocks, ifs and loops in it"
+            ),
+            RuntimeError::IteratorRunning => {
+                write!(f, "An iterator was used while it was producing an item")
+            }
+            RuntimeError::Traced(cause, trace) => {
+                write!(f, "{}\n{}", cause, traceback(trace, "").trim_end())
+            }
+        }
+    }
 }
 
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Value::Int(l), Value::Int(r)) => l == r,
+            (Value::BigInt(l), Value::BigInt(r)) => l == r,
             (Value::Float(l), Value::Float(r)) => l == r,
             (Value::Bool(l), Value::Bool(r)) => l == r,
             (Value::Str(l), Value::Str(r)) => l == r,
             (Value::Unit, Value::Unit) => true,
-            (Value::Function(lp, lb), Value::Function(rp, rb)) => lp == rp && lb == rb,
+            (Value::Function(lp, lb, lm), Value::Function(rp, rb, rm)) => {
+                lp == rp && lb == rb && lm == rm
+            }
             (Value::NativeFunc(l), Value::NativeFunc(r)) => *l as usize == *r as usize,
+            (Value::Ok(l), Value::Ok(r)) | (Value::Err(l), Value::Err(r)) => l == r,
+            (Value::List(l), Value::List(r)) | (Value::Tuple(l), Value::Tuple(r)) => l == r,
+            (Value::Iterator(l), Value::Iterator(r)) => l == r,
             _ => false,
         }
     }
@@ -33,14 +261,223 @@ impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Value::Int(i) => write!(f, "{}", i),
+            Value::BigInt(i) => write!(f, "{}", i),
             Value::Float(fl) => write!(f, "{}", fl),
             Value::Bool(b) => write!(f, "{}", b),
             Value::Str(s) => write!(f, "{}", s),
             Value::Unit => write!(f, "()"),
-            Value::Function(params, _) => {
+            Value::Function(params, ..) => {
+                let params: Vec<String> = params.iter().map(ToString::to_string).collect();
                 write!(f, "<fn ({})>", params.join(", "))
             }
             Value::NativeFunc(_) => write!(f, "<native fn>"),
+            Value::Ok(value) => write!(f, "Ok({})", value),
+            Value::Err(value) => write!(f, "Err({})", value),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(Value::item).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(Value::item).collect();
+                write!(f, "{}", ast::tuple(&items))
+            }
+            Value::Iterator(_) => write!(f, "<iterator>"),
+        }
+    }
+}
+
+impl Value {
+    /// Builds a tuple, which is `Value::Unit` when there are no items.
+    pub fn tuple(items: Vec<Value>) -> Value {
+        if items.is_empty() {
+            Value::Unit
+        } else {
+            Value::Tuple(items)
+        }
+    }
+
+    /// Renders an item of a list or tuple, quoting strings.
+    fn item(&self) -> String {
+        match self {
+            Value::Str(s) => format!("{:?}", s),
+            item => item.to_string(),
+        }
+    }
+}
+
+/// Builtins that are only defined when the host grants their capability.
+const GATED_BUILTINS: &[(&str, Capability, NativeFn)] = &[
+    ("clock", Capability::Clock, clock),
+    ("env_var", Capability::Env, system::env_var),
+    ("set_env_var", Capability::Env, system::set_env_var),
+    ("read_file", Capability::Read, files::read_file),
+    ("read_lines", Capability::Read, files::read_lines),
+    ("file_exists", Capability::Read, files::file_exists),
+    ("file_size", Capability::Read, files::file_size),
+    ("list_dir", Capability::Read, files::list_dir),
+    ("write_file", Capability::Write, files::write_file),
+    ("append_file", Capability::Write, files::append_file),
+    ("remove_file", Capability::Write, files::remove_file),
+    ("mkdir", Capability::Write, files::mkdir),
+];
+
+/// Returns the current time in seconds since the Unix epoch.
+fn clock(args: Vec<Value>, _: &Environment) -> Result<Value, RuntimeError> {
+    if !args.is_empty() {
+        return Err(RuntimeError::ArgumentMismatch(0, args.len()));
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64());
+    Ok(Value::Float(now))
+}
+
+/// A sandbox limit that stopped a script.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Limit {
+    /// More than this many expressions were evaluated.
+    Steps(u64),
+    /// A string grew beyond this many bytes or a list beyond this many items.
+    Size(usize),
+    /// The deadline passed.
+    Deadline,
+    /// A `CancelHandle` was triggered.
+    Cancelled,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Steps(max) => write!(f, "Step limit exceeded: more than {} steps", max),
+            Limit::Size(max) => {
+                write!(
+                    f,
+                    "Size limit exceeded: a value grew beyond {} bytes or items",
+                    max
+                )
+            }
+            Limit::Deadline => write!(f, "Time limit exceeded"),
+            Limit::Cancelled => write!(f, "Execution cancelled"),
+        }
+    }
+}
+
+/// Stops a running script from another thread. The script notices at its
+/// next check, within a few hundred evaluation steps.
+// The command line has no way to cancel a script yet; only tests use this.
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Clone)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// How often, in steps, the deadline and the cancellation flag are checked.
+const CHECK_INTERVAL: u64 = 256;
+
+/// The state of an execution shared by every environment cloned from the
+/// same root: the functions currently being called and the sandbox limits.
+struct Runtime {
+    frames: Vec<Frame>,
+    max_depth: usize,
+    steps: u64,
+    max_steps: Option<u64>,
+    max_size: Option<usize>,
+    deadline: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+    permissions: Permissions,
+    /// The command line arguments given after the script.
+    args: Vec<String>,
+    /// Environment variables set by the script.
+    env_vars: HashMap<String, String>,
+    /// Where `read_line` and friends read from, standard input by default.
+    input: Box<dyn BufRead>,
+    /// Set once a script catches a step, time or cancellation error: it gets
+    /// this many more steps to handle it before the limits apply again.
+    grace_until: Option<u64>,
+    modules: Modules,
+    /// The imported file whose code is running, or `None` for the script.
+    module: Option<Rc<Module>>,
+    /// The definitions of the prelude, if it was loaded.
+    prelude: Vec<(String, Value)>,
+    /// Where the builtin that is running was called from, for the frames
+    /// of the functions it calls back.
+    native_call_site: Span,
+}
+
+impl Runtime {
+    /// Counts one evaluation step against the limits.
+    fn step(&mut self) -> Result<(), RuntimeError> {
+        self.steps += 1;
+        if self.grace_until.is_some_and(|until| self.steps <= until) {
+            return Ok(());
+        }
+        if let Some(max) = self.max_steps
+            && self.steps > max
+        {
+            return Err(RuntimeError::LimitExceeded(Limit::Steps(max)));
+        }
+        if self.steps.is_multiple_of(CHECK_INTERVAL) {
+            if self.cancelled.load(Ordering::Relaxed) {
+                return Err(RuntimeError::LimitExceeded(Limit::Cancelled));
+            }
+            if self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Err(RuntimeError::LimitExceeded(Limit::Deadline));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a script may catch `limit`. Running out of steps or time, or
+    /// being cancelled, can be caught only once so that a `try` in a loop
+    /// cannot keep the script alive forever.
+    fn catch_limit(&mut self, limit: &Limit) -> bool {
+        match limit {
+            Limit::Size(_) => true,
+            _ if self.grace_until.is_some() => false,
+            _ => {
+                self.grace_until = Some(self.steps + CHECK_INTERVAL);
+                true
+            }
+        }
+    }
+
+    /// Rejects strings and lists longer than the size limit.
+    fn check_size(&self, value: &Value) -> Result<(), RuntimeError> {
+        match value {
+            Value::Str(s) => self.check_len(s.len()),
+            Value::List(items) | Value::Tuple(items) => self.check_len(items.len()),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_len(&self, len: usize) -> Result<(), RuntimeError> {
+        match self.max_size {
+            Some(max) if len > max => Err(RuntimeError::LimitExceeded(Limit::Size(max))),
+            _ => Ok(()),
+        }
+    }
+
+    fn push(&mut self, frame: Frame) -> Result<(), RuntimeError> {
+        if self.frames.len() >= self.max_depth {
+            return Err(RuntimeError::StackOverflow(self.max_depth));
+        }
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    /// Attaches the current call stack to an error that does not have one yet.
+    fn trace(&self, err: RuntimeError) -> RuntimeError {
+        match err {
+            RuntimeError::Traced(..) => err,
+            err => RuntimeError::Traced(Box::new(err), self.frames.iter().rev().cloned().collect()),
         }
     }
 }
@@ -48,16 +485,48 @@ impl fmt::Display for Value {
 #[derive(Clone)]
 pub struct Environment {
     values: HashMap<String, Value>,
+    runtime: Rc<RefCell<Runtime>>,
 }
 
 impl Environment {
+    /// Creates the global environment of a script that may not touch
+    /// anything outside the interpreter.
     pub fn new() -> Self {
+        Environment::with_permissions(Permissions::default())
+    }
+
+    /// Creates the global environment of a script with the builtins that
+    /// `permissions` allow.
+    pub fn with_permissions(permissions: Permissions) -> Self {
+        Environment::with_runtime(Rc::new(RefCell::new(Runtime {
+            frames: Vec::new(),
+            max_depth: DEFAULT_MAX_CALL_DEPTH,
+            steps: 0,
+            max_steps: None,
+            max_size: None,
+            deadline: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            permissions,
+            args: Vec::new(),
+            env_vars: HashMap::new(),
+            input: Box::new(BufReader::new(io::stdin())),
+            grace_until: None,
+            modules: Modules::default(),
+            module: None,
+            prelude: Vec::new(),
+            native_call_site: Span { line: 0, col: 0 },
+        })))
+    }
+
+    /// Creates a global environment with the builtins that share `runtime`.
+    fn with_runtime(runtime: Rc<RefCell<Runtime>>) -> Self {
         let mut env = Environment {
             values: HashMap::new(),
+            runtime,
         };
         env.define(
             "println".to_string(),
-            Value::NativeFunc(|args| {
+            Value::NativeFunc(|args, _| {
                 for (i, arg) in args.iter().enumerate() {
                     if i > 0 {
                         print!(" ");
@@ -65,24 +534,353 @@ impl Environment {
                     print!("{}", arg);
                 }
                 println!();
-                Value::Unit
+                Ok(Value::Unit)
             }),
         );
         env.define(
             "print".to_string(),
-            Value::NativeFunc(|args| {
+            Value::NativeFunc(|args, _| {
                 for (i, arg) in args.iter().enumerate() {
                     if i > 0 {
                         print!(" ");
                     }
                     print!("{}", arg);
                 }
-                Value::Unit
+                Ok(Value::Unit)
             }),
         );
+        // Explicit wrap-around and clamping arithmetic for when overflow is intended.
+        env.define(
+            "wrapping_add".to_string(),
+            Value::NativeFunc(|args, _| {
+                let (l, r) = int_operands("wrapping_add", args)?;
+                Ok(Value::Int(l.wrapping_add(r)))
+            }),
+        );
+        env.define(
+            "wrapping_sub".to_string(),
+            Value::NativeFunc(|args, _| {
+                let (l, r) = int_operands("wrapping_sub", args)?;
+                Ok(Value::Int(l.wrapping_sub(r)))
+            }),
+        );
+        env.define(
+            "wrapping_mul".to_string(),
+            Value::NativeFunc(|args, _| {
+                let (l, r) = int_operands("wrapping_mul", args)?;
+                Ok(Value::Int(l.wrapping_mul(r)))
+            }),
+        );
+        env.define(
+            "saturating_add".to_string(),
+            Value::NativeFunc(|args, _| {
+                let (l, r) = int_operands("saturating_add", args)?;
+                Ok(Value::Int(l.saturating_add(r)))
+            }),
+        );
+        env.define(
+            "saturating_sub".to_string(),
+            Value::NativeFunc(|args, _| {
+                let (l, r) = int_operands("saturating_sub", args)?;
+                Ok(Value::Int(l.saturating_sub(r)))
+            }),
+        );
+        env.define(
+            "saturating_mul".to_string(),
+            Value::NativeFunc(|args, _| {
+                let (l, r) = int_operands("saturating_mul", args)?;
+                Ok(Value::Int(l.saturating_mul(r)))
+            }),
+        );
+        // Results and errors.
+        env.define(
+            "Ok".to_string(),
+            Value::NativeFunc(|args, _| Ok(Value::Ok(Box::new(single_operand(args)?)))),
+        );
+        env.define(
+            "Err".to_string(),
+            Value::NativeFunc(|args, _| Ok(Value::Err(Box::new(single_operand(args)?)))),
+        );
+        env.define(
+            "error".to_string(),
+            Value::NativeFunc(|args, _| Err(RuntimeError::Raised(single_operand(args)?))),
+        );
+        env.define(
+            "is_ok".to_string(),
+            Value::NativeFunc(|args, _| match single_operand(args)? {
+                Value::Ok(_) => Ok(Value::Bool(true)),
+                Value::Err(_) => Ok(Value::Bool(false)),
+                other => Err(not_a_result("is_ok", other)),
+            }),
+        );
+        env.define(
+            "is_err".to_string(),
+            Value::NativeFunc(|args, _| match single_operand(args)? {
+                Value::Ok(_) => Ok(Value::Bool(false)),
+                Value::Err(_) => Ok(Value::Bool(true)),
+                other => Err(not_a_result("is_err", other)),
+            }),
+        );
+        env.define(
+            "unwrap".to_string(),
+            Value::NativeFunc(|args, _| match single_operand(args)? {
+                Value::Ok(value) => Ok(*value),
+                Value::Err(value) => Err(RuntimeError::Raised(*value)),
+                other => Err(not_a_result("unwrap", other)),
+            }),
+        );
+        env.define(
+            "parse_int".to_string(),
+            Value::NativeFunc(|args, _| {
+                let text = str_operand("parse_int", args)?;
+                Ok(match parse_int(&text) {
+                    Some(value) => Value::Ok(Box::new(value)),
+                    None => {
+                        Value::Err(Box::new(Value::Str(format!("invalid integer: '{}'", text))))
+                    }
+                })
+            }),
+        );
+        env.define(
+            "parse_float".to_string(),
+            Value::NativeFunc(|args, _| {
+                let text = str_operand("parse_float", args)?;
+                Ok(match text.parse() {
+                    Ok(value) => Value::Ok(Box::new(Value::Float(value))),
+                    Err(_) => {
+                        Value::Err(Box::new(Value::Str(format!("invalid float: '{}'", text))))
+                    }
+                })
+            }),
+        );
+        env.define("args".to_string(), Value::NativeFunc(system::args));
+        env.define("exit".to_string(), Value::NativeFunc(system::exit));
+        env.define(
+            "read_line".to_string(),
+            Value::NativeFunc(system::read_line),
+        );
+        env.define("read_all".to_string(), Value::NativeFunc(system::read_all));
+        env.define(
+            "stdin_lines".to_string(),
+            Value::NativeFunc(system::stdin_lines),
+        );
+        env.define(
+            "str".to_string(),
+            Value::NativeFunc(|args, _| Ok(Value::Str(single_operand(args)?.to_string()))),
+        );
+        // Lists.
+        env.define(
+            "list".to_string(),
+            Value::NativeFunc(|args, _| Ok(Value::List(args))),
+        );
+        env.define(
+            "push".to_string(),
+            Value::NativeFunc(|args, _| match <[Value; 2]>::try_from(args) {
+                Ok([Value::List(mut items), value]) => {
+                    items.push(value);
+                    Ok(Value::List(items))
+                }
+                Ok(args) => Err(RuntimeError::TypeMismatch(format!(
+                    "push expects a list and a value, got {:?}",
+                    args
+                ))),
+                Err(args) => Err(RuntimeError::ArgumentMismatch(2, args.len())),
+            }),
+        );
+        for (name, native) in [
+            ("map", collections::map as NativeFn),
+            ("filter", collections::filter),
+            ("reduce", collections::reduce),
+            ("any", collections::any),
+            ("all", collections::all),
+            ("find", collections::find),
+            ("sort_by", collections::sort_by),
+            ("zip", collections::zip),
+            ("enumerate", collections::enumerate),
+            ("flat_map", collections::flat_map),
+            ("group_by", collections::group_by),
+            ("iter", iterators::iter),
+            ("next", iterators::next),
+            ("range", iterators::range),
+            ("take", iterators::take),
+            ("skip", iterators::skip),
+            ("collect", iterators::collect),
+        ] {
+            env.define(name.to_string(), Value::NativeFunc(native));
+        }
+        env.define(
+            "len".to_string(),
+            Value::NativeFunc(|args, _| match single_operand(args)? {
+                Value::List(items) => Ok(Value::Int(items.len() as i64)),
+                other => Err(RuntimeError::TypeMismatch(format!(
+                    "len expects a list, got {:?}",
+                    other
+                ))),
+            }),
+        );
+        env.define(
+            "get".to_string(),
+            Value::NativeFunc(|args, _| match <[Value; 2]>::try_from(args) {
+                Ok([Value::List(items), Value::Int(index)]) => {
+                    let len = items.len();
+                    usize::try_from(index)
+                        .ok()
+                        .and_then(|i| items.into_iter().nth(i))
+                        .ok_or(RuntimeError::IndexOutOfBounds(index, len))
+                }
+                Ok(args) => Err(RuntimeError::TypeMismatch(format!(
+                    "get expects a list and an integer, got {:?}",
+                    args
+                ))),
+                Err(args) => Err(RuntimeError::ArgumentMismatch(2, args.len())),
+            }),
+        );
+        for &(name, capability, native) in GATED_BUILTINS {
+            if env.permissions().allows(capability) {
+                env.define(name.to_string(), Value::NativeFunc(native));
+            }
+        }
+        let prelude = env.runtime.borrow().prelude.clone();
+        for (name, value) in prelude {
+            env.define(name, value);
+        }
         env
     }
 
+    /// Makes the prelude's definitions available in every environment
+    /// created from now on, including those of imported files.
+    pub(crate) fn set_prelude(&mut self, definitions: Vec<(String, Value)>) {
+        self.runtime.borrow_mut().prelude = definitions;
+    }
+
+    /// Creates the global environment of an imported file, which shares the
+    /// limits and permissions of this one.
+    pub(crate) fn module_env(&self) -> Environment {
+        Environment::with_runtime(Rc::clone(&self.runtime))
+    }
+
+    /// The files of the running program.
+    pub(crate) fn modules(&self) -> std::cell::RefMut<'_, Modules> {
+        std::cell::RefMut::map(self.runtime.borrow_mut(), |runtime| &mut runtime.modules)
+    }
+
+    /// Resolves imports relative to `path`, the file the script was read
+    /// from.
+    pub fn set_script_path(&mut self, path: &std::path::Path) {
+        self.modules().enter_script(path);
+    }
+
+    /// Sets the command line arguments returned by `args()`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.runtime.borrow_mut().args = args;
+    }
+
+    /// The command line arguments given to the script.
+    pub fn script_args(&self) -> std::cell::Ref<'_, Vec<String>> {
+        std::cell::Ref::map(self.runtime.borrow(), |runtime| &runtime.args)
+    }
+
+    /// Makes `read_line` and friends read from `input` instead of standard
+    /// input.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.runtime.borrow_mut().input = input;
+    }
+
+    /// The reader behind `read_line` and friends.
+    pub fn input(&self) -> std::cell::RefMut<'_, dyn BufRead> {
+        std::cell::RefMut::map(self.runtime.borrow_mut(), |runtime| runtime.input.as_mut())
+    }
+
+    /// The value the script gave an environment variable, if it set it.
+    pub fn env_var_override(&self, name: &str) -> Option<String> {
+        self.runtime.borrow().env_vars.get(name).cloned()
+    }
+
+    /// Records a value the script gave an environment variable.
+    pub fn set_env_var_override(&self, name: String, value: String) {
+        self.runtime.borrow_mut().env_vars.insert(name, value);
+    }
+
+    /// The capabilities granted to the script.
+    pub fn permissions(&self) -> std::cell::Ref<'_, Permissions> {
+        std::cell::Ref::map(self.runtime.borrow(), |runtime| &runtime.permissions)
+    }
+
+    /// Limits how many function calls may be nested at once.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.runtime.borrow_mut().max_depth = depth;
+    }
+
+    /// Limits how many expressions may be evaluated in total.
+    pub fn set_max_steps(&mut self, steps: u64) {
+        self.runtime.borrow_mut().max_steps = Some(steps);
+    }
+
+    /// Limits how many bytes a string, or items a list or tuple, may hold.
+    pub fn set_max_size(&mut self, size: usize) {
+        self.runtime.borrow_mut().max_size = Some(size);
+    }
+
+    /// Stops the script once `deadline` has passed.
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.runtime.borrow_mut().deadline = Some(deadline);
+    }
+
+    /// Returns a handle that stops the script when triggered.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle(Arc::clone(&self.runtime.borrow().cancelled))
+    }
+
+    /// Calls `func` on behalf of the builtin `native`, as if the builtin's
+    /// call site called it.
+    pub fn call(&self, native: &str, func: Value, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let runtime = self.runtime.borrow();
+        let frame = Frame {
+            function: native.to_string(),
+            call_site: runtime.native_call_site,
+            module: runtime.module.clone(),
+        };
+        drop(runtime);
+        call_function(frame, func, args, self.clone())
+    }
+
+    /// Runs `f` as the call `frame` of a function defined in `module`, as
+    /// if the function were called again.
+    pub(crate) fn with_frame<T>(
+        &self,
+        frame: Frame,
+        module: Option<Rc<Module>>,
+        f: impl FnOnce() -> Result<T, RuntimeError>,
+    ) -> Result<T, RuntimeError> {
+        in_frame(&self.runtime, frame, || self.in_module(module, f))
+    }
+
+    /// Runs `f` as code of `module`, the imported file whose code is
+    /// running, or `None` for the script.
+    pub(crate) fn in_module<T>(&self, module: Option<Rc<Module>>, f: impl FnOnce() -> T) -> T {
+        let outer = std::mem::replace(&mut self.runtime.borrow_mut().module, module);
+        let result = f();
+        self.runtime.borrow_mut().module = outer;
+        result
+    }
+
+    /// The imported file whose code is running, or `None` for the script.
+    pub(crate) fn module(&self) -> Option<Rc<Module>> {
+        self.runtime.borrow().module.clone()
+    }
+
+    /// Counts one evaluation step against the limits.
+    pub(crate) fn step(&self) -> Result<(), RuntimeError> {
+        self.runtime.borrow_mut().step()
+    }
+
+    /// Rejects a list of `len` items if that is over the size limit.
+    pub(crate) fn check_len(&self, len: usize) -> Result<(), RuntimeError> {
+        self.runtime.borrow().check_len(len)
+    }
+
     pub fn define(&mut self, name: String, value: Value) {
         self.values.insert(name, value);
     }
@@ -90,198 +888,574 @@ impl Environment {
     pub fn get(&self, name: &str) -> Option<Value> {
         self.values.get(name).cloned()
     }
+
+    /// Copies assignments made in `inner`, an environment cloned from this
+    /// one, back into it, except to the names in `locals`.
+    pub(crate) fn write_back(&mut self, inner: &Environment, locals: &HashSet<String>) {
+        for (name, value) in inner.values.iter() {
+            if self.values.contains_key(name) && !locals.contains(name) {
+                self.define(name.clone(), value.clone());
+            }
+        }
+    }
+
+    /// Returns the names of all defined values in alphabetical order.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.values.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+fn single_operand(args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let count = args.len();
+    match <[Value; 1]>::try_from(args) {
+        Ok([value]) => Ok(value),
+        Err(_) => Err(RuntimeError::ArgumentMismatch(1, count)),
+    }
+}
+
+pub(crate) fn str_operand(name: &str, args: Vec<Value>) -> Result<String, RuntimeError> {
+    match single_operand(args)? {
+        Value::Str(s) => Ok(s),
+        other => Err(RuntimeError::TypeMismatch(format!(
+            "{} expects a string, got {:?}",
+            name, other
+        ))),
+    }
+}
+
+fn not_a_result(name: &str, value: Value) -> RuntimeError {
+    RuntimeError::TypeMismatch(format!("{} expects Ok or Err, got {:?}", name, value))
+}
+
+/// Parses an optionally signed decimal integer, promoting it to a big
+/// integer if it does not fit in an `i64`.
+fn parse_int(text: &str) -> Option<Value> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let magnitude = BigInt::parse(digits, 10)?;
+    Some(Value::from_bigint(if negative {
+        magnitude.neg()
+    } else {
+        magnitude
+    }))
+}
+
+fn int_operands(name: &str, args: Vec<Value>) -> Result<(i64, i64), RuntimeError> {
+    match args.as_slice() {
+        [Value::Int(l), Value::Int(r)] => Ok((*l, *r)),
+        [_, _] => Err(RuntimeError::TypeMismatch(format!(
+            "{} expects two integers, got {:?}",
+            name, args
+        ))),
+        _ => Err(RuntimeError::ArgumentMismatch(2, args.len())),
+    }
 }
 
-pub fn eval_expression(expr: ast::Expr, env: &mut Environment) -> Value {
-    match expr {
-        ast::Expr::Literal(literal) => match literal {
+impl Value {
+    /// Demotes a big integer back to `Value::Int` when it fits.
+    fn from_bigint(value: BigInt) -> Value {
+        match value.to_i64() {
+            Some(i) => Value::Int(i),
+            None => Value::BigInt(value),
+        }
+    }
+}
+
+fn eval_int_binary(l: i64, op: BinaryOp, r: i64) -> Result<Value, RuntimeError> {
+    let result = match op {
+        BinaryOp::Add => l.checked_add(r),
+        BinaryOp::Sub => l.checked_sub(r),
+        BinaryOp::Mul => l.checked_mul(r),
+        BinaryOp::Div => {
+            if r == 0 {
+                return Err(RuntimeError::DivisionByZero);
+            }
+            l.checked_div(r)
+        }
+        BinaryOp::Lt => return Ok(Value::Bool(l < r)),
+        BinaryOp::Gt => return Ok(Value::Bool(l > r)),
+        BinaryOp::Eq => return Ok(Value::Bool(l == r)),
+        op => {
+            return Err(RuntimeError::TypeMismatch(format!(
+                "{:?} {:?} {:?}",
+                Value::Int(l),
+                op,
+                Value::Int(r)
+            )));
+        }
+    };
+    match result {
+        Some(i) => Ok(Value::Int(i)),
+        // Promote to a big integer on overflow.
+        None => eval_big_binary(BigInt::from_i64(l), op, BigInt::from_i64(r)),
+    }
+}
+
+fn eval_big_binary(l: BigInt, op: BinaryOp, r: BigInt) -> Result<Value, RuntimeError> {
+    let result = match op {
+        BinaryOp::Add => l.add(&r),
+        BinaryOp::Sub => l.sub(&r),
+        BinaryOp::Mul => l.mul(&r),
+        BinaryOp::Div => l.div(&r).ok_or(RuntimeError::DivisionByZero)?,
+        BinaryOp::Lt => return Ok(Value::Bool(l < r)),
+        BinaryOp::Gt => return Ok(Value::Bool(l > r)),
+        BinaryOp::Eq => return Ok(Value::Bool(l == r)),
+        op => {
+            return Err(RuntimeError::TypeMismatch(format!(
+                "{:?} {:?} {:?}",
+                Value::BigInt(l),
+                op,
+                Value::BigInt(r)
+            )));
+        }
+    };
+    Ok(Value::from_bigint(result))
+}
+
+fn eval_binary(left_val: Value, op: BinaryOp, right_val: Value) -> Result<Value, RuntimeError> {
+    let value = match (left_val, op, right_val) {
+        // Integer math. Overflow promotes to a big integer.
+        (Value::Int(l), op, Value::Int(r)) => return eval_int_binary(l, op, r),
+        (Value::BigInt(l), op, Value::BigInt(r)) => return eval_big_binary(l, op, r),
+        (Value::Int(l), op, Value::BigInt(r)) => {
+            return eval_big_binary(BigInt::from_i64(l), op, r);
+        }
+        (Value::BigInt(l), op, Value::Int(r)) => {
+            return eval_big_binary(l, op, BigInt::from_i64(r));
+        }
+        // Mixed math (BigInt and Float).
+        (Value::BigInt(l), op, r @ Value::Float(_)) => {
+            return eval_binary(Value::Float(l.to_f64()), op, r);
+        }
+        (l @ Value::Float(_), op, Value::BigInt(r)) => {
+            return eval_binary(l, op, Value::Float(r.to_f64()));
+        }
+        // Mixed math (Int and Float).
+        (Value::Int(l), BinaryOp::Add, Value::Float(r)) => Value::Float(l as f64 + r),
+        (Value::Float(l), BinaryOp::Add, Value::Int(r)) => Value::Float(l + r as f64),
+        (Value::Int(l), BinaryOp::Sub, Value::Float(r)) => Value::Float(l as f64 - r),
+        (Value::Float(l), BinaryOp::Sub, Value::Int(r)) => Value::Float(l - r as f64),
+        (Value::Int(l), BinaryOp::Mul, Value::Float(r)) => Value::Float(l as f64 * r),
+        (Value::Float(l), BinaryOp::Mul, Value::Int(r)) => Value::Float(l * r as f64),
+        (Value::Int(l), BinaryOp::Div, Value::Float(r)) => Value::Float(l as f64 / r),
+        (Value::Float(l), BinaryOp::Div, Value::Int(r)) => Value::Float(l / r as f64),
+        // Float math.
+        (Value::Float(l), BinaryOp::Add, Value::Float(r)) => Value::Float(l + r),
+        (Value::Float(l), BinaryOp::Sub, Value::Float(r)) => Value::Float(l - r),
+        (Value::Float(l), BinaryOp::Mul, Value::Float(r)) => Value::Float(l * r),
+        (Value::Float(l), BinaryOp::Div, Value::Float(r)) => Value::Float(l / r),
+        (Value::Float(l), BinaryOp::Lt, Value::Float(r)) => Value::Bool(l < r),
+        (Value::Float(l), BinaryOp::Gt, Value::Float(r)) => Value::Bool(l > r),
+        (Value::Float(l), BinaryOp::Eq, Value::Float(r)) => Value::Bool(l == r),
+        // String concatenation.
+        (Value::Str(l), BinaryOp::Add, Value::Str(r)) => {
+            let mut new_string = l.clone();
+            new_string.push_str(&r);
+            Value::Str(new_string)
+        }
+        // Tuples, lists and `()` are equal when their items are.
+        (l @ (Value::Tuple(_) | Value::List(_) | Value::Unit), BinaryOp::Eq, r)
+            if std::mem::discriminant(&l) == std::mem::discriminant(&r) =>
+        {
+            Value::Bool(l == r)
+        }
+        // Logical operations.
+        (Value::Bool(l), BinaryOp::And, Value::Bool(r)) => Value::Bool(l && r),
+        (Value::Bool(l), BinaryOp::Or, Value::Bool(r)) => Value::Bool(l || r),
+        (l, op, r) => {
+            return Err(RuntimeError::TypeMismatch(format!(
+                "{:?} {:?} {:?}",
+                l, op, r
+            )));
+        }
+    };
+    Ok(value)
+}
+
+/// The outcome of evaluating an expression in tail position. A call there is
+/// handed back to the enclosing function call instead of being made, so that
+/// tail recursion runs in constant stack space.
+enum Flow {
+    Value(Value),
+    TailCall(Frame, Value, Vec<Value>, Environment),
+}
+
+pub fn eval_expression(expr: ast::Expr, env: &mut Environment) -> Result<Value, RuntimeError> {
+    match eval_flow(expr, env)? {
+        Flow::Value(value) => Ok(value),
+        Flow::TailCall(frame, func, args, env) => call_function(frame, func, args, env),
+    }
+}
+
+fn eval_flow(expr: ast::Expr, env: &mut Environment) -> Result<Flow, RuntimeError> {
+    env.runtime.borrow_mut().step()?;
+    let value = match expr {
+        ast::Expr::Literal(literal, _) => match literal {
             ast::Literal::Int(i) => Value::Int(i),
+            ast::Literal::BigInt(i) => Value::from_bigint(i),
             ast::Literal::Float(f) => Value::Float(f),
             ast::Literal::Bool(b) => Value::Bool(b),
             ast::Literal::Str(s) => Value::Str(s),
         },
-        ast::Expr::Variable(name) => match env.get(&name) {
+        ast::Expr::Variable(name, _) => match modules::get(&name, env) {
             Some(val) => val,
-            None => panic!("Undefined variable: {}", name),
+            None => return Err(undefined(name, env, RuntimeError::UndefinedVariable)),
         },
         ast::Expr::Binary(lhs, op, rhs) => {
-            let left_val = eval_expression(*lhs, env);
-            let right_val = eval_expression(*rhs, env);
-            match (left_val, op, right_val) {
-                // Integer math.
-                (Value::Int(l), BinaryOp::Add, Value::Int(r)) => Value::Int(l + r),
-                (Value::Int(l), BinaryOp::Sub, Value::Int(r)) => Value::Int(l - r),
-                (Value::Int(l), BinaryOp::Mul, Value::Int(r)) => Value::Int(l * r),
-                (Value::Int(l), BinaryOp::Div, Value::Int(r)) => Value::Int(l / r),
-                (Value::Int(l), BinaryOp::Lt, Value::Int(r)) => Value::Bool(l < r),
-                (Value::Int(l), BinaryOp::Gt, Value::Int(r)) => Value::Bool(l > r),
-                (Value::Int(l), BinaryOp::Eq, Value::Int(r)) => Value::Bool(l == r),
-                // Mixed math (Int and Float).
-                (Value::Int(l), BinaryOp::Add, Value::Float(r)) => Value::Float(l as f64 + r),
-                (Value::Float(l), BinaryOp::Add, Value::Int(r)) => Value::Float(l + r as f64),
-                (Value::Int(l), BinaryOp::Sub, Value::Float(r)) => Value::Float(l as f64 - r),
-                (Value::Float(l), BinaryOp::Sub, Value::Int(r)) => Value::Float(l - r as f64),
-                (Value::Int(l), BinaryOp::Mul, Value::Float(r)) => Value::Float(l as f64 * r),
-                (Value::Float(l), BinaryOp::Mul, Value::Int(r)) => Value::Float(l * r as f64),
-                (Value::Int(l), BinaryOp::Div, Value::Float(r)) => Value::Float(l as f64 / r),
-                (Value::Float(l), BinaryOp::Div, Value::Int(r)) => Value::Float(l / r as f64),
-                // Float math.
-                (Value::Float(l), BinaryOp::Add, Value::Float(r)) => Value::Float(l + r),
-                (Value::Float(l), BinaryOp::Sub, Value::Float(r)) => Value::Float(l - r),
-                (Value::Float(l), BinaryOp::Mul, Value::Float(r)) => Value::Float(l * r),
-                (Value::Float(l), BinaryOp::Div, Value::Float(r)) => Value::Float(l / r),
-                (Value::Float(l), BinaryOp::Lt, Value::Float(r)) => Value::Bool(l < r),
-                (Value::Float(l), BinaryOp::Gt, Value::Float(r)) => Value::Bool(l > r),
-                (Value::Float(l), BinaryOp::Eq, Value::Float(r)) => Value::Bool(l == r),
-                // String concatenation.
-                (Value::Str(l), BinaryOp::Add, Value::Str(r)) => {
-                    let mut new_string = l.clone();
-                    new_string.push_str(&r);
-                    Value::Str(new_string)
-                }
-                // Logical operations.
-                (Value::Bool(l), BinaryOp::And, Value::Bool(r)) => Value::Bool(l && r),
-                (Value::Bool(l), BinaryOp::Or, Value::Bool(r)) => Value::Bool(l || r),
-                (l, op, r) => panic!("Type mismatch: {:?} {:?} {:?}", l, op, r),
+            let left_val = eval_expression(*lhs, env)?;
+            let right_val = eval_expression(*rhs, env)?;
+            let value = eval_binary(left_val, op, right_val)?;
+            env.runtime.borrow().check_size(&value)?;
+            value
+        }
+        ast::Expr::Block(statements, tail, _) => return eval_block(statements, tail, env),
+        ast::Expr::Tuple(items, _) => {
+            let mut values = Vec::new();
+            for item in items {
+                values.push(eval_expression(item, env)?);
             }
+            Value::tuple(values)
         }
-        ast::Expr::Block(statements, tail) => eval_block(statements, tail, env),
-        ast::Expr::If(condition, then_branch, else_branch) => {
-            let cond_val = eval_expression(*condition, env);
+        ast::Expr::TupleIndex(tuple, index, _) => match eval_expression(*tuple, env)? {
+            Value::Tuple(mut items) if index < items.len() => items.swap_remove(index),
+            value => return Err(RuntimeError::NoTupleIndex(value.item(), index)),
+        },
+        ast::Expr::If(condition, then_branch, else_branch, _) => {
+            let cond_val = eval_expression(*condition, env)?;
             if let Value::Bool(true) = cond_val {
-                eval_expression(*then_branch, env)
+                return eval_flow(*then_branch, env);
             } else if let Some(else_expr) = else_branch {
-                eval_expression(*else_expr, env)
+                return eval_flow(*else_expr, env);
             } else {
                 Value::Unit
             }
         }
-        ast::Expr::Call(name, args) => {
-            let func_val = match env.get(&name) {
+        ast::Expr::Call(name, args, call_site) | ast::Expr::MethodCall(name, args, call_site) => {
+            let func_val = match modules::get(&name, env) {
                 Some(val) => val,
-                None => panic!("Undefined function: {}", name),
+                None => return Err(undefined(name, env, RuntimeError::UndefinedFunction)),
             };
             let mut arg_values = Vec::new();
             for arg_expr in args {
-                arg_values.push(eval_expression(arg_expr, env));
-            }
-            match func_val {
-                Value::Function(params, body) => {
-                    if arg_values.len() != params.len() {
-                        panic!(
-                            "Mismatched arguments: expected {}, got {}",
-                            params.len(),
-                            arg_values.len()
-                        );
-                    }
-                    // Clone the current environment to support recursion (dynamic scoping).
-                    let mut func_env = env.clone();
-                    for (param, arg_val) in params.iter().zip(arg_values) {
-                        func_env.define(param.clone(), arg_val);
+                arg_values.push(eval_expression(arg_expr, env)?);
+            }
+            // Clone the current environment to support recursion (dynamic scoping).
+            let frame = Frame {
+                function: name,
+                call_site,
+                module: env.module(),
+            };
+            return Ok(Flow::TailCall(frame, func_val, arg_values, env.clone()));
+        }
+        ast::Expr::Propagate(expr, _) => match eval_expression(*expr, env)? {
+            Value::Ok(value) => *value,
+            Value::Err(value) => return Err(RuntimeError::Propagated(*value)),
+            other => {
+                return Err(RuntimeError::TypeMismatch(format!(
+                    "the `?` operator expects Ok or Err, got {:?}",
+                    other
+                )));
+            }
+        },
+        ast::Expr::Try(body, name, handler, _) => match eval_expression(*body, env) {
+            Ok(value) => value,
+            Err(err) => {
+                match err.cause() {
+                    // `exit` ends the script no matter where it is called.
+                    RuntimeError::Exit(_) => return Err(err),
+                    RuntimeError::LimitExceeded(limit)
+                        if !env.runtime.borrow_mut().catch_limit(limit) =>
+                    {
+                        return Err(err);
                     }
-                    eval_expression(body, &mut func_env)
+                    _ => {}
                 }
-                Value::NativeFunc(func) => func(arg_values),
-                _ => panic!("Can only call functions, not {:?}", func_val),
+                let mut catch_env = env.clone();
+                catch_env.define(name.clone(), err.value());
+                let flow = eval_flow(*handler, &mut catch_env);
+                env.write_back(&catch_env, &HashSet::from([name]));
+                return flow;
             }
-        }
-        ast::Expr::Unary(op, rhs) => {
-            let val = eval_expression(*rhs, env);
+        },
+        ast::Expr::Unary(op, rhs, _) => {
+            let val = eval_expression(*rhs, env)?;
             match (op, val) {
-                (ast::UnaryOp::Neg, Value::Int(i)) => Value::Int(-i),
+                (ast::UnaryOp::Neg, Value::Int(i)) => match i.checked_neg() {
+                    Some(negated) => Value::Int(negated),
+                    None => Value::from_bigint(BigInt::from_i64(i).neg()),
+                },
+                (ast::UnaryOp::Neg, Value::BigInt(i)) => Value::from_bigint(i.neg()),
                 (ast::UnaryOp::Neg, Value::Float(f)) => Value::Float(-f),
                 (ast::UnaryOp::Not, Value::Bool(b)) => Value::Bool(!b),
-                (op, val) => panic!("Cannot apply unary op {:?} to {:?}", op, val),
+                (op, val) => {
+                    return Err(RuntimeError::TypeMismatch(format!(
+                        "cannot apply unary op {:?} to {:?}",
+                        op, val
+                    )));
+                }
             }
         }
+    };
+    Ok(Flow::Value(value))
+}
+
+/// The error for using `name` when it is not defined: builtins the host has
+/// not granted and private definitions of imported files are reported as
+/// such rather than as unknown names.
+fn undefined(name: String, env: &Environment, unknown: fn(String) -> RuntimeError) -> RuntimeError {
+    if let Some(err) = modules::private_item(&name, env) {
+        return err;
+    }
+    match GATED_BUILTINS.iter().find(|(builtin, ..)| *builtin == name) {
+        Some((_, capability, _)) => {
+            RuntimeError::PermissionDenied(format!("{} requires {}", name, capability))
+        }
+        None => unknown(name),
     }
 }
 
-pub fn eval_statement(stmt: ast::Stmt, env: &mut Environment) -> Value {
+/// Calls `func` with `args` in `env`, the environment of the call site.
+fn call_function(
+    frame: Frame,
+    func: Value,
+    args: Vec<Value>,
+    env: Environment,
+) -> Result<Value, RuntimeError> {
+    match func {
+        Value::Function(..) => {
+            let runtime = Rc::clone(&env.runtime);
+            // The function sets the file it was defined in while it runs.
+            let caller = runtime.borrow().module.clone();
+            let result = in_frame(&runtime, frame, || run_function(func, args, env));
+            runtime.borrow_mut().module = caller;
+            result
+        }
+        Value::NativeFunc(native) => call_native(&frame, native, args, &env),
+        _ => Err(RuntimeError::NotCallable(format!("{:?}", func))),
+    }
+}
+
+/// Runs `f` with `frame` pushed onto the call stack, tracing its errors.
+fn in_frame<T>(
+    runtime: &Rc<RefCell<Runtime>>,
+    frame: Frame,
+    f: impl FnOnce() -> Result<T, RuntimeError>,
+) -> Result<T, RuntimeError> {
+    runtime.borrow_mut().push(frame)?;
+    let result = f().map_err(|err| runtime.borrow().trace(err));
+    runtime.borrow_mut().frames.pop();
+    result
+}
+
+/// Calls a builtin, remembering where it was called from while it runs.
+fn call_native(
+    frame: &Frame,
+    native: NativeFn,
+    args: Vec<Value>,
+    env: &Environment,
+) -> Result<Value, RuntimeError> {
+    let outer = std::mem::replace(
+        &mut env.runtime.borrow_mut().native_call_site,
+        frame.call_site,
+    );
+    let result = native(args, env);
+    env.runtime.borrow_mut().native_call_site = outer;
+    let value = result?;
+    env.runtime.borrow().check_size(&value)?;
+    Ok(value)
+}
+
+/// Runs a user-defined function, reusing its frame for calls it makes in
+/// tail position.
+fn run_function(
+    mut func: Value,
+    mut args: Vec<Value>,
+    mut env: Environment,
+) -> Result<Value, RuntimeError> {
+    loop {
+        let Value::Function(params, body, module) = func else {
+            return Err(RuntimeError::NotCallable(format!("{:?}", func)));
+        };
+        env.runtime.borrow_mut().module = module;
+        if args.len() != params.len() {
+            return Err(RuntimeError::ArgumentMismatch(params.len(), args.len()));
+        }
+        for (param, arg_val) in params.into_iter().zip(args) {
+            bind(param, arg_val, &mut env)?;
+        }
+        // A function with `yield` in it returns a generator that runs the
+        // body as items are asked for.
+        if body.contains_yield() {
+            let frame = env.runtime.borrow().frames.last().cloned();
+            let frame = frame.expect("functions run inside a frame");
+            return Ok(Iter::generator(frame, env, body));
+        }
+        let flow = match eval_flow(body, &mut env) {
+            // `?` returns the error from the function it is used in.
+            Err(RuntimeError::Propagated(value)) => return Ok(Value::Err(Box::new(value))),
+            flow => flow?,
+        };
+        match flow {
+            Flow::Value(value) => return Ok(value),
+            Flow::TailCall(frame, Value::NativeFunc(native), next_args, next_env) => {
+                return call_native(&frame, native, next_args, &next_env);
+            }
+            Flow::TailCall(frame, next, next_args, next_env) => {
+                if let Some(top) = env.runtime.borrow_mut().frames.last_mut() {
+                    *top = frame;
+                }
+                func = next;
+                args = next_args;
+                env = next_env;
+            }
+        }
+    }
+}
+
+pub fn eval_statement(stmt: ast::Stmt, env: &mut Environment) -> Result<Value, RuntimeError> {
     match stmt {
-        ast::Stmt::Let(name, expr) => {
-            let value = eval_expression(expr, env);
+        // Type annotations are only used by `toy-rs check`.
+        ast::Stmt::Let(name, _, expr, ..) => {
+            let value = eval_expression(expr, env)?;
             env.define(name, value);
-            Value::Unit
+            Ok(Value::Unit)
         }
-        ast::Stmt::Fn(name, params, body) => {
-            let func_value = Value::Function(params, body);
+        ast::Stmt::LetTuple(patterns, _, expr, _) => {
+            let value = eval_expression(expr, env)?;
+            bind(ast::Pattern::Tuple(patterns), value, env)?;
+            Ok(Value::Unit)
+        }
+        ast::Stmt::Fn(name, params, _, body, ..) => {
+            let params = params.into_iter().map(|param| param.pattern).collect();
+            let func_value = Value::Function(params, body, env.module());
             env.define(name, func_value);
-            Value::Unit
+            Ok(Value::Unit)
         }
         ast::Stmt::Expression(expr) => {
-            eval_expression(expr, env);
-            Value::Unit
+            eval_expression(expr, env)?;
+            Ok(Value::Unit)
         }
         ast::Stmt::ImplicitReturn(expr) => eval_expression(expr, env),
-        ast::Stmt::Assign(name, expr) => {
-            let value = eval_expression(expr, env);
+        ast::Stmt::Import(import, _) => {
+            modules::import(&import, env)?;
+            Ok(Value::Unit)
+        }
+        ast::Stmt::Assign(name, expr, _) => {
+            let value = eval_expression(expr, env)?;
             if env.get(&name).is_none() {
-                panic!("Cannot assign to undefined variable '{}'", name);
+                return Err(RuntimeError::AssignToUndefined(name));
             }
             env.define(name, value);
-            Value::Unit
+            Ok(Value::Unit)
         }
-        ast::Stmt::While(condition, body) => {
+        ast::Stmt::While(condition, body, _) => {
             loop {
-                let cond_val = eval_expression(condition.clone(), env);
+                let cond_val = eval_expression(condition.clone(), env)?;
                 match cond_val {
                     Value::Bool(true) => {
-                        eval_expression(body.clone(), env);
+                        eval_expression(body.clone(), env)?;
                     }
                     Value::Bool(false) => {
                         break;
                     }
-                    _ => panic!("While loop condition must be a boolean!"),
+                    _ => return Err(RuntimeError::InvalidCondition),
                 }
             }
-            Value::Unit
+            Ok(Value::Unit)
         }
+        ast::Stmt::For(pattern, iterable, body, _) => {
+            let iter = Iter::of("for", eval_expression(iterable, env)?)?;
+            let loop_vars: HashSet<String> = pattern.names().into_iter().cloned().collect();
+            while let Some(item) = iter.next(env)? {
+                let mut loop_env = env.clone();
+                let result = bind(pattern.clone(), item, &mut loop_env)
+                    .and_then(|_| eval_expression(body.clone(), &mut loop_env));
+                env.write_back(&loop_env, &loop_vars);
+                result?;
+            }
+            Ok(Value::Unit)
+        }
+        // Generators run their `yield` statements themselves.
+        ast::Stmt::Yield(..) => Err(RuntimeError::MisplacedYield),
     }
 }
 
+/// Defines the names in `pattern` to the parts of `value` they match.
+pub(crate) fn bind(
+    pattern: ast::Pattern,
+    value: Value,
+    env: &mut Environment,
+) -> Result<(), RuntimeError> {
+    match (pattern, value) {
+        (ast::Pattern::Name(name), value) => env.define(name, value),
+        (ast::Pattern::Tuple(patterns), Value::Unit) if patterns.is_empty() => {}
+        (ast::Pattern::Tuple(patterns), Value::Tuple(items)) if patterns.len() == items.len() => {
+            for (pattern, item) in patterns.into_iter().zip(items) {
+                bind(pattern, item, env)?;
+            }
+        }
+        (ast::Pattern::Tuple(patterns), value) => {
+            return Err(RuntimeError::PatternMismatch(value.item(), patterns.len()));
+        }
+    }
+    Ok(())
+}
+
 fn eval_block(
     statements: Vec<ast::Stmt>,
     tail_expr: Option<Box<ast::Expr>>,
     env: &mut Environment,
-) -> Value {
+) -> Result<Flow, RuntimeError> {
     let mut block_env = env.clone();
     let mut local_vars = HashSet::new();
+    let mut result = Ok(Value::Unit);
     for stmt in statements {
-        if let ast::Stmt::Let(name, _) = &stmt {
-            local_vars.insert(name.clone());
-        }
-        eval_statement(stmt, &mut block_env);
-    }
-    // Propagate assignments back to the parent environment.
-    for (name, value) in block_env.values.iter() {
-        if env.values.contains_key(name) && !local_vars.contains(name) {
-            env.define(name.clone(), value.clone());
+        local_vars.extend(stmt.declared_names().into_iter().cloned());
+        result = eval_statement(stmt, &mut block_env);
+        if result.is_err() {
+            break;
         }
     }
+    // Assignments made before an error still happen, in case it is caught.
+    env.write_back(&block_env, &local_vars);
+    result?;
     if let Some(expr) = tail_expr {
-        eval_expression(*expr, &mut block_env)
+        eval_flow(*expr, &mut block_env)
     } else {
-        Value::Unit
+        Ok(Flow::Value(Value::Unit))
     }
 }
 
+/// Parses `input` and runs it in `env`, returning the value of its last
+/// statement.
+#[cfg(test)]
+pub(crate) fn eval_in(input: &str, env: &mut Environment) -> Result<Value, RuntimeError> {
+    let lexer = crate::lexer::Lexer::new(input);
+    let mut parser = crate::parser::Parser::new(lexer).unwrap();
+    let program = parser.parse_program().unwrap();
+    let mut last_value = Value::Unit;
+    for stmt in program {
+        last_value = eval_statement(stmt, env)?;
+    }
+    Ok(last_value)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::lexer::Lexer;
     use crate::parser::Parser;
 
+    fn eval_program(input: &str) -> Result<Value, RuntimeError> {
+        eval_in(input, &mut Environment::new())
+    }
+
     fn eval_helper(input: &str) -> Value {
-        let lexer = Lexer::new(input);
-        let mut parser = Parser::new(lexer);
-        let program = parser.parse_program();
-        let mut env = Environment::new();
-        let mut last_value = Value::Unit;
-        for stmt in program {
-            last_value = eval_statement(stmt, &mut env);
-        }
-        last_value
+        eval_program(input).unwrap_or_else(|err| panic!("{}", err))
     }
 
     #[test]
@@ -336,6 +1510,66 @@ mod tests {
         assert_eq!(eval_helper(input), Value::Int(10));
     }
 
+    #[test]
+    fn test_for_loop() {
+        let input = "
+            let total = 0;
+            let x = 100;
+            for x in list(1, 2, 3) {
+                let doubled = x * 2;
+                total += doubled;
+            }
+            total + x
+        ";
+        // Assignments in the body persist; the loop variable does not.
+        assert_eq!(eval_helper(input), Value::Int(112));
+    }
+
+    #[test]
+    fn test_tuples() {
+        let input = "
+            fn divmod(a, b) { (a / b, a - a / b * b) }
+            fn swap((a, b)) { (b, a) }
+            let (q, r) = divmod(7, 2);
+            let total = 0;
+            for (i, (x, y)) in enumerate(list(swap((1, 2)), (3, 4))) {
+                total += i * 100 + x * 10 + y;
+            }
+            let () = ();
+            let nested = ((q, r), (1,));
+            (total, nested.0.1, nested.1.0, nested)
+        ";
+        assert_eq!(
+            eval_helper(input).to_string(),
+            "(155, 1, 1, ((3, 1), (1,)))"
+        );
+        assert_eq!(eval_helper("()"), Value::Unit);
+        assert_eq!(
+            eval_helper("(1, (\"a\",)) == (1, (\"a\",))"),
+            Value::Bool(true)
+        );
+        assert_eq!(eval_helper("(1, 2) == (2, 1)"), Value::Bool(false));
+        assert_eq!(eval_helper("(1, 2) == (1, 2, 3)"), Value::Bool(false));
+        assert_eq!(eval_helper("() == ()"), Value::Bool(true));
+        assert_eq!(eval_helper("list(1) == list(1)"), Value::Bool(true));
+        assert_eq!(eval_helper("(\"a\", 1)").to_string(), "(\"a\", 1)");
+
+        let err = |input| eval_program(input).unwrap_err().to_string();
+        assert_eq!(
+            err("let (a, b) = (1, 2, 3);"),
+            "Cannot take (1, 2, 3) apart as a tuple of 2 items"
+        );
+        assert_eq!(
+            err("let (a,) = 1;"),
+            "Cannot take 1 apart as a tuple of 1 item"
+        );
+        assert_eq!(err("(1, 2).2"), "(1, 2) has no item at index 2");
+        assert!(err("(1, 2) == list(1, 2)").starts_with("Type mismatch"));
+        assert_eq!(err("\"ab\".0"), "\"ab\" has no item at index 0");
+        assert!(err("fn f((a, b)) { a } f(1)").starts_with("Cannot take 1 apart"));
+        assert!(err("for (a, b) in list(1) { }").starts_with("Cannot take 1 apart"));
+    }
+
     #[test]
     fn test_if_expression_value() {
         let input = "
@@ -356,4 +1590,401 @@ mod tests {
         assert_eq!(eval_helper(r#"print("hello")"#), Value::Unit);
         assert_eq!(eval_helper(r#"println("world")"#), Value::Unit);
     }
+
+    #[test]
+    fn test_integer_division_by_zero() {
+        assert_eq!(eval_program("10 / 0"), Err(RuntimeError::DivisionByZero));
+        // Float division keeps IEEE semantics.
+        assert_eq!(eval_helper("1.0 / 0"), Value::Float(f64::INFINITY));
+    }
+
+    #[test]
+    fn test_big_integer_promotion() {
+        assert_eq!(
+            eval_helper("9223372036854775807 + 1").to_string(),
+            "9223372036854775808"
+        );
+        assert_eq!(
+            eval_helper("0 - 9223372036854775807 - 2").to_string(),
+            "-9223372036854775809"
+        );
+        assert_eq!(
+            eval_helper("4611686018427387904 * 4").to_string(),
+            "18446744073709551616"
+        );
+        assert_eq!(
+            eval_helper("let min = 0 - 9223372036854775807 - 1; min / -1").to_string(),
+            "9223372036854775808"
+        );
+        assert_eq!(
+            eval_helper("let min = 0 - 9223372036854775807 - 1; -min").to_string(),
+            "9223372036854775808"
+        );
+        assert_eq!(
+            eval_program("100000000000000000000 / 0"),
+            Err(RuntimeError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_big_integer_demotion() {
+        // Results that fit in an `i64` are plain integers again.
+        assert_eq!(
+            eval_helper("9223372036854775807 + 1 - 1"),
+            Value::Int(i64::MAX)
+        );
+        assert_eq!(
+            eval_helper("100000000000000000000 / 10000000000"),
+            Value::Int(10_000_000_000)
+        );
+        assert_eq!(eval_helper("100000000000000000000 > 1"), Value::Bool(true));
+        assert_eq!(
+            eval_helper("100000000000000000000 == 100000000000000000000"),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval_helper("100000000000000000000 * 0.5"),
+            Value::Float(5e19)
+        );
+    }
+
+    #[test]
+    fn test_wrapping_and_saturating_builtins() {
+        assert_eq!(
+            eval_helper("wrapping_add(9223372036854775807, 1)"),
+            Value::Int(i64::MIN)
+        );
+        assert_eq!(
+            eval_helper("wrapping_sub(0 - 9223372036854775807, 2)"),
+            Value::Int(i64::MAX)
+        );
+        assert_eq!(eval_helper("wrapping_mul(3, 4)"), Value::Int(12));
+        assert_eq!(
+            eval_helper("saturating_add(9223372036854775807, 1)"),
+            Value::Int(i64::MAX)
+        );
+        assert_eq!(
+            eval_helper("saturating_sub(0 - 9223372036854775807, 5)"),
+            Value::Int(i64::MIN)
+        );
+        assert_eq!(
+            eval_helper("saturating_mul(4611686018427387904, 4)"),
+            Value::Int(i64::MAX)
+        );
+        assert_eq!(
+            eval_program("wrapping_add(1)"),
+            Err(RuntimeError::ArgumentMismatch(2, 1))
+        );
+    }
+
+    #[test]
+    fn test_tail_calls_run_in_constant_stack() {
+        // Well past the default call depth limit.
+        let input = "
+            fn count(n, acc) { if n < 1 { acc } else { count(n - 1, acc + 1) } }
+            fn is_even(n) { if n == 0 { true } else { is_odd(n - 1) } }
+            fn is_odd(n) { if n == 0 { false } else { { is_even(n - 1) } } }
+            println(count(5000, 0));
+            is_even(5001)
+        ";
+        assert_eq!(eval_helper(input), Value::Bool(false));
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let program = Parser::new(Lexer::new(
+            "fn sum(n) { if n < 1 { 0 } else { n + sum(n - 1) } } sum(2) + sum(5)",
+        ))
+        .unwrap()
+        .parse_program()
+        .unwrap();
+        let mut env = Environment::new();
+        env.set_max_call_depth(4);
+        let result = program
+            .into_iter()
+            .map(|stmt| eval_statement(stmt, &mut env))
+            .collect::<Result<Vec<_>, _>>();
+        let err = result.unwrap_err();
+        assert_eq!(err.cause(), &RuntimeError::StackOverflow(4));
+        assert_eq!(err.trace().len(), 4);
+        assert_eq!(
+            err.to_string(),
+            "Stack overflow: more than 4 nested calls\n    at sum (1:39)\n    [previous frame repeated 2 more times]\n    at sum (1:63)"
+        );
+        // The frames of the failed calls are released.
+        assert!(env.runtime.borrow().frames.is_empty());
+    }
+
+    #[test]
+    fn test_stack_trace() {
+        let input = "
+fn check(n) { 10 / n }
+fn run(n) {
+    let x = check(n);
+    x
+}
+fn tail(n) { run(n) }
+tail(0)";
+        let err = eval_program(input).unwrap_err();
+        assert_eq!(err.cause(), &RuntimeError::DivisionByZero);
+        let frame = |function: &str, line, col| Frame {
+            function: function.to_string(),
+            call_site: Span { line, col },
+            module: None,
+        };
+        // `tail` calls `run` in tail position, so `run` takes over its frame.
+        assert_eq!(err.trace(), [frame("check", 4, 13), frame("run", 7, 14)]);
+        assert_eq!(
+            traceback(err.trace(), "script.toy"),
+            "    at check (script.toy:4:13)\n    at run (script.toy:7:14)\n"
+        );
+        // Errors outside any function have no trace.
+        assert_eq!(eval_program("1 / 0").unwrap_err().trace(), []);
+    }
+
+    #[test]
+    fn test_result_values() {
+        assert_eq!(eval_helper("Ok(1)"), Value::Ok(Box::new(Value::Int(1))));
+        assert_eq!(eval_helper("Err(\"no\")").to_string(), "Err(no)");
+        assert_eq!(
+            eval_helper("parse_int(\"-12\")"),
+            Value::Ok(Box::new(Value::Int(-12)))
+        );
+        assert_eq!(
+            eval_helper("unwrap(parse_int(\"99999999999999999999\"))").to_string(),
+            "99999999999999999999"
+        );
+        assert_eq!(
+            eval_helper("parse_int(\"1.5\")").to_string(),
+            "Err(invalid integer: '1.5')"
+        );
+        assert_eq!(
+            eval_helper("is_ok(parse_float(\"2.5\")) && is_err(parse_float(\"x\"))"),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval_program("unwrap(Err(\"boom\"))"),
+            Err(RuntimeError::Raised(Value::Str("boom".to_string())))
+        );
+        assert_eq!(
+            eval_program("is_ok(1)"),
+            Err(RuntimeError::TypeMismatch(
+                "is_ok expects Ok or Err, got Int(1)".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_question_mark_returns_errors() {
+        let input = "
+            fn add(a, b) { Ok(parse_int(a)? + parse_int(b)?) }
+            println(add(\"1\", \"2\"));
+            add(\"1\", \"two\")
+        ";
+        assert_eq!(
+            eval_helper(input).to_string(),
+            "Err(invalid integer: 'two')"
+        );
+        assert_eq!(
+            eval_program("Err(3)?"),
+            Err(RuntimeError::Propagated(Value::Int(3)))
+        );
+        assert_eq!(eval_helper("Ok(3)? + 1"), Value::Int(4));
+    }
+
+    #[test]
+    fn test_try_catch() {
+        let input = "
+            fn check(n) { if n < 0 { error(\"negative\") } else { n } }
+            let e = \"kept\";
+            let a = try { check(-1) } catch e { e };
+            let b = try { 5 / 0 } catch e { e };
+            let c = try { check(5) } catch e { \"unused\" };
+            a + \", \" + b + \", \" + e
+        ";
+        assert_eq!(
+            eval_helper(input),
+            Value::Str("negative, Division by zero, kept".to_string())
+        );
+        assert_eq!(
+            eval_helper("let r = try { Err(7)? } catch e { e + 1 }; r"),
+            Value::Int(8)
+        );
+        let input = "
+            let log = 0;
+            try { log = 1; error(\"x\"); log = 2; } catch e { log = log + 10; };
+            log
+        ";
+        // Assignments made before the error and in the handler both stick.
+        assert_eq!(eval_helper(input), Value::Int(11));
+    }
+
+    #[test]
+    fn test_limits() {
+        let forever = "let i = 0; while true { i = i + 1; }";
+        let mut env = Environment::new();
+        env.set_max_steps(1000);
+        assert_eq!(
+            eval_in(forever, &mut env),
+            Err(RuntimeError::LimitExceeded(Limit::Steps(1000)))
+        );
+
+        let mut env = Environment::new();
+        env.set_max_size(64);
+        assert_eq!(
+            eval_in("let s = \"ab\"; while true { s = s + s; }", &mut env),
+            Err(RuntimeError::LimitExceeded(Limit::Size(64)))
+        );
+        assert_eq!(
+            eval_in("\"short\"", &mut env),
+            Ok(Value::Str("short".to_string()))
+        );
+
+        let mut env = Environment::new();
+        env.set_deadline(Instant::now());
+        assert_eq!(
+            eval_in(forever, &mut env),
+            Err(RuntimeError::LimitExceeded(Limit::Deadline))
+        );
+    }
+
+    #[test]
+    fn test_catch_limit() {
+        let input = "
+            let r = try { let i = 0; while true { i = i + 1; } } catch e { e };
+            r
+        ";
+        let mut env = Environment::new();
+        env.set_max_steps(1000);
+        assert_eq!(
+            eval_in(input, &mut env),
+            Ok(Value::Str(
+                "Step limit exceeded: more than 1000 steps".to_string()
+            ))
+        );
+        // Only the first time: after that the limit stops the script.
+        assert_eq!(
+            eval_in(input, &mut env),
+            Err(RuntimeError::LimitExceeded(Limit::Steps(1000)))
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let script = std::thread::spawn(move || {
+            let mut env = Environment::new();
+            sender.send(env.cancel_handle()).unwrap();
+            // Values may hold iterators, which cannot leave their thread.
+            eval_in("while true { }", &mut env)
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        });
+        receiver.recv().unwrap().cancel();
+        assert_eq!(
+            script.join().unwrap().unwrap_err(),
+            RuntimeError::LimitExceeded(Limit::Cancelled).to_string()
+        );
+    }
+
+    #[test]
+    fn test_permissions() {
+        assert_eq!(
+            eval_program("clock()"),
+            Err(RuntimeError::PermissionDenied(
+                "clock requires --allow-clock".to_string()
+            ))
+        );
+        assert_eq!(
+            eval_helper("let r = try { clock() } catch e { e }; r"),
+            Value::Str("Permission denied: clock requires --allow-clock".to_string())
+        );
+
+        let mut permissions = Permissions::default();
+        permissions.allow(Capability::Clock, None);
+        let mut env = Environment::with_permissions(permissions);
+        assert_eq!(eval_in("clock() > 0.0", &mut env), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_exit() {
+        assert_eq!(eval_program("exit(3); 1"), Err(RuntimeError::Exit(3)));
+        // `try` does not stop a script from exiting.
+        let result = eval_program("fn quit() { exit(0) } let r = try { quit() } catch e { 1 }; r");
+        assert_eq!(
+            result.map_err(|err| err.cause().clone()),
+            Err(RuntimeError::Exit(0))
+        );
+    }
+
+    #[test]
+    fn test_args_and_env_vars() {
+        let mut permissions = Permissions::default();
+        permissions.allow(Capability::Env, None);
+        let mut env = Environment::with_permissions(permissions);
+        env.set_args(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(eval_in("len(args())", &mut env), Ok(Value::Int(2)));
+        let input = "
+            set_env_var(\"TOY_RS_TEST_VARIABLE\", \"set\");
+            unwrap(env_var(\"TOY_RS_TEST_VARIABLE\"))
+        ";
+        assert_eq!(eval_in(input, &mut env), Ok(Value::Str("set".to_string())));
+        assert!(std::env::var("TOY_RS_TEST_VARIABLE").is_err());
+        assert_eq!(
+            eval_in("env_var(\"TOY_RS_UNSET_VARIABLE\")", &mut env),
+            Ok(Value::Err(Box::new(Value::Str(
+                "environment variable 'TOY_RS_UNSET_VARIABLE' is not set".to_string()
+            ))))
+        );
+    }
+
+    #[test]
+    fn test_read_input() {
+        let mut env = Environment::new();
+        env.set_input(Box::new("first\r\nsecond\nthird\nfourth".as_bytes()));
+        let input = "
+            let a = unwrap(read_line());
+            let b = unwrap(read_line());
+            a + \"|\" + b
+        ";
+        assert_eq!(
+            eval_in(input, &mut env),
+            Ok(Value::Str("first|second".to_string()))
+        );
+        assert_eq!(
+            eval_in("collect(stdin_lines())", &mut env),
+            Ok(Value::List(vec![
+                Value::Str("third".to_string()),
+                Value::Str("fourth".to_string())
+            ]))
+        );
+        assert_eq!(
+            eval_in("read_line()", &mut env),
+            Ok(Value::Err(Box::new(Value::Str("end of input".to_string()))))
+        );
+        assert_eq!(
+            eval_in("read_all()", &mut env),
+            Ok(Value::Ok(Box::new(Value::Str(String::new()))))
+        );
+    }
+
+    #[test]
+    fn test_stdin_lines_is_lazy() {
+        let mut env = Environment::new();
+        env.set_input(Box::new("first\nsecond\nthird\nfourth\n".as_bytes()));
+        assert_eq!(
+            eval_in("stdin_lines().take(2).collect()", &mut env),
+            Ok(Value::List(vec![
+                Value::Str("first".to_string()),
+                Value::Str("second".to_string())
+            ]))
+        );
+        // The lines after the ones taken are still there to be read.
+        assert_eq!(
+            eval_in("read_all()", &mut env),
+            Ok(Value::Ok(Box::new(Value::Str(
+                "third\nfourth\n".to_string()
+            ))))
+        );
+    }
 }
@@ -0,0 +1,612 @@
+//! Lazy iterators and generator functions.
+//!
+//! An iterator hands out its items one at a time, when `next` or a `for`
+//! loop asks for one. Adapters such as `map` and `take` wrap another
+//! iterator and only pull from it as needed, so iterators may be infinite.
+//!
+//! Calling a function whose body contains `yield` returns a generator
+//! instead of running the body. Each time an item is asked for, the body
+//! runs up to its next `yield`. The evaluator itself cannot stop halfway
+//! through an expression, so a generator keeps the statements it has yet to
+//! run on a stack of tasks, one per block or loop it is inside. That is why
+//! `yield` must be a statement of the body, or of the blocks, `if`s and
+//! loops in it, rather than part of a larger expression.
+
+use crate::ast::{Expr, Pattern, Stmt};
+use crate::collections;
+use crate::interpreter::{
+    Environment, Frame, RuntimeError, Value, bind, eval_expression, eval_statement,
+};
+use crate::modules::Module;
+use crate::system;
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::rc::Rc;
+
+/// An iterator value. Clones share their position.
+#[derive(Clone)]
+pub struct Iter(Rc<RefCell<Source>>);
+
+impl fmt::Debug for Iter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Iterator")
+    }
+}
+
+impl PartialEq for Iter {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Where the items of an iterator come from.
+enum Source {
+    List(std::vec::IntoIter<Value>),
+    /// The next integer and the end, if the range has one.
+    Range(i64, Option<i64>),
+    Map(Iter, Value),
+    Filter(Iter, Value),
+    /// How many more items may be taken.
+    Take(Iter, usize),
+    /// How many items are still to be skipped.
+    Skip(Iter, usize),
+    /// The index of the next item.
+    Enumerate(Iter, i64),
+    Zip(Iter, Iter),
+    /// The items of the list returned for the current item.
+    FlatMap(Iter, Value, std::vec::IntoIter<Value>),
+    Generator(Generator),
+    /// The lines of the script's input, read as they are asked for.
+    Lines,
+    /// Exhausted, or stopped by an error.
+    Done,
+}
+
+impl Iter {
+    fn value(source: Source) -> Value {
+        Value::Iterator(Iter(Rc::new(RefCell::new(source))))
+    }
+
+    pub fn list(items: Vec<Value>) -> Iter {
+        Iter(Rc::new(RefCell::new(Source::List(items.into_iter()))))
+    }
+
+    /// Iterates over a list, or over the remaining items of an iterator.
+    pub fn of(name: &str, value: Value) -> Result<Iter, RuntimeError> {
+        match value {
+            Value::List(items) => Ok(Iter::list(items)),
+            Value::Iterator(iter) => Ok(iter),
+            other => Err(RuntimeError::TypeMismatch(format!(
+                "{} expects a list or an iterator, got {:?}",
+                name, other
+            ))),
+        }
+    }
+
+    /// Produces the next item, or `None` once there are no more. Each item
+    /// counts as a step towards the step limit.
+    pub fn next(&self, env: &Environment) -> Result<Option<Value>, RuntimeError> {
+        env.step()?;
+        let Ok(mut source) = self.0.try_borrow_mut() else {
+            return Err(RuntimeError::IteratorRunning);
+        };
+        let item = source.next(env);
+        if !matches!(item, Ok(Some(_))) {
+            *source = Source::Done;
+        }
+        item
+    }
+
+    /// Collects the remaining items into a list.
+    pub fn collect(&self, env: &Environment) -> Result<Vec<Value>, RuntimeError> {
+        let mut items = Vec::new();
+        while let Some(item) = self.next(env)? {
+            items.push(item);
+            env.check_len(items.len())?;
+        }
+        Ok(items)
+    }
+
+    pub fn map(self, f: Value) -> Value {
+        Iter::value(Source::Map(self, f))
+    }
+
+    pub fn filter(self, f: Value) -> Value {
+        Iter::value(Source::Filter(self, f))
+    }
+
+    pub fn enumerate(self) -> Value {
+        Iter::value(Source::Enumerate(self, 0))
+    }
+
+    pub fn zip(self, other: Iter) -> Value {
+        Iter::value(Source::Zip(self, other))
+    }
+
+    pub fn flat_map(self, f: Value) -> Value {
+        Iter::value(Source::FlatMap(self, f, Vec::new().into_iter()))
+    }
+
+    /// Iterates over the rest of the script's input, one line at a time.
+    pub fn lines() -> Value {
+        Iter::value(Source::Lines)
+    }
+
+    /// Starts a generator that runs `body` in `env`, which holds the
+    /// arguments of the call.
+    pub fn generator(frame: Frame, env: Environment, body: Expr) -> Value {
+        let module = env.module();
+        let scope = Scope {
+            env,
+            locals: HashSet::new(),
+        };
+        Iter::value(Source::Generator(Generator {
+            frame,
+            module,
+            tasks: vec![Task::Block(block_statements(body), scope)],
+        }))
+    }
+}
+
+/// Returns an iterator over the items of a list. Given an iterator, returns
+/// it as it is.
+pub fn iter(args: Vec<Value>, _: &Environment) -> Result<Value, RuntimeError> {
+    match <[Value; 1]>::try_from(args) {
+        Ok([value]) => Ok(Value::Iterator(Iter::of("iter", value)?)),
+        Err(args) => Err(RuntimeError::ArgumentMismatch(1, args.len())),
+    }
+}
+
+/// Returns `Ok` holding the next item of an iterator, or an `Err` once it is
+/// exhausted.
+pub fn next(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    match <[Value; 1]>::try_from(args) {
+        Ok([Value::Iterator(iter)]) => Ok(match iter.next(env)? {
+            Some(item) => Value::Ok(Box::new(item)),
+            None => Value::Err(Box::new(Value::Str("end of iteration".to_string()))),
+        }),
+        Ok([other]) => Err(RuntimeError::TypeMismatch(format!(
+            "next expects an iterator, got {:?}",
+            other
+        ))),
+        Err(args) => Err(RuntimeError::ArgumentMismatch(1, args.len())),
+    }
+}
+
+/// `range(start, end)` counts from `start` up to, but not including, `end`.
+/// `range(start)` counts up from `start` without end.
+pub fn range(args: Vec<Value>, _: &Environment) -> Result<Value, RuntimeError> {
+    match args.as_slice() {
+        [Value::Int(start)] => Ok(Iter::value(Source::Range(*start, None))),
+        [Value::Int(start), Value::Int(end)] => Ok(Iter::value(Source::Range(*start, Some(*end)))),
+        [_] | [_, _] => Err(RuntimeError::TypeMismatch(format!(
+            "range expects integers, got {:?}",
+            args
+        ))),
+        _ => Err(RuntimeError::ArgumentMismatch(2, args.len())),
+    }
+}
+
+/// Returns an iterator over the first `n` items of a list or iterator.
+pub fn take(args: Vec<Value>, _: &Environment) -> Result<Value, RuntimeError> {
+    let (iter, n) = items_and_count("take", args)?;
+    Ok(Iter::value(Source::Take(iter, n)))
+}
+
+/// Returns an iterator over the items of a list or iterator after the first
+/// `n`.
+pub fn skip(args: Vec<Value>, _: &Environment) -> Result<Value, RuntimeError> {
+    let (iter, n) = items_and_count("skip", args)?;
+    Ok(Iter::value(Source::Skip(iter, n)))
+}
+
+/// Returns a list of the remaining items of an iterator.
+pub fn collect(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    match <[Value; 1]>::try_from(args) {
+        Ok([value]) => Ok(Value::List(Iter::of("collect", value)?.collect(env)?)),
+        Err(args) => Err(RuntimeError::ArgumentMismatch(1, args.len())),
+    }
+}
+
+/// Takes the list or iterator and the count arguments of `take` and `skip`.
+fn items_and_count(name: &str, args: Vec<Value>) -> Result<(Iter, usize), RuntimeError> {
+    match <[Value; 2]>::try_from(args) {
+        Ok([items @ (Value::List(_) | Value::Iterator(_)), Value::Int(n)]) if n >= 0 => {
+            Ok((Iter::of(name, items)?, n as usize))
+        }
+        Ok(args) => Err(RuntimeError::TypeMismatch(format!(
+            "{} expects a list or an iterator and a non-negative integer, got {:?}",
+            name, args
+        ))),
+        Err(args) => Err(RuntimeError::ArgumentMismatch(2, args.len())),
+    }
+}
+
+impl Source {
+    fn next(&mut self, env: &Environment) -> Result<Option<Value>, RuntimeError> {
+        match self {
+            Source::List(items) => Ok(items.next()),
+            Source::Range(next, end) => {
+                if end.is_some_and(|end| *next >= end) {
+                    return Ok(None);
+                }
+                let item = *next;
+                match next.checked_add(1) {
+                    Some(after) => *next = after,
+                    None => *end = Some(item),
+                }
+                Ok(Some(Value::Int(item)))
+            }
+            Source::Map(inner, f) => match inner.next(env)? {
+                Some(item) => Ok(Some(env.call("map", f.clone(), vec![item])?)),
+                None => Ok(None),
+            },
+            Source::Filter(inner, f) => {
+                while let Some(item) = inner.next(env)? {
+                    if collections::test("filter", f, item.clone(), env)? {
+                        return Ok(Some(item));
+                    }
+                }
+                Ok(None)
+            }
+            Source::Take(inner, remaining) => {
+                if *remaining == 0 {
+                    return Ok(None);
+                }
+                *remaining -= 1;
+                inner.next(env)
+            }
+            Source::Skip(inner, remaining) => {
+                while *remaining > 0 {
+                    *remaining -= 1;
+                    if inner.next(env)?.is_none() {
+                        return Ok(None);
+                    }
+                }
+                inner.next(env)
+            }
+            Source::Enumerate(inner, index) => {
+                let Some(item) = inner.next(env)? else {
+                    return Ok(None);
+                };
+                let pair = Value::Tuple(vec![Value::Int(*index), item]);
+                *index += 1;
+                Ok(Some(pair))
+            }
+            Source::Zip(left, right) => {
+                let Some(l) = left.next(env)? else {
+                    return Ok(None);
+                };
+                Ok(right.next(env)?.map(|r| Value::Tuple(vec![l, r])))
+            }
+            Source::FlatMap(inner, f, current) => loop {
+                if let Some(item) = current.next() {
+                    return Ok(Some(item));
+                }
+                let Some(item) = inner.next(env)? else {
+                    return Ok(None);
+                };
+                match env.call("flat_map", f.clone(), vec![item])? {
+                    Value::List(items) => *current = items.into_iter(),
+                    other => return Err(collections::not_a_list("flat_map", other)),
+                }
+            },
+            Source::Generator(generator) => generator.resume(env),
+            // A failed read raises the same `Err` that `read_line` returns.
+            Source::Lines => match system::next_line(env) {
+                Ok(line) => Ok(line.map(Value::Str)),
+                Err(err) => Err(RuntimeError::Raised(system::input_error(err))),
+            },
+            Source::Done => Ok(None),
+        }
+    }
+}
+
+/// A suspended call of a generator function.
+struct Generator {
+    /// The call that created the generator, shown in stack traces.
+    frame: Frame,
+    /// The imported file the generator function was defined in.
+    module: Option<Rc<Module>>,
+    /// What is left to run, the innermost block or loop last.
+    tasks: Vec<Task>,
+}
+
+enum Task {
+    /// The rest of a block, with the scope it runs in.
+    Block(VecDeque<Stmt>, Scope),
+    /// A `while` loop: its condition and body.
+    While(Expr, Expr),
+    /// A `for` loop: the items left, the loop variables and the body.
+    For(Iter, Pattern, Expr),
+}
+
+/// The environment of a block and the names it declared, which are not
+/// copied back to the enclosing block when it ends.
+struct Scope {
+    env: Environment,
+    locals: HashSet<String>,
+}
+
+/// What running one statement of a generator led to.
+enum Step {
+    Yield(Value),
+    /// A block or loop to run before the rest of the current block.
+    Enter(Task),
+    Continue,
+}
+
+impl Generator {
+    fn resume(&mut self, env: &Environment) -> Result<Option<Value>, RuntimeError> {
+        let result = env.with_frame(self.frame.clone(), self.module.clone(), || self.run());
+        // `?` ends the generator, passing the error on to whoever asked for
+        // the next item.
+        result.map_err(|err| match err {
+            RuntimeError::Propagated(value) => RuntimeError::Raised(value),
+            err => err,
+        })
+    }
+
+    /// Runs until the next `yield`, returning its value, or to the end of
+    /// the body.
+    fn run(&mut self) -> Result<Option<Value>, RuntimeError> {
+        while let Some(task) = self.tasks.pop() {
+            match task {
+                Task::Block(mut stmts, mut scope) => {
+                    let Some(stmt) = stmts.pop_front() else {
+                        self.end_block(scope);
+                        continue;
+                    };
+                    scope
+                        .locals
+                        .extend(stmt.declared_names().into_iter().cloned());
+                    let step = statement(stmt, &mut scope.env)?;
+                    self.tasks.push(Task::Block(stmts, scope));
+                    match step {
+                        Step::Yield(value) => return Ok(Some(value)),
+                        Step::Enter(task) => self.tasks.push(task),
+                        Step::Continue => {}
+                    }
+                }
+                Task::While(condition, body) => {
+                    match eval_expression(condition.clone(), self.env())? {
+                        Value::Bool(true) => {
+                            let scope = self.scope(HashSet::new());
+                            self.tasks.push(Task::While(condition, body.clone()));
+                            self.tasks.push(Task::Block(block_statements(body), scope));
+                        }
+                        Value::Bool(false) => {}
+                        _ => return Err(RuntimeError::InvalidCondition),
+                    }
+                }
+                Task::For(iter, pattern, body) => {
+                    let Some(item) = iter.next(self.env())? else {
+                        continue;
+                    };
+                    let mut scope = self.scope(pattern.names().into_iter().cloned().collect());
+                    bind(pattern.clone(), item, &mut scope.env)?;
+                    self.tasks.push(Task::For(iter, pattern, body.clone()));
+                    self.tasks.push(Task::Block(block_statements(body), scope));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// The environment of the innermost block.
+    fn env(&mut self) -> &mut Environment {
+        self.tasks
+            .iter_mut()
+            .rev()
+            .find_map(|task| match task {
+                Task::Block(_, scope) => Some(&mut scope.env),
+                _ => None,
+            })
+            .expect("loops run inside a block")
+    }
+
+    /// A scope for a block inside the innermost one.
+    fn scope(&mut self, locals: HashSet<String>) -> Scope {
+        Scope {
+            env: self.env().clone(),
+            locals,
+        }
+    }
+
+    /// Copies the assignments made in a finished block back to the block
+    /// around it, like the evaluator does.
+    fn end_block(&mut self, scope: Scope) {
+        if self.tasks.is_empty() {
+            return;
+        }
+        self.env().write_back(&scope.env, &scope.locals);
+    }
+}
+
+/// Runs a statement of a generator, which may yield or start a block or loop
+/// that does.
+fn statement(stmt: Stmt, env: &mut Environment) -> Result<Step, RuntimeError> {
+    if !stmt.contains_yield() {
+        eval_statement(stmt, env)?;
+        return Ok(Step::Continue);
+    }
+    let nested = |expr, env: &Environment| {
+        Task::Block(
+            block_statements(expr),
+            Scope {
+                env: env.clone(),
+                locals: HashSet::new(),
+            },
+        )
+    };
+    Ok(match stmt {
+        Stmt::Yield(value, _) => Step::Yield(eval_expression(value, env)?),
+        Stmt::While(condition, body, _) => Step::Enter(Task::While(condition, body)),
+        Stmt::For(pattern, iterable, body, _) => {
+            let iter = Iter::of("for", eval_expression(iterable, env)?)?;
+            Step::Enter(Task::For(iter, pattern, body))
+        }
+        Stmt::Expression(block @ Expr::Block(..))
+        | Stmt::ImplicitReturn(block @ Expr::Block(..)) => Step::Enter(nested(block, env)),
+        Stmt::Expression(Expr::If(condition, then_branch, else_branch, _))
+        | Stmt::ImplicitReturn(Expr::If(condition, then_branch, else_branch, _)) => {
+            match eval_expression(*condition, env)? {
+                Value::Bool(true) => Step::Enter(nested(*then_branch, env)),
+                _ => match else_branch {
+                    Some(else_branch) => Step::Enter(nested(*else_branch, env)),
+                    None => Step::Continue,
+                },
+            }
+        }
+        // Anywhere else, such as inside a `try`, the generator could not
+        // stop at the `yield`.
+        _ => return Err(RuntimeError::MisplacedYield),
+    })
+}
+
+/// The statements of a block, with its tail as the last one.
+fn block_statements(expr: Expr) -> VecDeque<Stmt> {
+    match expr {
+        Expr::Block(stmts, tail, _) => {
+            let mut stmts: VecDeque<Stmt> = stmts.into();
+            stmts.extend(tail.map(|tail| Stmt::Expression(*tail)));
+            stmts
+        }
+        expr => VecDeque::from([Stmt::Expression(expr)]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{Environment, RuntimeError, Value, eval_in};
+
+    fn eval(input: &str) -> Result<Value, RuntimeError> {
+        eval_in(input, &mut Environment::new())
+    }
+
+    fn eval_str(input: &str) -> String {
+        eval(input)
+            .unwrap_or_else(|err| panic!("{}", err))
+            .to_string()
+    }
+
+    #[test]
+    fn test_adapters() {
+        let functions = "
+            fn square(n) { n * n }
+            fn even(n) { n / 2 * 2 == n }
+            fn pair(n) { list(n, n) }
+        ";
+        for (input, expected) in [
+            // Infinite ranges work as long as only finitely many items are
+            // asked for.
+            (
+                "range(0).map(square).filter(even).take(3).collect()",
+                "[0, 4, 16]",
+            ),
+            ("collect(skip(range(0, 5), 3))", "[3, 4]"),
+            ("collect(range(3, 1))", "[]"),
+            ("iter(list(1, 2)).flat_map(pair).collect()", "[1, 1, 2, 2]"),
+            (
+                "enumerate(zip(range(5), list(\"a\", \"b\"))).collect()",
+                "[(0, (5, \"a\")), (1, (6, \"b\"))]",
+            ),
+            ("find(range(1), even)", "Ok(2)"),
+            ("reduce(range(0, 5), 0, wrapping_add)", "10"),
+            ("sort_by(range(0, 3), square)", "[0, 1, 2]"),
+            (
+                "let it = iter(list(1)); list(next(it), next(it))",
+                "[Ok(1), Err(end of iteration)]",
+            ),
+            // Lists given to the list builtins still give lists.
+            ("list(1, 2).map(square)", "[1, 4]"),
+        ] {
+            assert_eq!(
+                eval_str(&format!("{} {}", functions, input)),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_generators() {
+        let input = "
+            fn fib() {
+                let a = 0;
+                let b = 1;
+                while true {
+                    yield a;
+                    let next = a + b;
+                    a = b;
+                    b = next;
+                }
+            }
+            fib().take(8).collect()
+        ";
+        assert_eq!(eval_str(input), "[0, 1, 1, 2, 3, 5, 8, 13]");
+        // Generators resume inside blocks, ifs and loops, and may recurse.
+        let input = "
+            fn upto(n) {
+                if n > 0 {
+                    for x in upto(n - 1) {
+                        yield x;
+                    }
+                    yield n;
+                } else {
+                    { yield 0; };
+                }
+            }
+            collect(upto(3))
+        ";
+        assert_eq!(eval_str(input), "[0, 1, 2, 3]");
+        // A generator's body runs only as items are asked for.
+        let input = "
+            fn noisy() { yield 1; error(\"too far\"); }
+            let g = noisy();
+            next(g)
+        ";
+        assert_eq!(eval_str(input), "Ok(1)");
+    }
+
+    #[test]
+    fn test_iterator_errors() {
+        // `?` ends the generator with the error.
+        let input = "
+            fn parsed(xs) { for x in xs { yield parse_int(x)?; } }
+            let g = parsed(list(\"1\", \"x\", \"3\"));
+            let first = next(g);
+            let caught = try { next(g) } catch e { e };
+            list(first, caught, next(g))
+        ";
+        assert_eq!(
+            eval_str(input),
+            "[Ok(1), \"invalid integer: 'x'\", Err(end of iteration)]"
+        );
+        assert_eq!(
+            eval("fn g() { try { yield 1; } catch e { e }; } next(g())")
+                .map_err(|err| err.cause().clone()),
+            Err(RuntimeError::MisplacedYield)
+        );
+        assert_eq!(
+            eval("let m = 0; fn f(x) { next(m) } m = map(range(0), f); next(m)")
+                .map_err(|err| err.cause().clone()),
+            Err(RuntimeError::IteratorRunning)
+        );
+        assert_eq!(
+            eval("take(1, 2)"),
+            Err(RuntimeError::TypeMismatch(
+                "take expects a list or an iterator and a non-negative integer, got [Int(1), Int(2)]"
+                    .to_string()
+            ))
+        );
+        assert_eq!(
+            eval("for x in 3 { }"),
+            Err(RuntimeError::TypeMismatch(
+                "for expects a list or an iterator, got Int(3)".to_string()
+            ))
+        );
+    }
+}
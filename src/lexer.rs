@@ -1,9 +1,12 @@
+use crate::bigint::BigInt;
+use std::fmt;
 use std::iter::Peekable;
 use std::str::Chars;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Int(i64),
+    BigInt(BigInt),
     Float(f64),
     Str(String),
     Identifier(String),
@@ -12,6 +15,13 @@ pub enum Token {
     If,
     Else,
     While,
+    For,
+    In,
+    Yield,
+    Try,
+    Catch,
+    Import,
+    Pub,
     True,
     False,
     Plus,
@@ -35,37 +45,123 @@ pub enum Token {
     RBrace,
     SemiColon,
     Comma,
-    Comment,
+    Colon,
+    ColonColon,
+    Arrow,
+    Question,
+    Dot,
+    // The full text of a `//` or `/* */` comment, including delimiters.
+    Comment(String),
+    // `/// text`, documenting the item that follows.
+    DocComment(String),
+    // `//! text`, documenting the enclosing file.
+    InnerDocComment(String),
     Eof,
 }
 
+/// A 1-based line and column position in the source.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Span {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
 pub struct Lexer<'a> {
     input: Peekable<Chars<'a>>,
+    line: usize,
+    col: usize,
+    token_start: Span,
+    // Whether the last token can end an operand, so that a `.` after it
+    // starts a tuple index such as `pair.0` rather than a float literal.
+    after_operand: bool,
+    // Whether the last token is such a `.`, so that a number is an index.
+    after_dot: bool,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        Lexer {
+        let mut lexer = Lexer {
             input: input.chars().peekable(),
+            line: 1,
+            col: 1,
+            token_start: Span { line: 1, col: 1 },
+            after_operand: false,
+            after_dot: false,
+        };
+        // A `#!` line lets scripts be run directly on Unix.
+        if input.starts_with("#!") {
+            while lexer.bump().is_some_and(|ch| ch != '\n') {}
         }
+        lexer
+    }
+
+    /// The position where the most recently returned token starts.
+    pub fn span(&self) -> Span {
+        self.token_start
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.input.next()?;
+        if ch == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(ch)
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        let mut lookahead = self.input.clone();
+        lookahead.next();
+        lookahead.next()
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, LexError> {
+        Err(LexError {
+            message: message.into(),
+            span: self.token_start,
+        })
     }
 
     fn skip_whitespace(&mut self) {
         while let Some(&ch) = self.input.peek() {
             if ch.is_whitespace() {
-                self.input.next();
+                self.bump();
             } else {
                 break;
             }
         }
     }
 
-    pub fn next_token(&mut self) -> Token {
+    pub fn next_token(&mut self) -> Result<Token, LexError> {
         self.skip_whitespace();
-        match self.input.next() {
+        self.token_start = Span {
+            line: self.line,
+            col: self.col,
+        };
+        let token = match self.bump() {
             Some('+') => {
                 if let Some(&'=') = self.input.peek() {
-                    self.input.next();
+                    self.bump();
                     Token::PlusEq
                 } else {
                     Token::Plus
@@ -73,15 +169,18 @@ impl<'a> Lexer<'a> {
             }
             Some('-') => {
                 if let Some(&'=') = self.input.peek() {
-                    self.input.next();
+                    self.bump();
                     Token::MinusEq
+                } else if let Some(&'>') = self.input.peek() {
+                    self.bump();
+                    Token::Arrow
                 } else {
                     Token::Minus
                 }
             }
             Some('*') => {
                 if let Some(&'=') = self.input.peek() {
-                    self.input.next();
+                    self.bump();
                     Token::StarEq
                 } else {
                     Token::Star
@@ -89,17 +188,14 @@ impl<'a> Lexer<'a> {
             }
             Some('/') => {
                 if let Some(&'=') = self.input.peek() {
-                    self.input.next();
+                    self.bump();
                     Token::SlashEq
                 } else if let Some(&'/') = self.input.peek() {
-                    self.input.next(); // Eat the second `/`.
-                    while let Some(&ch) = self.input.peek() {
-                        if ch == '\n' {
-                            break;
-                        }
-                        self.input.next();
-                    }
-                    Token::Comment
+                    self.bump(); // Eat the second `/`.
+                    self.read_line_comment()
+                } else if let Some(&'*') = self.input.peek() {
+                    self.bump(); // Eat the `*`.
+                    self.read_block_comment()?
                 } else {
                     Token::Slash
                 }
@@ -110,12 +206,21 @@ impl<'a> Lexer<'a> {
             Some('}') => Token::RBrace,
             Some(';') => Token::SemiColon,
             Some(',') => Token::Comma,
+            Some(':') => {
+                if let Some(&':') = self.input.peek() {
+                    self.bump();
+                    Token::ColonColon
+                } else {
+                    Token::Colon
+                }
+            }
             Some('!') => Token::Bang,
+            Some('?') => Token::Question,
             Some('<') => Token::Lt,
             Some('>') => Token::Gt,
             Some('=') => {
                 if let Some(&'=') = self.input.peek() {
-                    self.input.next();
+                    self.bump();
                     Token::EqEq
                 } else {
                     Token::Eq
@@ -123,78 +228,239 @@ impl<'a> Lexer<'a> {
             }
             Some('&') => {
                 if let Some(&'&') = self.input.peek() {
-                    self.input.next(); // Eat second `&`,
+                    self.bump(); // Eat second `&`,
                     Token::And
                 } else {
-                    panic!("Unexpected character '&'. Did you mean '&&'?");
+                    return self.error("Unexpected character '&'. Did you mean '&&'?");
                 }
             }
             Some('|') => {
                 if let Some(&'|') = self.input.peek() {
-                    self.input.next(); // Eat second `|`.
+                    self.bump(); // Eat second `|`.
                     Token::Or
                 } else {
-                    panic!("Unexpected character '|'. Did you mean '||'?");
+                    return self.error("Unexpected character '|'. Did you mean '||'?");
                 }
             }
-            Some('"') => self.read_string(),
-            Some(ch) if ch.is_ascii_digit() => self.read_number(ch),
+            Some('.')
+                if !self.after_operand
+                    && matches!(self.input.peek(), Some(ch) if ch.is_ascii_digit()) =>
+            {
+                return self.error("Float literals must start with a digit, e.g. '0.5'");
+            }
+            Some('.') => Token::Dot,
+            Some('"') => self.read_string()?,
+            Some(ch) if ch.is_ascii_digit() && self.after_dot => self.read_index(ch)?,
+            Some(ch) if ch.is_ascii_digit() => self.read_number(ch)?,
             Some(ch) if ch.is_alphabetic() || ch == '_' => self.read_identifier(ch),
             None => Token::Eof,
-            Some(ch) => panic!("Unexpected character: {}", ch),
+            Some(ch) => return self.error(format!("Unexpected character: {}", ch)),
+        };
+        let is_index = self.after_dot && matches!(token, Token::Int(_));
+        self.after_dot = self.after_operand && token == Token::Dot;
+        self.after_operand = is_index
+            || matches!(
+                token,
+                Token::Identifier(_)
+                    | Token::Str(_)
+                    | Token::RParen
+                    | Token::RBrace
+                    | Token::Question
+            );
+        Ok(token)
+    }
+
+    // Reads the `0` of `pair.0`, which is never part of a float.
+    fn read_index(&mut self, first_digit: char) -> Result<Token, LexError> {
+        let mut digits = String::from(first_digit);
+        self.read_digits(&mut digits);
+        self.check_number_suffix()?;
+        match digits.parse::<i64>() {
+            Ok(index) => Ok(Token::Int(index)),
+            Err(_) => self.error(format!("Tuple index out of range: {}", digits)),
+        }
+    }
+
+    fn read_line_comment(&mut self) -> Token {
+        let mut text = String::new();
+        while let Some(&ch) = self.input.peek() {
+            if ch == '\n' {
+                break;
+            }
+            self.bump();
+            text.push(ch);
+        }
+        // `///` and `//!` are doc comments, but `////` is a regular comment.
+        let doc = |rest: &str| {
+            rest.strip_prefix(' ')
+                .unwrap_or(rest)
+                .trim_end()
+                .to_string()
+        };
+        match text.strip_prefix('/') {
+            Some(rest) if !rest.starts_with('/') => Token::DocComment(doc(rest)),
+            Some(_) => Token::Comment(format!("//{}", text)),
+            None => match text.strip_prefix('!') {
+                Some(rest) => Token::InnerDocComment(doc(rest)),
+                None => Token::Comment(format!("//{}", text)),
+            },
+        }
+    }
+
+    // Block comments nest, so `/* a /* b */ c */` is a single comment.
+    fn read_block_comment(&mut self) -> Result<Token, LexError> {
+        let mut text = String::from("/*");
+        let mut depth = 1;
+        while depth > 0 {
+            match self.bump() {
+                Some('/') if self.input.peek() == Some(&'*') => {
+                    self.bump();
+                    text.push_str("/*");
+                    depth += 1;
+                }
+                Some('*') if self.input.peek() == Some(&'/') => {
+                    self.bump();
+                    text.push_str("*/");
+                    depth -= 1;
+                }
+                Some(ch) => text.push(ch),
+                None => return self.error("Unterminated block comment"),
+            }
         }
+        Ok(Token::Comment(text))
     }
 
-    fn read_number(&mut self, first_digit: char) -> Token {
+    fn read_number(&mut self, first_digit: char) -> Result<Token, LexError> {
+        if first_digit == '0' {
+            let radix = match self.input.peek() {
+                Some('x') => Some((16, "hexadecimal")),
+                Some('o') => Some((8, "octal")),
+                Some('b') => Some((2, "binary")),
+                _ => None,
+            };
+            if let Some((radix, kind)) = radix {
+                self.bump(); // Eat the radix prefix.
+                return self.read_radix_number(radix, kind);
+            }
+        }
+
         let mut number_str = String::from(first_digit);
-        let mut has_dot = false;
+        self.read_digits(&mut number_str);
+        let mut is_float = false;
+        // Only treat the `.` as a decimal point when a digit follows it.
+        if self.input.peek() == Some(&'.')
+            && matches!(self.peek_second(), Some(ch) if ch.is_ascii_digit())
+        {
+            self.bump();
+            number_str.push('.');
+            self.read_digits(&mut number_str);
+            is_float = true;
+        }
+        if let Some(&e) = self.input.peek()
+            && (e == 'e' || e == 'E')
+        {
+            self.bump();
+            number_str.push('e');
+            if let Some(&sign) = self.input.peek()
+                && (sign == '+' || sign == '-')
+            {
+                self.bump();
+                number_str.push(sign);
+            }
+            if !matches!(self.input.peek(), Some(ch) if ch.is_ascii_digit()) {
+                return self.error("Missing digits in the exponent of a float literal");
+            }
+            self.read_digits(&mut number_str);
+            is_float = true;
+        }
+        self.check_number_suffix()?;
+
+        if is_float {
+            let value = number_str.parse::<f64>().unwrap();
+            if value.is_infinite() {
+                return self.error(format!("Float literal out of range: {}", number_str));
+            }
+            Ok(Token::Float(value))
+        } else if let Ok(value) = number_str.parse::<i64>() {
+            Ok(Token::Int(value))
+        } else {
+            // Too long for an `i64`, so keep it as an arbitrary-precision integer.
+            Ok(Token::BigInt(BigInt::parse(&number_str, 10).unwrap()))
+        }
+    }
+
+    fn read_radix_number(&mut self, radix: u32, kind: &str) -> Result<Token, LexError> {
+        let mut digits = String::new();
+        while let Some(&ch) = self.input.peek() {
+            if ch == '_' {
+                self.bump();
+            } else if ch.is_ascii_alphanumeric() {
+                if !ch.is_digit(radix) {
+                    return self.error(format!("Invalid digit '{}' in {} literal", ch, kind));
+                }
+                self.bump();
+                digits.push(ch);
+            } else {
+                break;
+            }
+        }
+        if digits.is_empty() {
+            return self.error(format!("Missing digits in {} literal", kind));
+        }
+        match i64::from_str_radix(&digits, radix) {
+            Ok(value) => Ok(Token::Int(value)),
+            Err(_) => Ok(Token::BigInt(BigInt::parse(&digits, radix).unwrap())),
+        }
+    }
+
+    // Reads decimal digits, dropping `_` separators.
+    fn read_digits(&mut self, number_str: &mut String) {
         while let Some(&ch) = self.input.peek() {
             if ch.is_ascii_digit() {
-                self.input.next();
+                self.bump();
                 number_str.push(ch);
-            } else if ch == '.' && !has_dot {
-                self.input.next();
-                number_str.push(ch);
-                has_dot = true;
+            } else if ch == '_' {
+                self.bump();
             } else {
                 break;
             }
         }
+    }
 
-        if has_dot {
-            let value = number_str.parse::<f64>().unwrap();
-            Token::Float(value)
-        } else {
-            let value = number_str.parse::<i64>().unwrap();
-            Token::Int(value)
+    fn check_number_suffix(&mut self) -> Result<(), LexError> {
+        match self.input.peek() {
+            Some(&ch) if ch.is_alphanumeric() || ch == '_' => {
+                self.error(format!("Invalid suffix '{}' on number literal", ch))
+            }
+            _ => Ok(()),
         }
     }
 
-    fn read_string(&mut self) -> Token {
+    fn read_string(&mut self) -> Result<Token, LexError> {
         let mut string_content = String::new();
         loop {
             match self.input.peek() {
                 Some(&'"') => {
-                    self.input.next(); // Eat the `"`.
+                    self.bump(); // Eat the `"`.
                     break;
                 }
                 Some(_) => {
-                    let ch = self.input.next().unwrap();
+                    let ch = self.bump().unwrap();
                     string_content.push(ch);
                 }
                 None => {
-                    panic!("Unterminated string literal");
+                    return self.error("Unterminated string literal");
                 }
             }
         }
-        Token::Str(string_content)
+        Ok(Token::Str(string_content))
     }
 
     fn read_identifier(&mut self, first_char: char) -> Token {
         let mut ident = String::from(first_char);
         while let Some(&ch) = self.input.peek() {
             if ch.is_alphanumeric() || ch == '_' {
-                self.input.next();
+                self.bump();
                 ident.push(ch);
             } else {
                 break;
@@ -205,7 +471,14 @@ impl<'a> Lexer<'a> {
             "fn" => Token::Fn,
             "if" => Token::If,
             "while" => Token::While,
+            "for" => Token::For,
+            "in" => Token::In,
+            "yield" => Token::Yield,
             "else" => Token::Else,
+            "try" => Token::Try,
+            "catch" => Token::Catch,
+            "import" => Token::Import,
+            "pub" => Token::Pub,
             "true" => Token::True,
             "false" => Token::False,
             _ => Token::Identifier(ident),
@@ -213,6 +486,20 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// Lexes the whole input, including comments and the final `Eof`.
+pub fn tokenize(input: &str) -> Result<Vec<(Token, Span)>, LexError> {
+    let mut lexer = Lexer::new(input);
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next_token()?;
+        let is_eof = token == Token::Eof;
+        tokens.push((token, lexer.span()));
+        if is_eof {
+            return Ok(tokens);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -222,15 +509,15 @@ mod tests {
         let input = "=+(){},;";
         let mut lexer = Lexer::new(input);
 
-        assert_eq!(lexer.next_token(), Token::Eq);
-        assert_eq!(lexer.next_token(), Token::Plus);
-        assert_eq!(lexer.next_token(), Token::LParen);
-        assert_eq!(lexer.next_token(), Token::RParen);
-        assert_eq!(lexer.next_token(), Token::LBrace);
-        assert_eq!(lexer.next_token(), Token::RBrace);
-        assert_eq!(lexer.next_token(), Token::Comma);
-        assert_eq!(lexer.next_token(), Token::SemiColon);
-        assert_eq!(lexer.next_token(), Token::Eof);
+        assert_eq!(lexer.next_token().unwrap(), Token::Eq);
+        assert_eq!(lexer.next_token().unwrap(), Token::Plus);
+        assert_eq!(lexer.next_token().unwrap(), Token::LParen);
+        assert_eq!(lexer.next_token().unwrap(), Token::RParen);
+        assert_eq!(lexer.next_token().unwrap(), Token::LBrace);
+        assert_eq!(lexer.next_token().unwrap(), Token::RBrace);
+        assert_eq!(lexer.next_token().unwrap(), Token::Comma);
+        assert_eq!(lexer.next_token().unwrap(), Token::SemiColon);
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
     }
 
     #[test]
@@ -238,26 +525,114 @@ mod tests {
         let input = "let fn if else while true false my_var";
         let mut lexer = Lexer::new(input);
 
-        assert_eq!(lexer.next_token(), Token::Let);
-        assert_eq!(lexer.next_token(), Token::Fn);
-        assert_eq!(lexer.next_token(), Token::If);
-        assert_eq!(lexer.next_token(), Token::Else);
-        assert_eq!(lexer.next_token(), Token::While);
-        assert_eq!(lexer.next_token(), Token::True);
-        assert_eq!(lexer.next_token(), Token::False);
-        assert_eq!(lexer.next_token(), Token::Identifier("my_var".to_string()));
-        assert_eq!(lexer.next_token(), Token::Eof);
+        assert_eq!(lexer.next_token().unwrap(), Token::Let);
+        assert_eq!(lexer.next_token().unwrap(), Token::Fn);
+        assert_eq!(lexer.next_token().unwrap(), Token::If);
+        assert_eq!(lexer.next_token().unwrap(), Token::Else);
+        assert_eq!(lexer.next_token().unwrap(), Token::While);
+        assert_eq!(lexer.next_token().unwrap(), Token::True);
+        assert_eq!(lexer.next_token().unwrap(), Token::False);
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Identifier("my_var".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
+    }
+
+    #[test]
+    fn test_next_token_error_handling() {
+        let mut lexer = Lexer::new("try catch f()?");
+
+        assert_eq!(lexer.next_token().unwrap(), Token::Try);
+        assert_eq!(lexer.next_token().unwrap(), Token::Catch);
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Identifier("f".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::LParen);
+        assert_eq!(lexer.next_token().unwrap(), Token::RParen);
+        assert_eq!(lexer.next_token().unwrap(), Token::Question);
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
     }
 
     #[test]
+    fn test_next_token_iteration() {
+        let mut lexer = Lexer::new("for x in xs.take(2) { yield x; }");
+
+        assert_eq!(lexer.next_token().unwrap(), Token::For);
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Identifier("x".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::In);
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Identifier("xs".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::Dot);
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Identifier("take".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::LParen);
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(2));
+        assert_eq!(lexer.next_token().unwrap(), Token::RParen);
+        assert_eq!(lexer.next_token().unwrap(), Token::LBrace);
+        assert_eq!(lexer.next_token().unwrap(), Token::Yield);
+    }
+
+    #[test]
+    fn test_next_token_tuple_indexes() {
+        let tokens: Vec<Token> = tokenize("t.0.1 (a).2 1.5")
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("t".to_string()),
+                Token::Dot,
+                Token::Int(0),
+                Token::Dot,
+                Token::Int(1),
+                Token::LParen,
+                Token::Identifier("a".to_string()),
+                Token::RParen,
+                Token::Dot,
+                Token::Int(2),
+                Token::Float(1.5),
+                Token::Eof,
+            ]
+        );
+        // Only an operand can be indexed.
+        assert!(tokenize("x = .5;").is_err());
+        assert!(tokenize("t.1_000x").is_err());
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
     fn test_next_token_numbers() {
         let input = "123 3.14 0";
         let mut lexer = Lexer::new(input);
 
-        assert_eq!(lexer.next_token(), Token::Int(123));
-        assert_eq!(lexer.next_token(), Token::Float(3.14));
-        assert_eq!(lexer.next_token(), Token::Int(0));
-        assert_eq!(lexer.next_token(), Token::Eof);
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(123));
+        assert_eq!(lexer.next_token().unwrap(), Token::Float(3.14));
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(0));
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
+    }
+
+    #[test]
+    fn test_next_token_big_numbers() {
+        let input = "9223372036854775807 9223372036854775808";
+        let mut lexer = Lexer::new(input);
+
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(i64::MAX));
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::BigInt(BigInt::parse("9223372036854775808", 10).unwrap())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
     }
 
     #[test]
@@ -265,9 +640,9 @@ mod tests {
         let input = r#""hello" "world""#;
         let mut lexer = Lexer::new(input);
 
-        assert_eq!(lexer.next_token(), Token::Str("hello".to_string()));
-        assert_eq!(lexer.next_token(), Token::Str("world".to_string()));
-        assert_eq!(lexer.next_token(), Token::Eof);
+        assert_eq!(lexer.next_token().unwrap(), Token::Str("hello".to_string()));
+        assert_eq!(lexer.next_token().unwrap(), Token::Str("world".to_string()));
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
     }
 
     #[test]
@@ -275,22 +650,57 @@ mod tests {
         let input = "+ - * / ! < > == = && || += -= *= /=";
         let mut lexer = Lexer::new(input);
 
-        assert_eq!(lexer.next_token(), Token::Plus);
-        assert_eq!(lexer.next_token(), Token::Minus);
-        assert_eq!(lexer.next_token(), Token::Star);
-        assert_eq!(lexer.next_token(), Token::Slash);
-        assert_eq!(lexer.next_token(), Token::Bang);
-        assert_eq!(lexer.next_token(), Token::Lt);
-        assert_eq!(lexer.next_token(), Token::Gt);
-        assert_eq!(lexer.next_token(), Token::EqEq);
-        assert_eq!(lexer.next_token(), Token::Eq);
-        assert_eq!(lexer.next_token(), Token::And);
-        assert_eq!(lexer.next_token(), Token::Or);
-        assert_eq!(lexer.next_token(), Token::PlusEq);
-        assert_eq!(lexer.next_token(), Token::MinusEq);
-        assert_eq!(lexer.next_token(), Token::StarEq);
-        assert_eq!(lexer.next_token(), Token::SlashEq);
-        assert_eq!(lexer.next_token(), Token::Eof);
+        assert_eq!(lexer.next_token().unwrap(), Token::Plus);
+        assert_eq!(lexer.next_token().unwrap(), Token::Minus);
+        assert_eq!(lexer.next_token().unwrap(), Token::Star);
+        assert_eq!(lexer.next_token().unwrap(), Token::Slash);
+        assert_eq!(lexer.next_token().unwrap(), Token::Bang);
+        assert_eq!(lexer.next_token().unwrap(), Token::Lt);
+        assert_eq!(lexer.next_token().unwrap(), Token::Gt);
+        assert_eq!(lexer.next_token().unwrap(), Token::EqEq);
+        assert_eq!(lexer.next_token().unwrap(), Token::Eq);
+        assert_eq!(lexer.next_token().unwrap(), Token::And);
+        assert_eq!(lexer.next_token().unwrap(), Token::Or);
+        assert_eq!(lexer.next_token().unwrap(), Token::PlusEq);
+        assert_eq!(lexer.next_token().unwrap(), Token::MinusEq);
+        assert_eq!(lexer.next_token().unwrap(), Token::StarEq);
+        assert_eq!(lexer.next_token().unwrap(), Token::SlashEq);
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
+    }
+
+    #[test]
+    fn test_next_token_imports() {
+        let mut lexer = Lexer::new("import utils::{add}: pub");
+        assert_eq!(lexer.next_token().unwrap(), Token::Import);
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Identifier("utils".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::ColonColon);
+        assert_eq!(lexer.next_token().unwrap(), Token::LBrace);
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Identifier("add".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::RBrace);
+        assert_eq!(lexer.next_token().unwrap(), Token::Colon);
+        assert_eq!(lexer.next_token().unwrap(), Token::Pub);
+    }
+
+    #[test]
+    fn test_shebang() {
+        let mut lexer = Lexer::new("#!/usr/bin/env toy-rs\nlet x");
+        assert_eq!(lexer.next_token().unwrap(), Token::Let);
+        assert_eq!(lexer.span(), Span { line: 2, col: 1 });
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Identifier("x".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
+
+        // Only on the first line.
+        let mut lexer = Lexer::new("\n#!");
+        assert!(lexer.next_token().is_err());
     }
 
     #[test]
@@ -298,12 +708,15 @@ mod tests {
         let input = "  \t\nlet  x = 5;";
         let mut lexer = Lexer::new(input);
 
-        assert_eq!(lexer.next_token(), Token::Let);
-        assert_eq!(lexer.next_token(), Token::Identifier("x".to_string()));
-        assert_eq!(lexer.next_token(), Token::Eq);
-        assert_eq!(lexer.next_token(), Token::Int(5));
-        assert_eq!(lexer.next_token(), Token::SemiColon);
-        assert_eq!(lexer.next_token(), Token::Eof);
+        assert_eq!(lexer.next_token().unwrap(), Token::Let);
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Identifier("x".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::Eq);
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(5));
+        assert_eq!(lexer.next_token().unwrap(), Token::SemiColon);
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
     }
 
     #[test]
@@ -311,12 +724,122 @@ mod tests {
         let input = "let x = 5; // comment";
         let mut lexer = Lexer::new(input);
 
-        assert_eq!(lexer.next_token(), Token::Let);
-        assert_eq!(lexer.next_token(), Token::Identifier("x".to_string()));
-        assert_eq!(lexer.next_token(), Token::Eq);
-        assert_eq!(lexer.next_token(), Token::Int(5));
-        assert_eq!(lexer.next_token(), Token::SemiColon);
-        assert_eq!(lexer.next_token(), Token::Comment);
-        assert_eq!(lexer.next_token(), Token::Eof);
+        assert_eq!(lexer.next_token().unwrap(), Token::Let);
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Identifier("x".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::Eq);
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(5));
+        assert_eq!(lexer.next_token().unwrap(), Token::SemiColon);
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Comment("// comment".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
+    }
+
+    #[test]
+    fn test_next_token_number_formats() {
+        let input = "0xFF 0o17 0b1010 1_000_000 1.5e-3 2E3 6.02e+23 0x_dead_BEEF";
+        let mut lexer = Lexer::new(input);
+
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(255));
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(15));
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(10));
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(1_000_000));
+        assert_eq!(lexer.next_token().unwrap(), Token::Float(1.5e-3));
+        assert_eq!(lexer.next_token().unwrap(), Token::Float(2e3));
+        assert_eq!(lexer.next_token().unwrap(), Token::Float(6.02e23));
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(0xdead_beef));
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
+    }
+
+    #[test]
+    fn test_next_token_number_errors() {
+        let cases = [
+            ("0x", "Missing digits in hexadecimal literal"),
+            ("0b102", "Invalid digit '2' in binary literal"),
+            ("0o8", "Invalid digit '8' in octal literal"),
+            ("1e", "Missing digits in the exponent of a float literal"),
+            ("1.5e+", "Missing digits in the exponent of a float literal"),
+            ("1e999", "Float literal out of range: 1e999"),
+            ("12abc", "Invalid suffix 'a' on number literal"),
+            (".5", "Float literals must start with a digit, e.g. '0.5'"),
+        ];
+        for (input, message) in cases {
+            let mut lexer = Lexer::new(input);
+            let err = lexer.next_token().unwrap_err();
+            assert_eq!(err.message, message, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let input = "let x = 1;\n  let y = @;";
+        let mut lexer = Lexer::new(input);
+        for _ in 0..8 {
+            lexer.next_token().unwrap();
+        }
+        let err = lexer.next_token().unwrap_err();
+        assert_eq!(err.span, Span { line: 2, col: 11 });
+        assert_eq!(err.to_string(), "2:11: Unexpected character: @");
+
+        let mut lexer = Lexer::new("\"abc");
+        assert_eq!(
+            lexer.next_token().unwrap_err().message,
+            "Unterminated string literal"
+        );
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let input = "1 /* one */ 2 /* outer /* inner */ still outer */ 3 /**/ 4";
+        let mut lexer = Lexer::new(input);
+
+        let comment = |text: &str| Token::Comment(text.to_string());
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(1));
+        assert_eq!(lexer.next_token().unwrap(), comment("/* one */"));
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(2));
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            comment("/* outer /* inner */ still outer */")
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(3));
+        assert_eq!(lexer.next_token().unwrap(), comment("/**/"));
+        assert_eq!(lexer.next_token().unwrap(), Token::Int(4));
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
+
+        let mut lexer = Lexer::new("let x = 1;\n/* open /* nested */");
+        for _ in 0..5 {
+            lexer.next_token().unwrap();
+        }
+        let err = lexer.next_token().unwrap_err();
+        assert_eq!(err.to_string(), "2:1: Unterminated block comment");
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let input = "//! Module docs.\n/// Adds.\n///\n//// Not docs.\nfn";
+        let mut lexer = Lexer::new(input);
+
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::InnerDocComment("Module docs.".to_string())
+        );
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::DocComment("Adds.".to_string())
+        );
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::DocComment(String::new())
+        );
+        assert_eq!(
+            lexer.next_token().unwrap(),
+            Token::Comment("//// Not docs.".to_string())
+        );
+        assert_eq!(lexer.next_token().unwrap(), Token::Fn);
+        assert_eq!(lexer.next_token().unwrap(), Token::Eof);
     }
 }
@@ -0,0 +1,705 @@
+use crate::ast::{Expr, Literal, Stmt, Visibility};
+use crate::interpreter::Environment;
+use crate::lexer::{Lexer, Span, Token, tokenize};
+use crate::parser::{ParseError, Parser};
+use crate::prelude;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+pub const LINTS: &[&str] = &[
+    "unused_variable",
+    "unused_parameter",
+    "unused_assignment",
+    "infinite_loop",
+    "shadowing",
+    "constant_condition",
+    "undefined_function",
+    "unknown_lint",
+];
+
+// Lint groups that can be named in directives, besides the lints themselves.
+const GROUPS: &[(&str, &[&str])] = &[
+    (
+        "unused",
+        &["unused_variable", "unused_parameter", "unused_assignment"],
+    ),
+    ("all", LINTS),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub lint: &'static str,
+    pub level: Level,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = match self.level {
+            Level::Deny => "error",
+            _ => "warning",
+        };
+        write!(
+            f,
+            "{}: {}[{}]: {}",
+            self.span, level, self.lint, self.message
+        )
+    }
+}
+
+/// Lints a program, returning the diagnostics that are not allowed, in
+/// source order.
+///
+/// Every lint warns by default. A `// toy: allow(unused)` comment on a line
+/// of its own applies to the whole file; after code, it applies only to
+/// that line. `warn(..)` and `deny(..)` work the same way.
+pub fn lint(source: &str) -> Result<Vec<Diagnostic>, ParseError> {
+    let program = Parser::new(Lexer::new(source))?.parse_program()?;
+    let mut config = Config::from_tokens(&tokenize(source)?);
+    let mut linter = Linter::new(&program);
+    linter.scopes.push(Vec::new());
+    for stmt in &program {
+        linter.stmt(stmt);
+    }
+    linter.pop_scope();
+
+    let mut diagnostics: Vec<Diagnostic> = std::mem::take(&mut config.errors)
+        .into_iter()
+        .chain(linter.diagnostics)
+        .filter_map(|(lint, message, span)| {
+            let level = config.level(lint, span.line);
+            (level != Level::Allow).then_some(Diagnostic {
+                lint,
+                level,
+                message,
+                span,
+            })
+        })
+        .collect();
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span);
+    Ok(diagnostics)
+}
+
+struct Config {
+    file: HashMap<&'static str, Level>,
+    lines: HashMap<(usize, &'static str), Level>,
+    // Problems with the directives themselves, reported as `unknown_lint`.
+    errors: Vec<(&'static str, String, Span)>,
+}
+
+impl Config {
+    fn from_tokens(tokens: &[(Token, Span)]) -> Self {
+        let mut config = Config {
+            file: HashMap::new(),
+            lines: HashMap::new(),
+            errors: Vec::new(),
+        };
+        let mut last_code_line = None;
+        for (token, span) in tokens {
+            let Token::Comment(text) = token else {
+                last_code_line = Some(span.line);
+                continue;
+            };
+            let trailing = last_code_line == Some(span.line);
+            let Some((level, names)) = parse_directive(text) else {
+                continue;
+            };
+            for name in names.split(',').map(str::trim) {
+                let Some(lints) = resolve(name) else {
+                    config
+                        .errors
+                        .push(("unknown_lint", format!("unknown lint `{}`", name), *span));
+                    continue;
+                };
+                for &lint in lints {
+                    if trailing {
+                        config.lines.insert((span.line, lint), level);
+                    } else {
+                        config.file.insert(lint, level);
+                    }
+                }
+            }
+        }
+        config
+    }
+
+    fn level(&self, lint: &'static str, line: usize) -> Level {
+        self.lines
+            .get(&(line, lint))
+            .or_else(|| self.file.get(lint))
+            .copied()
+            .unwrap_or(Level::Warn)
+    }
+}
+
+// Parses `// toy: allow(a, b)` into its level and the list inside the parens.
+fn parse_directive(comment: &str) -> Option<(Level, &str)> {
+    let body = comment
+        .strip_prefix("//")
+        .or_else(|| comment.strip_prefix("/*")?.strip_suffix("*/"))?;
+    let body = body.trim().strip_prefix("toy:")?.trim();
+    let (level, rest) = body.split_once('(')?;
+    let level = match level.trim() {
+        "allow" => Level::Allow,
+        "warn" => Level::Warn,
+        "deny" => Level::Deny,
+        _ => return None,
+    };
+    Some((level, rest.trim_end().strip_suffix(')')?))
+}
+
+fn resolve(name: &str) -> Option<&'static [&'static str]> {
+    if let Some((_, lints)) = GROUPS.iter().find(|(group, _)| *group == name) {
+        return Some(lints);
+    }
+    let index = LINTS.iter().position(|lint| *lint == name)?;
+    Some(&LINTS[index..=index])
+}
+
+#[derive(PartialEq)]
+enum BindingKind {
+    Let,
+    Param,
+}
+
+struct Binding {
+    name: String,
+    kind: BindingKind,
+    span: Span,
+    read: bool,
+    assigned: bool,
+}
+
+struct Linter {
+    scopes: Vec<Vec<Binding>>,
+    functions: HashSet<String>,
+    // Names that some function reads without binding them itself. Because
+    // scoping is dynamic, such a read may see any caller's variable.
+    free_reads: HashSet<String>,
+    // Every name bound by a `let` or a parameter anywhere in the program.
+    bound_names: HashSet<String>,
+    // The namespaces of imported files, whose contents are not linted.
+    namespaces: HashSet<String>,
+    builtins: Environment,
+    diagnostics: Vec<(&'static str, String, Span)>,
+}
+
+impl Linter {
+    fn new(program: &[Stmt]) -> Self {
+        let mut linter = Linter {
+            scopes: Vec::new(),
+            functions: HashSet::new(),
+            free_reads: HashSet::new(),
+            bound_names: HashSet::new(),
+            namespaces: HashSet::new(),
+            builtins: prelude::globals(),
+            diagnostics: Vec::new(),
+        };
+        for stmt in program {
+            linter.collect_functions(stmt);
+        }
+        linter
+    }
+
+    fn report(&mut self, lint: &'static str, message: String, span: Span) {
+        self.diagnostics.push((lint, message, span));
+    }
+
+    fn collect_functions(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Fn(name, params, _, body, ..) => {
+                self.functions.insert(name.clone());
+                let names = params.iter().flat_map(|param| param.pattern.names());
+                let mut declared: HashSet<String> = names.cloned().collect();
+                self.bound_names.extend(declared.iter().cloned());
+                let mut reads = Vec::new();
+                scan_expr(body, &mut declared, &mut reads);
+                self.free_reads
+                    .extend(reads.into_iter().filter(|name| !declared.contains(name)));
+                for_each_nested_stmt(body, &mut |stmt| self.collect_functions(stmt));
+            }
+            Stmt::Let(_, _, expr, ..) | Stmt::LetTuple(_, _, expr, _) => {
+                self.bound_names
+                    .extend(stmt.declared_names().into_iter().cloned());
+                for_each_nested_stmt(expr, &mut |stmt| self.collect_functions(stmt))
+            }
+            Stmt::Assign(_, expr, _) | Stmt::Expression(expr) | Stmt::ImplicitReturn(expr) => {
+                for_each_nested_stmt(expr, &mut |stmt| self.collect_functions(stmt))
+            }
+            Stmt::While(condition, body, _) => {
+                for_each_nested_stmt(condition, &mut |stmt| self.collect_functions(stmt));
+                for_each_nested_stmt(body, &mut |stmt| self.collect_functions(stmt));
+            }
+            Stmt::For(pattern, iterable, body, _) => {
+                self.bound_names
+                    .extend(pattern.names().into_iter().cloned());
+                for_each_nested_stmt(iterable, &mut |stmt| self.collect_functions(stmt));
+                for_each_nested_stmt(body, &mut |stmt| self.collect_functions(stmt));
+            }
+            Stmt::Yield(value, _) => {
+                for_each_nested_stmt(value, &mut |stmt| self.collect_functions(stmt))
+            }
+            Stmt::Import(import, _) => {
+                self.functions.extend(import.names().iter().cloned());
+                self.namespaces.insert(import.namespace());
+            }
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<&mut Binding> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|binding| binding.name == name)
+    }
+
+    fn declare(&mut self, name: &str, kind: BindingKind, span: Span) {
+        if let Some(earlier) = self.lookup(name) {
+            let line = earlier.span.line;
+            self.report(
+                "shadowing",
+                format!("`{}` shadows an earlier binding from line {}", name, line),
+                span,
+            );
+        }
+        self.scopes.last_mut().unwrap().push(Binding {
+            name: name.to_string(),
+            kind,
+            span,
+            read: false,
+            assigned: false,
+        });
+    }
+
+    fn pop_scope(&mut self) {
+        for binding in self.scopes.pop().unwrap_or_default() {
+            if binding.read
+                || binding.name.starts_with('_')
+                || self.free_reads.contains(&binding.name)
+            {
+                continue;
+            }
+            let (lint, message) = match binding.kind {
+                BindingKind::Param => (
+                    "unused_parameter",
+                    format!("unused parameter `{}`", binding.name),
+                ),
+                BindingKind::Let if binding.assigned => (
+                    "unused_assignment",
+                    format!("`{}` is assigned but never read", binding.name),
+                ),
+                BindingKind::Let => (
+                    "unused_variable",
+                    format!("unused variable `{}`", binding.name),
+                ),
+            };
+            self.report(lint, message, binding.span);
+        }
+    }
+
+    // Marks a called binding as read, or reports the call if nothing defines it.
+    fn call(&mut self, name: &str, span: Span) {
+        if let Some(binding) = self.lookup(name) {
+            binding.read = true;
+        } else if !self.functions.contains(name)
+            && !self.bound_names.contains(name)
+            && !name
+                .split_once("::")
+                .is_some_and(|(namespace, _)| self.namespaces.contains(namespace))
+            && self.builtins.get(name).is_none()
+        {
+            self.report(
+                "undefined_function",
+                format!("call to undefined function `{}`", name),
+                span,
+            );
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let(name, _, value, _, vis, span) => {
+                self.expr(value);
+                self.declare(name, BindingKind::Let, *span);
+                // Files that import this one can read it.
+                if *vis == Visibility::Public
+                    && let Some(binding) = self.lookup(name)
+                {
+                    binding.read = true;
+                }
+            }
+            Stmt::LetTuple(_, _, value, span) => {
+                self.expr(value);
+                for name in stmt.declared_names() {
+                    self.declare(name, BindingKind::Let, *span);
+                }
+            }
+            Stmt::Fn(_, params, _, body, _, _, span) => {
+                self.scopes.push(Vec::new());
+                for name in params.iter().flat_map(|param| param.pattern.names()) {
+                    self.declare(name, BindingKind::Param, *span);
+                }
+                self.expr(body);
+                self.pop_scope();
+            }
+            Stmt::Assign(name, value, span) => {
+                match value {
+                    // In `x += 1` the read of `x` only feeds the new value of
+                    // `x`, so it does not count as a use.
+                    Expr::Binary(lhs, _, rhs) if matches!(&**lhs, Expr::Variable(n, s) if n == name && s == span) => {
+                        self.expr(rhs)
+                    }
+                    _ => self.expr(value),
+                }
+                if let Some(binding) = self.lookup(name) {
+                    binding.assigned = true;
+                }
+            }
+            Stmt::While(condition, body, span) => {
+                match condition {
+                    // An infinite generator is one way to write a stream.
+                    Expr::Literal(Literal::Bool(true), _) if body.contains_yield() => {}
+                    Expr::Literal(Literal::Bool(true), _) => self.report(
+                        "infinite_loop",
+                        "`while true` loop never exits".to_string(),
+                        *span,
+                    ),
+                    Expr::Literal(Literal::Bool(false), _) => self.report(
+                        "constant_condition",
+                        "loop condition is always false".to_string(),
+                        condition.span(),
+                    ),
+                    _ => {}
+                }
+                self.expr(condition);
+                self.expr(body);
+            }
+            Stmt::For(pattern, iterable, body, span) => {
+                self.expr(iterable);
+                self.scopes.push(Vec::new());
+                for name in pattern.names() {
+                    self.declare(name, BindingKind::Let, *span);
+                }
+                self.expr(body);
+                self.pop_scope();
+            }
+            Stmt::Expression(expr) | Stmt::ImplicitReturn(expr) | Stmt::Yield(expr, _) => {
+                self.expr(expr)
+            }
+            Stmt::Import(..) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(..) => {}
+            Expr::Variable(name, _) => {
+                if let Some(binding) = self.lookup(name) {
+                    binding.read = true;
+                }
+            }
+            Expr::Unary(_, operand, _) => self.expr(operand),
+            Expr::Binary(lhs, _, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Call(name, args, span) | Expr::MethodCall(name, args, span) => {
+                self.call(name, *span);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Block(stmts, tail, _) => {
+                self.scopes.push(Vec::new());
+                for stmt in stmts {
+                    self.stmt(stmt);
+                }
+                if let Some(tail) = tail {
+                    self.expr(tail);
+                }
+                self.pop_scope();
+            }
+            Expr::If(condition, then_branch, else_branch, _) => {
+                if let Expr::Literal(Literal::Bool(value), span) = &**condition {
+                    self.report(
+                        "constant_condition",
+                        format!("`if` condition is always {}", value),
+                        *span,
+                    );
+                }
+                self.expr(condition);
+                self.expr(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            Expr::Propagate(operand, _) | Expr::TupleIndex(operand, _, _) => self.expr(operand),
+            Expr::Tuple(items, _) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            Expr::Try(body, name, handler, span) => {
+                self.expr(body);
+                self.scopes.push(Vec::new());
+                self.declare(name, BindingKind::Let, *span);
+                self.expr(handler);
+                self.pop_scope();
+            }
+        }
+    }
+}
+
+// Collects the names a function body declares and reads, without looking
+// into nested functions, which are scanned on their own.
+fn scan_expr(expr: &Expr, declared: &mut HashSet<String>, reads: &mut Vec<String>) {
+    match expr {
+        Expr::Literal(..) => {}
+        Expr::Variable(name, _) | Expr::Call(name, _, _) | Expr::MethodCall(name, _, _) => {
+            reads.push(name.clone());
+            if let Expr::Call(_, args, _) | Expr::MethodCall(_, args, _) = expr {
+                for arg in args {
+                    scan_expr(arg, declared, reads);
+                }
+            }
+        }
+        Expr::Unary(_, operand, _)
+        | Expr::Propagate(operand, _)
+        | Expr::TupleIndex(operand, _, _) => scan_expr(operand, declared, reads),
+        Expr::Tuple(items, _) => {
+            for item in items {
+                scan_expr(item, declared, reads);
+            }
+        }
+        Expr::Try(body, name, handler, _) => {
+            scan_expr(body, declared, reads);
+            declared.insert(name.clone());
+            scan_expr(handler, declared, reads);
+        }
+        Expr::Binary(lhs, _, rhs) => {
+            scan_expr(lhs, declared, reads);
+            scan_expr(rhs, declared, reads);
+        }
+        Expr::Block(stmts, tail, _) => {
+            for stmt in stmts {
+                match stmt {
+                    Stmt::Let(_, _, value, ..) | Stmt::LetTuple(_, _, value, _) => {
+                        declared.extend(stmt.declared_names().into_iter().cloned());
+                        scan_expr(value, declared, reads);
+                    }
+                    Stmt::Fn(name, ..) => {
+                        declared.insert(name.clone());
+                    }
+                    Stmt::Assign(_, value, _)
+                    | Stmt::Expression(value)
+                    | Stmt::ImplicitReturn(value)
+                    | Stmt::Yield(value, _) => scan_expr(value, declared, reads),
+                    Stmt::While(condition, body, _) => {
+                        scan_expr(condition, declared, reads);
+                        scan_expr(body, declared, reads);
+                    }
+                    Stmt::For(pattern, iterable, body, _) => {
+                        declared.extend(pattern.names().into_iter().cloned());
+                        scan_expr(iterable, declared, reads);
+                        scan_expr(body, declared, reads);
+                    }
+                    Stmt::Import(..) => {}
+                }
+            }
+            if let Some(tail) = tail {
+                scan_expr(tail, declared, reads);
+            }
+        }
+        Expr::If(condition, then_branch, else_branch, _) => {
+            scan_expr(condition, declared, reads);
+            scan_expr(then_branch, declared, reads);
+            if let Some(else_branch) = else_branch {
+                scan_expr(else_branch, declared, reads);
+            }
+        }
+    }
+}
+
+// Calls `f` on every statement directly inside the blocks of `expr`.
+fn for_each_nested_stmt(expr: &Expr, f: &mut dyn FnMut(&Stmt)) {
+    match expr {
+        Expr::Literal(..) | Expr::Variable(..) => {}
+        Expr::Call(_, args, _) | Expr::MethodCall(_, args, _) | Expr::Tuple(args, _) => {
+            for arg in args {
+                for_each_nested_stmt(arg, f);
+            }
+        }
+        Expr::Unary(_, operand, _)
+        | Expr::Propagate(operand, _)
+        | Expr::TupleIndex(operand, _, _) => for_each_nested_stmt(operand, f),
+        Expr::Try(body, _, handler, _) => {
+            for_each_nested_stmt(body, f);
+            for_each_nested_stmt(handler, f);
+        }
+        Expr::Binary(lhs, _, rhs) => {
+            for_each_nested_stmt(lhs, f);
+            for_each_nested_stmt(rhs, f);
+        }
+        Expr::Block(stmts, tail, _) => {
+            for stmt in stmts {
+                f(stmt);
+            }
+            if let Some(tail) = tail {
+                for_each_nested_stmt(tail, f);
+            }
+        }
+        Expr::If(condition, then_branch, else_branch, _) => {
+            for_each_nested_stmt(condition, f);
+            for_each_nested_stmt(then_branch, f);
+            if let Some(else_branch) = else_branch {
+                for_each_nested_stmt(else_branch, f);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lints(source: &str) -> Vec<String> {
+        lint(source)
+            .unwrap()
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_unused() {
+        let source = "
+fn add(a, b, _c) { a }
+let x = 1;
+let y = 2;
+y = 3;
+let z = 0;
+z += 1;
+let used = 4;
+add(used, 0, 0)
+";
+        assert_eq!(
+            lints(source),
+            vec![
+                "2:1: warning[unused_parameter]: unused parameter `b`",
+                "3:1: warning[unused_variable]: unused variable `x`",
+                "4:1: warning[unused_assignment]: `y` is assigned but never read",
+                "6:1: warning[unused_assignment]: `z` is assigned but never read",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pub_bindings() {
+        let source = "
+pub let exported = 1;
+let private = 2;
+";
+        assert_eq!(
+            lints(source),
+            vec!["3:1: warning[unused_variable]: unused variable `private`"]
+        );
+    }
+
+    #[test]
+    fn test_dynamic_scope_reads() {
+        // `limit` is read by `check` through dynamic scoping.
+        let source = "
+fn check(n) { n < limit }
+fn run() { let limit = 10; check(5) }
+run()
+";
+        assert_eq!(lints(source), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_control_flow_lints() {
+        let source = "
+let n = 0;
+while true { n += 1; }
+while false { }
+if true { print(n); }
+";
+        assert_eq!(
+            lints(source),
+            vec![
+                "3:1: warning[infinite_loop]: `while true` loop never exits",
+                "4:7: warning[constant_condition]: loop condition is always false",
+                "5:4: warning[constant_condition]: `if` condition is always true",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shadowing_and_undefined_functions() {
+        let source = "
+let x = 1;
+fn f(x) { x }
+{ let x = 2; println(x, f(x), missing(x)) }
+";
+        assert_eq!(
+            lints(source),
+            vec![
+                "2:1: warning[unused_variable]: unused variable `x`",
+                "3:1: warning[shadowing]: `x` shadows an earlier binding from line 2",
+                "4:3: warning[shadowing]: `x` shadows an earlier binding from line 2",
+                "4:31: warning[undefined_function]: call to undefined function `missing`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loops_and_method_calls() {
+        let source = "
+fn evens(xs) {
+    for x in xs { yield x; }
+    for i in xs { }
+}
+evens(list(1)).missing();
+fn naturals() {
+    let n = 0;
+    while true { yield n; n += 1; }
+}
+naturals();
+";
+        assert_eq!(
+            lints(source),
+            vec![
+                "4:5: warning[unused_variable]: unused variable `i`",
+                "6:16: warning[undefined_function]: call to undefined function `missing`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_directives() {
+        let source = "
+// toy: allow(unused)
+// toy: deny(undefined_function)
+let x = 1;
+missing();
+while true { } // toy: allow(infinite_loop)
+// toy: allow(bogus)
+";
+        assert_eq!(
+            lints(source),
+            vec![
+                "5:1: error[undefined_function]: call to undefined function `missing`",
+                "7:1: warning[unknown_lint]: unknown lint `bogus`",
+            ]
+        );
+    }
+}
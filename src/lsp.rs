@@ -0,0 +1,1071 @@
+use crate::ast::{Expr, Pattern, Stmt, signature, tuple};
+use crate::formatter::format_source;
+use crate::json::Json;
+use crate::lexer::{Lexer, Span, Token, tokenize};
+use crate::lint::{self, Level};
+use crate::parser::Parser;
+use crate::prelude;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+// JSON-RPC error code for requests the server does not implement.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Runs a language server over the given streams until the client sends
+/// `exit` or closes the input.
+pub fn serve(mut input: impl BufRead, output: impl Write) -> io::Result<()> {
+    let mut server = Server {
+        documents: HashMap::new(),
+        output,
+    };
+    while let Some(message) = read_message(&mut input)? {
+        let Ok(message) = Json::parse(&message) else {
+            continue;
+        };
+        if !server.handle(&message)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Reads one `Content-Length` framed message, or `None` at end of input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing Content-Length header",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+struct Server<W> {
+    documents: HashMap<String, String>,
+    output: W,
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+
+    /// Handles one message. Returns `false` once the client asks to exit.
+    fn handle(&mut self, message: &Json) -> io::Result<bool> {
+        let Some(method) = message.get("method").and_then(Json::as_str) else {
+            // A response to a request we never send.
+            return Ok(true);
+        };
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let Some(id) = message.get("id") else {
+            return self.notification(method, params);
+        };
+        let response = match self.request(method, params) {
+            Some(result) => Json::object(vec![
+                ("jsonrpc", Json::str("2.0")),
+                ("id", id.clone()),
+                ("result", result),
+            ]),
+            None => Json::object(vec![
+                ("jsonrpc", Json::str("2.0")),
+                ("id", id.clone()),
+                (
+                    "error",
+                    Json::object(vec![
+                        ("code", Json::Int(METHOD_NOT_FOUND)),
+                        ("message", Json::str(format!("Unknown method: {}", method))),
+                    ]),
+                ),
+            ]),
+        };
+        self.send(response)?;
+        Ok(true)
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> io::Result<bool> {
+        let uri = document_uri(params).map(str::to_string);
+        match (method, uri) {
+            ("exit", _) => return Ok(false),
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params
+                    .get("textDocument")
+                    .and_then(|doc| doc.get("text"))
+                    .and_then(Json::as_str)
+                    .unwrap_or_default();
+                self.documents.insert(uri.clone(), text.to_string());
+                self.publish_diagnostics(&uri)?;
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                // Only full-document sync is advertised, so the last change
+                // holds the whole text.
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str);
+                if let Some(text) = text {
+                    self.documents.insert(uri.clone(), text.to_string());
+                    self.publish_diagnostics(&uri)?;
+                }
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(&uri);
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn request(&self, method: &str, params: &Json) -> Option<Json> {
+        let source = document_uri(params)
+            .and_then(|uri| self.documents.get(uri))
+            .map(String::as_str)
+            .unwrap_or_default();
+        // Malformed positions, such as negative lines, find nothing.
+        let coordinate = |position: &Json, key| {
+            usize::try_from(position.get(key)?.as_i64()?)
+                .ok()?
+                .checked_add(1)
+        };
+        let position = params.get("position").and_then(|position| {
+            Some((
+                coordinate(position, "line")?,
+                coordinate(position, "character")?,
+            ))
+        });
+        let uri = document_uri(params).unwrap_or_default();
+        let result = match method {
+            "initialize" => capabilities(),
+            "shutdown" => Json::Null,
+            "textDocument/definition" => {
+                let index = Index::build(source);
+                position
+                    .and_then(|position| index.symbol_at(position))
+                    .map_or(Json::Null, |symbol| {
+                        let symbol = &index.symbols[symbol];
+                        location(uri, symbol.span, &symbol.name)
+                    })
+            }
+            "textDocument/references" => {
+                let index = Index::build(source);
+                let include_declaration = params
+                    .get("context")
+                    .and_then(|context| context.get("includeDeclaration"))
+                    .and_then(Json::as_bool)
+                    .unwrap_or(true);
+                let locations = position
+                    .and_then(|position| index.symbol_at(position))
+                    .map(|symbol| {
+                        let name = &index.symbols[symbol].name;
+                        index
+                            .references
+                            .iter()
+                            .filter(|(span, target)| {
+                                *target == symbol
+                                    && (include_declaration || *span != index.symbols[symbol].span)
+                            })
+                            .map(|(span, _)| location(uri, *span, name))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Json::Array(locations)
+            }
+            "textDocument/hover" => position
+                .and_then(|position| Index::build(source).hover(position))
+                .map_or(Json::Null, |text| {
+                    Json::object(vec![(
+                        "contents",
+                        Json::object(vec![
+                            ("kind", Json::str("markdown")),
+                            ("value", Json::str(text)),
+                        ]),
+                    )])
+                }),
+            "textDocument/documentSymbol" => Index::build(source).document_symbols(None),
+            "textDocument/completion" => completions(&Index::build(source)),
+            "textDocument/formatting" => match format_source(source) {
+                Ok(formatted) if formatted != source => {
+                    let end = Span {
+                        line: source.lines().count() + 1,
+                        col: 1,
+                    };
+                    Json::Array(vec![Json::object(vec![
+                        ("range", range(Span { line: 1, col: 1 }, end)),
+                        ("newText", Json::str(formatted)),
+                    ])])
+                }
+                _ => Json::Array(Vec::new()),
+            },
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> io::Result<()> {
+        let source = &self.documents[uri];
+        let diagnostics = match lint::lint(source) {
+            Ok(diagnostics) => diagnostics
+                .into_iter()
+                .map(|diagnostic| {
+                    let severity = match diagnostic.level {
+                        Level::Deny => 1,
+                        _ => 2,
+                    };
+                    diagnostic_json(
+                        source,
+                        diagnostic.span,
+                        severity,
+                        Some(diagnostic.lint),
+                        diagnostic.message,
+                    )
+                })
+                .collect(),
+            Err(err) => vec![diagnostic_json(source, err.span, 1, None, err.message)],
+        };
+        self.send(Json::object(vec![
+            ("jsonrpc", Json::str("2.0")),
+            ("method", Json::str("textDocument/publishDiagnostics")),
+            (
+                "params",
+                Json::object(vec![
+                    ("uri", Json::str(uri)),
+                    ("diagnostics", Json::Array(diagnostics)),
+                ]),
+            ),
+        ]))
+    }
+}
+
+fn document_uri(params: &Json) -> Option<&str> {
+    params.get("textDocument")?.get("uri")?.as_str()
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        (
+            "capabilities",
+            Json::object(vec![
+                // 1 means the client always sends the full document.
+                ("textDocumentSync", Json::Int(1)),
+                ("definitionProvider", Json::Bool(true)),
+                ("referencesProvider", Json::Bool(true)),
+                ("hoverProvider", Json::Bool(true)),
+                ("documentSymbolProvider", Json::Bool(true)),
+                ("completionProvider", Json::object(Vec::new())),
+                ("documentFormattingProvider", Json::Bool(true)),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object(vec![
+                ("name", Json::str("toy-rs")),
+                ("version", Json::str(env!("CARGO_PKG_VERSION"))),
+            ]),
+        ),
+    ])
+}
+
+fn completions(index: &Index) -> Json {
+    // Completion item kinds from the LSP specification.
+    const FUNCTION: i64 = 3;
+    const VARIABLE: i64 = 6;
+    let mut seen = std::collections::HashSet::new();
+    let mut items = Vec::new();
+    for symbol in &index.symbols {
+        if symbol.kind == SymbolKind::Parameter || !seen.insert(symbol.name.clone()) {
+            continue;
+        }
+        let (kind, detail) = match symbol.kind {
+            SymbolKind::Function => (FUNCTION, symbol.detail.clone()),
+            _ => (VARIABLE, symbol.detail.clone()),
+        };
+        items.push(Json::object(vec![
+            ("label", Json::str(symbol.name.as_str())),
+            ("kind", Json::Int(kind)),
+            ("detail", Json::str(detail)),
+        ]));
+    }
+    for name in prelude::globals().names() {
+        if seen.insert(name.clone()) {
+            items.push(Json::object(vec![
+                ("label", Json::str(name)),
+                ("kind", Json::Int(FUNCTION)),
+                ("detail", Json::str("builtin")),
+            ]));
+        }
+    }
+    Json::Array(items)
+}
+
+fn position(span: Span) -> Json {
+    Json::object(vec![
+        ("line", Json::Int(span.line as i64 - 1)),
+        ("character", Json::Int(span.col as i64 - 1)),
+    ])
+}
+
+fn range(start: Span, end: Span) -> Json {
+    Json::object(vec![("start", position(start)), ("end", position(end))])
+}
+
+// The span just past a name that starts at `span`.
+fn name_end(span: Span, name: &str) -> Span {
+    Span {
+        line: span.line,
+        col: span.col + name.chars().count(),
+    }
+}
+
+fn location(uri: &str, span: Span, name: &str) -> Json {
+    Json::object(vec![
+        ("uri", Json::str(uri)),
+        ("range", range(span, name_end(span, name))),
+    ])
+}
+
+fn diagnostic_json(
+    source: &str,
+    span: Span,
+    severity: i64,
+    code: Option<&str>,
+    message: String,
+) -> Json {
+    // Highlight the word at the reported position, or a single character.
+    let word_len = source
+        .lines()
+        .nth(span.line.saturating_sub(1))
+        .map(|line| {
+            line.chars()
+                .skip(span.col.saturating_sub(1))
+                .take_while(|ch| ch.is_alphanumeric() || *ch == '_')
+                .count()
+        })
+        .unwrap_or(0)
+        .max(1);
+    let end = Span {
+        line: span.line,
+        col: span.col + word_len,
+    };
+    let mut fields = vec![
+        ("range", range(span, end)),
+        ("severity", Json::Int(severity)),
+        ("source", Json::str("toy-rs")),
+        ("message", Json::str(message)),
+    ];
+    if let Some(code) = code {
+        fields.push(("code", Json::str(code)));
+    }
+    Json::object(fields)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SymbolKind {
+    Function,
+    Variable,
+    Parameter,
+}
+
+struct Symbol {
+    name: String,
+    kind: SymbolKind,
+    // The span of the name itself, not of the `let` or `fn` keyword.
+    span: Span,
+    // From the keyword to the end of the declaration.
+    range: (Span, Span),
+    // The declaration as written, e.g. `fn add(a: int, b) -> int`.
+    detail: String,
+    doc: Option<String>,
+    // The function the symbol is declared in, if any.
+    parent: Option<usize>,
+}
+
+/// The declarations of a document and every name that refers to them.
+#[derive(Default)]
+struct Index {
+    symbols: Vec<Symbol>,
+    // Every occurrence of a symbol's name, declarations included.
+    references: Vec<(Span, usize)>,
+    // Calls that resolve to no declaration, such as builtins.
+    unresolved_calls: Vec<(Span, String)>,
+}
+
+// Resolution state while walking the AST.
+struct Resolver<'a> {
+    index: Index,
+    tokens: &'a [(Token, Span)],
+    scopes: Vec<Vec<(String, usize)>>,
+    current_fn: Option<usize>,
+    // Names used before any declaration was in scope, resolved at the end
+    // against functions declared anywhere, since calls may precede them.
+    deferred: Vec<(Span, String, bool)>,
+}
+
+impl Index {
+    /// Indexes a document. A document that does not parse has no symbols.
+    fn build(source: &str) -> Index {
+        let program = Parser::new(Lexer::new(source)).and_then(|mut p| p.parse_program());
+        let (Ok(program), Ok(tokens)) = (program, tokenize(source)) else {
+            return Index::default();
+        };
+        let mut resolver = Resolver {
+            index: Index::default(),
+            tokens: &tokens,
+            scopes: vec![Vec::new()],
+            current_fn: None,
+            deferred: Vec::new(),
+        };
+        for stmt in &program {
+            resolver.stmt(stmt);
+        }
+        resolver.finish()
+    }
+
+    fn symbol_at(&self, (line, col): (usize, usize)) -> Option<usize> {
+        self.references
+            .iter()
+            .find(|(span, symbol)| {
+                let end = name_end(*span, &self.symbols[*symbol].name);
+                span.line == line && span.col <= col && col < end.col
+            })
+            .map(|(_, symbol)| *symbol)
+    }
+
+    fn hover(&self, position: (usize, usize)) -> Option<String> {
+        if let Some(symbol) = self.symbol_at(position) {
+            let symbol = &self.symbols[symbol];
+            let mut text = format!("```toy\n{}\n```", symbol.detail);
+            if let Some(doc) = &symbol.doc {
+                text.push_str("\n\n");
+                text.push_str(doc);
+            }
+            return Some(text);
+        }
+        let (line, col) = position;
+        self.unresolved_calls
+            .iter()
+            .find(|(span, name)| {
+                span.line == line && span.col <= col && col < name_end(*span, name).col
+            })
+            .filter(|(_, name)| prelude::globals().get(name).is_some())
+            .map(|(_, name)| format!("```toy\n{}\n```\n\nBuiltin function.", name))
+    }
+
+    // Returns the `let` and `fn` symbols declared directly in `parent`, with
+    // the ones declared inside functions nested below them.
+    fn document_symbols(&self, parent: Option<usize>) -> Json {
+        // Symbol kinds from the LSP specification.
+        const FUNCTION: i64 = 12;
+        const VARIABLE: i64 = 13;
+        let symbols = self
+            .symbols
+            .iter()
+            .enumerate()
+            .filter(|(_, symbol)| symbol.parent == parent && symbol.kind != SymbolKind::Parameter)
+            .map(|(i, symbol)| {
+                let kind = match symbol.kind {
+                    SymbolKind::Function => FUNCTION,
+                    _ => VARIABLE,
+                };
+                Json::object(vec![
+                    ("name", Json::str(symbol.name.as_str())),
+                    ("detail", Json::str(symbol.detail.as_str())),
+                    ("kind", Json::Int(kind)),
+                    ("range", range(symbol.range.0, symbol.range.1)),
+                    (
+                        "selectionRange",
+                        range(symbol.span, name_end(symbol.span, &symbol.name)),
+                    ),
+                    ("children", self.document_symbols(Some(i))),
+                ])
+            })
+            .collect();
+        Json::Array(symbols)
+    }
+}
+
+impl Resolver<'_> {
+    fn finish(mut self) -> Index {
+        for (span, name, is_call) in std::mem::take(&mut self.deferred) {
+            let target = self
+                .index
+                .symbols
+                .iter()
+                .position(|s| s.name == name && s.kind == SymbolKind::Function)
+                .or_else(|| self.index.symbols.iter().position(|s| s.name == name));
+            match target {
+                Some(symbol) => self.index.references.push((span, symbol)),
+                None if is_call => self.index.unresolved_calls.push((span, name)),
+                None => {}
+            }
+        }
+        // `x += 1` reuses the span of `x` for the implicit read.
+        self.index.references.sort_by_key(|(span, _)| *span);
+        self.index.references.dedup();
+        self.index
+    }
+
+    fn token_index(&self, span: Span) -> Option<usize> {
+        self.tokens.iter().position(|(_, s)| *s == span)
+    }
+
+    // The span of the `}` that closes the block opened at `open`.
+    fn block_end(&self, open: Span) -> Option<Span> {
+        let start = self.token_index(open)?;
+        let mut depth = 0;
+        for (token, span) in &self.tokens[start..] {
+            match token {
+                Token::LBrace => depth += 1,
+                Token::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(name_end(*span, "}"));
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // Parameters start directly after the `(` at `open` or a `,` at the same
+    // depth; other tokens there belong to types. Returns their token indexes.
+    fn param_starts(&self, open: usize) -> Vec<usize> {
+        let mut starts = Vec::new();
+        let mut depth = 0;
+        for (i, (token, _)) in self.tokens.iter().enumerate().skip(open) {
+            if depth == 1
+                && matches!(token, Token::Identifier(_) | Token::LParen)
+                && matches!(self.tokens[i - 1].0, Token::LParen | Token::Comma)
+            {
+                starts.push(i);
+            }
+            match token {
+                Token::LParen => depth += 1,
+                Token::RParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        starts
+    }
+
+    // Declares the names of the pattern that starts at token `at`, such as
+    // `x` or `(q, r)`.
+    fn declare_pattern(&mut self, pattern: &Pattern, at: usize, kind: SymbolKind, detail: String) {
+        let mut spans = Vec::new();
+        let mut depth = 0;
+        for (token, span) in &self.tokens[at..] {
+            match token {
+                Token::Identifier(_) => spans.push(*span),
+                Token::LParen => depth += 1,
+                Token::RParen => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+        }
+        let parent = self.current_fn;
+        for (name, span) in pattern.names().into_iter().zip(spans) {
+            self.declare(Symbol {
+                name: name.clone(),
+                kind,
+                span,
+                range: (span, name_end(span, name)),
+                detail: detail.clone(),
+                doc: None,
+                parent,
+            });
+        }
+    }
+
+    fn declare(&mut self, symbol: Symbol) -> usize {
+        let id = self.index.symbols.len();
+        self.index.references.push((symbol.span, id));
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .push((symbol.name.clone(), id));
+        self.index.symbols.push(symbol);
+        id
+    }
+
+    fn resolve(&mut self, name: &str, span: Span, is_call: bool) {
+        let found = self
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(bound, _)| bound == name)
+            .map(|(_, id)| *id);
+        match found {
+            Some(id) => self.index.references.push((span, id)),
+            None => self.deferred.push((span, name.to_string(), is_call)),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let(name, ty, value, doc, vis, span) => {
+                self.expr(value);
+                let Some(at) = self.token_index(*span) else {
+                    return;
+                };
+                let name_span = self.tokens[at + 1].1;
+                let detail = match ty {
+                    Some(ty) => format!("{}let {}: {}", vis.prefix(), name, ty),
+                    None => format!("{}let {}", vis.prefix(), name),
+                };
+                self.declare(Symbol {
+                    name: name.clone(),
+                    kind: SymbolKind::Variable,
+                    span: name_span,
+                    range: (*span, name_end(name_span, name)),
+                    detail,
+                    doc: doc.clone(),
+                    parent: self.current_fn,
+                });
+            }
+            Stmt::LetTuple(patterns, ty, value, span) => {
+                self.expr(value);
+                let Some(at) = self.token_index(*span) else {
+                    return;
+                };
+                let detail = match ty {
+                    Some(ty) => format!("let {}: {}", tuple(patterns), ty),
+                    None => format!("let {}", tuple(patterns)),
+                };
+                let pattern = Pattern::Tuple(patterns.clone());
+                self.declare_pattern(&pattern, at + 1, SymbolKind::Variable, detail);
+            }
+            Stmt::Fn(name, params, ret, body, doc, vis, span) => {
+                let Some(at) = self.token_index(*span) else {
+                    return;
+                };
+                let name_span = self.tokens[at + 1].1;
+                let end = self
+                    .block_end(body.span())
+                    .unwrap_or(name_end(name_span, name));
+                let id = self.declare(Symbol {
+                    name: name.clone(),
+                    kind: SymbolKind::Function,
+                    span: name_span,
+                    range: (*span, end),
+                    detail: format!(
+                        "{}fn {}",
+                        vis.prefix(),
+                        signature(name, params, ret.as_ref())
+                    ),
+                    doc: doc.clone(),
+                    parent: self.current_fn,
+                });
+                let param_starts = self.param_starts(at + 2);
+                let outer_fn = self.current_fn.replace(id);
+                self.scopes.push(Vec::new());
+                for (param, start) in params.iter().zip(param_starts) {
+                    let detail = format!("parameter {}", param);
+                    self.declare_pattern(&param.pattern, start, SymbolKind::Parameter, detail);
+                }
+                self.expr(body);
+                self.scopes.pop();
+                self.current_fn = outer_fn;
+            }
+            Stmt::Assign(name, value, span) => {
+                self.resolve(name, *span, false);
+                self.expr(value);
+            }
+            Stmt::While(condition, body, _) => {
+                self.expr(condition);
+                self.expr(body);
+            }
+            Stmt::For(pattern, iterable, body, span) => {
+                self.expr(iterable);
+                self.scopes.push(Vec::new());
+                if let Some(at) = self.token_index(*span) {
+                    let detail = format!("for {}", pattern);
+                    self.declare_pattern(pattern, at + 1, SymbolKind::Variable, detail);
+                }
+                self.expr(body);
+                self.scopes.pop();
+            }
+            Stmt::Expression(expr) | Stmt::ImplicitReturn(expr) | Stmt::Yield(expr, _) => {
+                self.expr(expr)
+            }
+            Stmt::Import(import, span) => {
+                let Some(at) = self.token_index(*span) else {
+                    return;
+                };
+                // The names between the braces of `import module::{a, b};`.
+                let names: Vec<(String, Span)> = self.tokens[at..]
+                    .iter()
+                    .skip_while(|(token, _)| *token != Token::LBrace)
+                    .take_while(|(token, _)| *token != Token::RBrace)
+                    .filter_map(|(token, span)| match token {
+                        Token::Identifier(name) => Some((name.clone(), *span)),
+                        _ => None,
+                    })
+                    .collect();
+                for (name, name_span) in names {
+                    self.declare(Symbol {
+                        range: (name_span, name_end(name_span, &name)),
+                        name,
+                        kind: SymbolKind::Function,
+                        span: name_span,
+                        detail: import.to_string(),
+                        doc: None,
+                        parent: self.current_fn,
+                    });
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(..) => {}
+            Expr::Variable(name, span) => self.resolve(name, *span, false),
+            Expr::Unary(_, operand, _) => self.expr(operand),
+            Expr::Binary(lhs, _, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Call(name, args, span) | Expr::MethodCall(name, args, span) => {
+                self.resolve(name, *span, true);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Block(stmts, tail, _) => {
+                self.scopes.push(Vec::new());
+                for stmt in stmts {
+                    self.stmt(stmt);
+                }
+                if let Some(tail) = tail {
+                    self.expr(tail);
+                }
+                self.scopes.pop();
+            }
+            Expr::If(condition, then_branch, else_branch, _) => {
+                self.expr(condition);
+                self.expr(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            Expr::Propagate(operand, _) | Expr::TupleIndex(operand, _, _) => self.expr(operand),
+            Expr::Tuple(items, _) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            Expr::Try(body, name, handler, _) => {
+                self.expr(body);
+                self.scopes.push(Vec::new());
+                // The caught name is the token just before the handler's `{`.
+                let name_at = self
+                    .token_index(handler.span())
+                    .and_then(|at| at.checked_sub(1));
+                if let Some((Token::Identifier(_), name_span)) = name_at.map(|at| &self.tokens[at])
+                {
+                    let name_span = *name_span;
+                    self.declare(Symbol {
+                        name: name.clone(),
+                        kind: SymbolKind::Variable,
+                        span: name_span,
+                        range: (name_span, name_end(name_span, name)),
+                        detail: format!("catch {}", name),
+                        doc: None,
+                        parent: self.current_fn,
+                    });
+                }
+                self.expr(handler);
+                self.scopes.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const URI: &str = "file:///test.toy";
+
+    // Plays a scripted client session and returns every message the server
+    // sent, in order.
+    fn session(messages: &[Json]) -> Vec<Json> {
+        let mut input = String::new();
+        for message in messages {
+            let body = message.to_string();
+            input.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+        }
+        let mut output = Vec::new();
+        serve(Cursor::new(input), &mut output).unwrap();
+        let mut reader = Cursor::new(output);
+        let mut replies = Vec::new();
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            replies.push(Json::parse(&message).unwrap());
+        }
+        replies
+    }
+
+    fn request(id: i64, method: &str, params: Json) -> Json {
+        Json::object(vec![
+            ("jsonrpc", Json::str("2.0")),
+            ("id", Json::Int(id)),
+            ("method", Json::str(method)),
+            ("params", params),
+        ])
+    }
+
+    fn notification(method: &str, params: Json) -> Json {
+        Json::object(vec![
+            ("jsonrpc", Json::str("2.0")),
+            ("method", Json::str(method)),
+            ("params", params),
+        ])
+    }
+
+    fn open(text: &str) -> Json {
+        notification(
+            "textDocument/didOpen",
+            Json::object(vec![(
+                "textDocument",
+                Json::object(vec![
+                    ("uri", Json::str(URI)),
+                    ("languageId", Json::str("toy")),
+                    ("version", Json::Int(1)),
+                    ("text", Json::str(text)),
+                ]),
+            )]),
+        )
+    }
+
+    fn at(line: i64, character: i64) -> Json {
+        Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::str(URI))])),
+            (
+                "position",
+                Json::object(vec![
+                    ("line", Json::Int(line)),
+                    ("character", Json::Int(character)),
+                ]),
+            ),
+        ])
+    }
+
+    fn result(replies: &[Json], id: i64) -> &Json {
+        replies
+            .iter()
+            .find(|reply| reply.get("id") == Some(&Json::Int(id)))
+            .and_then(|reply| reply.get("result"))
+            .unwrap_or_else(|| panic!("no result for request {}", id))
+    }
+
+    const SOURCE: &str = "/// Adds two numbers.
+fn add(a, b) {
+    a + b
+}
+let total = add(1, 2);
+println(total);
+";
+
+    #[test]
+    fn test_initialize_and_shutdown() {
+        let replies = session(&[
+            request(1, "initialize", Json::object(Vec::new())),
+            notification("initialized", Json::object(Vec::new())),
+            request(2, "workspace/symbol", Json::object(Vec::new())),
+            request(3, "shutdown", Json::Null),
+            notification("exit", Json::Null),
+            // Never answered, because the server has exited.
+            request(4, "shutdown", Json::Null),
+        ]);
+        assert_eq!(replies.len(), 3);
+        let capabilities = result(&replies, 1).get("capabilities").unwrap();
+        assert_eq!(capabilities.get("hoverProvider"), Some(&Json::Bool(true)));
+        assert_eq!(
+            replies[1].get("error").and_then(|error| error.get("code")),
+            Some(&Json::Int(METHOD_NOT_FOUND))
+        );
+        assert_eq!(result(&replies, 3), &Json::Null);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let replies = session(&[open("let x = 1;\nlet y = 0b12;")]);
+        let diagnostics = replies[0]
+            .get("params")
+            .unwrap()
+            .get("diagnostics")
+            .unwrap();
+        assert_eq!(
+            diagnostics.to_string(),
+            r#"[{"range":{"start":{"line":1,"character":8},"end":{"line":1,"character":12}},"severity":1,"source":"toy-rs","message":"Invalid digit '2' in binary literal"}]"#
+        );
+
+        let replies = session(&[open("let unused = 1;")]);
+        let diagnostics = replies[0]
+            .get("params")
+            .unwrap()
+            .get("diagnostics")
+            .unwrap();
+        assert_eq!(
+            diagnostics.to_string(),
+            r#"[{"range":{"start":{"line":0,"character":0},"end":{"line":0,"character":3}},"severity":2,"source":"toy-rs","message":"unused variable `unused`","code":"unused_variable"}]"#
+        );
+    }
+
+    #[test]
+    fn test_definition_and_references() {
+        let mut references = at(4, 13);
+        if let Json::Object(fields) = &mut references {
+            fields.push((
+                "context".to_string(),
+                Json::object(vec![("includeDeclaration", Json::Bool(false))]),
+            ));
+        }
+        let replies = session(&[
+            open(SOURCE),
+            request(1, "textDocument/definition", at(4, 13)),
+            request(2, "textDocument/definition", at(2, 4)),
+            request(3, "textDocument/references", at(1, 7)),
+            request(4, "textDocument/references", references),
+            request(5, "textDocument/definition", at(5, 2)),
+            request(6, "textDocument/definition", at(-1, 0)),
+            request(7, "textDocument/hover", at(0, i64::MAX)),
+        ]);
+        assert_eq!(
+            result(&replies, 1).to_string(),
+            r#"{"uri":"file:///test.toy","range":{"start":{"line":1,"character":3},"end":{"line":1,"character":6}}}"#
+        );
+        assert_eq!(
+            result(&replies, 2).get("range").unwrap().to_string(),
+            r#"{"start":{"line":1,"character":7},"end":{"line":1,"character":8}}"#
+        );
+        let lines = |reply: &Json| -> Vec<i64> {
+            reply
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|location| {
+                    let start = location.get("range").unwrap().get("start").unwrap();
+                    start.get("line").unwrap().as_i64().unwrap()
+                })
+                .collect()
+        };
+        assert_eq!(lines(result(&replies, 3)), vec![1, 2]);
+        assert_eq!(lines(result(&replies, 4)), vec![4]);
+        assert_eq!(result(&replies, 5), &Json::Null);
+        assert_eq!(result(&replies, 6), &Json::Null);
+        assert_eq!(result(&replies, 7), &Json::Null);
+    }
+
+    #[test]
+    fn test_hover() {
+        let replies = session(&[
+            open(SOURCE),
+            request(1, "textDocument/hover", at(4, 14)),
+            request(2, "textDocument/hover", at(5, 3)),
+            request(3, "textDocument/hover", at(2, 4)),
+        ]);
+        let value = |id| {
+            result(&replies, id)
+                .get("contents")
+                .and_then(|contents| contents.get("value"))
+                .and_then(Json::as_str)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(value(1), "```toy\nfn add(a, b)\n```\n\nAdds two numbers.");
+        assert_eq!(value(2), "```toy\nprintln\n```\n\nBuiltin function.");
+        assert_eq!(value(3), "```toy\nparameter a\n```");
+    }
+
+    #[test]
+    fn test_document_symbols_and_completion() {
+        let source = "fn outer(n) {\n    let inner = n;\n    inner\n}\nlet x = 1;\n";
+        let replies = session(&[
+            open(source),
+            request(1, "textDocument/documentSymbol", at(0, 0)),
+            request(2, "textDocument/completion", at(0, 0)),
+        ]);
+        let symbols = result(&replies, 1).as_array().unwrap();
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(
+            symbols[0].get("range").unwrap().to_string(),
+            r#"{"start":{"line":0,"character":0},"end":{"line":3,"character":1}}"#
+        );
+        let children = symbols[0].get("children").and_then(Json::as_array).unwrap();
+        assert_eq!(children[0].get("name"), Some(&Json::str("inner")));
+        assert_eq!(symbols[1].get("detail"), Some(&Json::str("let x")));
+
+        let labels: Vec<&str> = result(&replies, 2)
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|item| item.get("label").and_then(Json::as_str))
+            .collect();
+        assert_eq!(&labels[..3], ["outer", "inner", "x"]);
+        assert!(labels.contains(&"println"));
+        assert!(labels.contains(&"wrapping_add"));
+    }
+
+    #[test]
+    fn test_formatting_after_change() {
+        let change = notification(
+            "textDocument/didChange",
+            Json::object(vec![
+                ("textDocument", Json::object(vec![("uri", Json::str(URI))])),
+                (
+                    "contentChanges",
+                    Json::Array(vec![Json::object(vec![("text", Json::str("let x=1;x"))])]),
+                ),
+            ]),
+        );
+        let replies = session(&[
+            open("let x = 1;\nx\n"),
+            request(1, "textDocument/formatting", at(0, 0)),
+            change,
+            request(2, "textDocument/formatting", at(0, 0)),
+        ]);
+        assert_eq!(result(&replies, 1), &Json::Array(Vec::new()));
+        assert_eq!(
+            result(&replies, 2).to_string(),
+            r#"[{"range":{"start":{"line":0,"character":0},"end":{"line":1,"character":0}},"newText":"let x = 1;\nx\n"}]"#
+        );
+    }
+}
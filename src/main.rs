@@ -1,29 +1,446 @@
 mod ast;
+mod bigint;
+mod checker;
+mod collections;
+mod dump;
+mod files;
+mod formatter;
 mod interpreter;
+mod iterators;
+mod json;
 mod lexer;
+mod lint;
+mod lsp;
+mod modules;
+mod optimizer;
 mod parser;
+mod permissions;
+mod prelude;
+mod system;
 
-use interpreter::{Environment, Value, eval_statement};
+use interpreter::{Environment, RuntimeError, Value, eval_statement};
+use permissions::{Capability, Permissions};
 use std::env;
 use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage: toy-rs [-O] [<limits>] [<permissions>] <filename.toy> [<args>...]
+       toy-rs [<options>] - [<args>...]              (program from standard input)
+       toy-rs [<options>] -e <program> [<args>...]
+       toy-rs [--dump-tokens | --dump-ast] [--json] <filename.toy>
+       toy-rs check <filename.toy>
+       toy-rs fmt [--check] <filename.toy>...
+       toy-rs lint <filename.toy>...
+       toy-rs lsp
+
+Options:
+  -O                    optimise the script before running it
+  --no-prelude          do not define the helpers of the prelude
+
+Limits:
+  --max-call-depth=<n>  nested calls allowed at once (default 1000, at most 10000)
+  --max-steps=<n>       expressions evaluated in total
+  --max-size=<n>        bytes in any string, items in any list or tuple
+  --timeout=<seconds>   wall-clock time the script may run
+
+Permissions (all denied by default):
+  --allow-read[=<dir>]  read files, optionally only inside <dir>
+  --allow-write[=<dir>] write files, optionally only inside <dir>
+  --allow-env           read and set environment variables
+  --allow-run           start other programs
+  --allow-clock         read the current time
+  --allow-all           all of the above";
+
+/// Native stack reserved per nested script call. Each call recurses through
+/// several `eval_*` frames, which are large in debug builds.
+const STACK_PER_CALL: usize = 256 * 1024;
+
+/// The largest `--max-call-depth`. A stack for this many calls is about
+/// 2.5 GiB of address space, most of which is never touched.
+const MAX_CALL_DEPTH: usize = 10_000;
+
+/// Settings that affect how a script is executed.
+struct RunOptions {
+    optimize: bool,
+    /// Whether to define the helpers of the prelude before the script runs.
+    prelude: bool,
+    max_call_depth: usize,
+    max_steps: Option<u64>,
+    max_size: Option<usize>,
+    timeout: Option<Duration>,
+    permissions: Permissions,
+    /// The arguments given after the script.
+    args: Vec<String>,
+    /// The file the program was read from, which imports are relative to.
+    script: Option<PathBuf>,
+}
+
+enum Mode {
+    Run,
+    Check,
+    DumpTokens,
+    DumpAst,
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("Usage: toy-rs <filename.toy>");
+    let mut args = env::args().skip(1).peekable();
+    let mut mode = Mode::Run;
+    if args.peek().map(String::as_str) == Some("fmt") {
+        args.next();
+        format_files(args.collect());
+        return;
+    }
+    if args.peek().map(String::as_str) == Some("lsp") {
+        let stdin = std::io::stdin();
+        if let Err(err) = lsp::serve(stdin.lock(), std::io::stdout()) {
+            eprintln!("Language server error: {}", err);
+            process::exit(1);
+        }
         return;
     }
-    let filename = &args[1];
-    let code = fs::read_to_string(filename).expect("Could not read file");
-    let lexer = lexer::Lexer::new(&code);
-    let mut parser = parser::Parser::new(lexer);
-    let program = parser.parse_program();
-    let mut env = Environment::new();
+    if args.peek().map(String::as_str) == Some("lint") {
+        args.next();
+        lint_files(args.collect());
+        return;
+    }
+    if args.peek().map(String::as_str) == Some("check") {
+        args.next();
+        mode = Mode::Check;
+    }
+    let mut json = false;
+    let mut options = RunOptions {
+        optimize: false,
+        prelude: true,
+        max_call_depth: interpreter::DEFAULT_MAX_CALL_DEPTH,
+        max_steps: None,
+        max_size: None,
+        timeout: None,
+        permissions: Permissions::default(),
+        args: Vec::new(),
+        script: None,
+    };
+    let mut filename = None;
+    let mut inline = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-e" if filename.is_none() => {
+                let Some(code) = args.next() else {
+                    eprintln!("Missing program after -e\n{}", USAGE);
+                    process::exit(2);
+                };
+                inline = Some(code);
+                filename = Some("<eval>".to_string());
+                if matches!(mode, Mode::Run) {
+                    options.args = args.by_ref().collect();
+                }
+            }
+            "--dump-tokens" => mode = Mode::DumpTokens,
+            "--dump-ast" => mode = Mode::DumpAst,
+            "--json" => json = true,
+            "-O" => options.optimize = true,
+            "--no-prelude" => options.prelude = false,
+            _ if arg.starts_with("--max-call-depth=") => {
+                options.max_call_depth = parse_limit(&arg, "call depth");
+                if options.max_call_depth > MAX_CALL_DEPTH {
+                    eprintln!(
+                        "Invalid call depth: {} (at most {})\n{}",
+                        options.max_call_depth, MAX_CALL_DEPTH, USAGE
+                    );
+                    process::exit(2);
+                }
+            }
+            _ if arg.starts_with("--max-steps=") => {
+                options.max_steps = Some(parse_limit(&arg, "step limit"));
+            }
+            _ if arg.starts_with("--max-size=") => {
+                options.max_size = Some(parse_limit(&arg, "size limit"));
+            }
+            _ if arg.starts_with("--timeout=") => {
+                let seconds: f64 = parse_limit(&arg, "timeout");
+                match Duration::try_from_secs_f64(seconds) {
+                    Ok(timeout) => options.timeout = Some(timeout),
+                    Err(_) => {
+                        eprintln!("Invalid timeout: {}\n{}", seconds, USAGE);
+                        process::exit(2);
+                    }
+                }
+            }
+            "--allow-all" => options.permissions = Permissions::all(),
+            _ if arg.starts_with("--allow-") => {
+                let (name, dir) = match arg.split_once('=') {
+                    Some((name, dir)) => (name, Some(Path::new(dir))),
+                    None => (arg.as_str(), None),
+                };
+                let capability = match name {
+                    "--allow-read" => Capability::Read,
+                    "--allow-write" => Capability::Write,
+                    "--allow-env" => Capability::Env,
+                    "--allow-run" => Capability::Process,
+                    "--allow-clock" => Capability::Clock,
+                    _ => {
+                        eprintln!("Unknown option: {}\n{}", arg, USAGE);
+                        process::exit(2);
+                    }
+                };
+                if dir.is_some() && !matches!(capability, Capability::Read | Capability::Write) {
+                    eprintln!("{} does not take a directory\n{}", name, USAGE);
+                    process::exit(2);
+                }
+                options.permissions.allow(capability, dir);
+            }
+            _ if arg.starts_with("--") => {
+                eprintln!("Unknown option: {}\n{}", arg, USAGE);
+                process::exit(2);
+            }
+            _ if filename.is_none() => {
+                filename = Some(arg);
+                // Everything after the script is passed to it.
+                if matches!(mode, Mode::Run) {
+                    options.args = args.by_ref().collect();
+                }
+            }
+            _ => {
+                eprintln!("Unexpected argument: {}\n{}", arg, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+    let Some(filename) = filename else {
+        println!("{}", USAGE);
+        return;
+    };
+    let (filename, code) = match inline {
+        Some(code) => (filename, code),
+        None if filename == "-" => {
+            let mut code = String::new();
+            io::stdin()
+                .read_to_string(&mut code)
+                .expect("Could not read program from standard input");
+            ("<stdin>".to_string(), code)
+        }
+        None => {
+            let code = fs::read_to_string(&filename).expect("Could not read file");
+            options.script = Some(PathBuf::from(&filename));
+            (filename, code)
+        }
+    };
+    match mode {
+        Mode::Run => run(&filename, &code, options),
+        Mode::Check => check_types(&filename, &code),
+        Mode::DumpTokens => dump_tokens(&filename, &code, json),
+        Mode::DumpAst => dump_ast(&filename, &code, json, options.optimize),
+    }
+}
+
+/// Parses the value of a `--name=value` option, exiting on bad input.
+fn parse_limit<T: FromStr>(arg: &str, what: &str) -> T {
+    let value = &arg[arg.find('=').map_or(0, |i| i + 1)..];
+    match value.parse() {
+        Ok(limit) => limit,
+        Err(_) => {
+            eprintln!("Invalid {}: {}\n{}", what, value, USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+fn run(filename: &str, code: &str, options: RunOptions) {
+    let mut program = parse_or_exit(filename, code);
+    if options.optimize {
+        program = optimizer::optimize(program);
+    }
+    // Non-tail calls recurse on the native stack, so run the script on a
+    // thread with room for the deepest call chain it is allowed to build.
+    let stack_size = (options.max_call_depth + 64).saturating_mul(STACK_PER_CALL);
+    let max_call_depth = options.max_call_depth;
+    let filename = filename.to_string();
+    let interpreter = match thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || execute(&filename, program, options))
+    {
+        Ok(interpreter) => interpreter,
+        Err(err) => {
+            eprintln!(
+                "Cannot reserve a stack for {} nested calls: {}",
+                max_call_depth, err
+            );
+            process::exit(1);
+        }
+    };
+    if interpreter.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn execute(filename: &str, program: Vec<ast::Stmt>, options: RunOptions) {
+    let mut env = Environment::with_permissions(options.permissions);
+    env.set_args(options.args);
+    if let Some(script) = &options.script {
+        env.set_script_path(script);
+    }
+    env.set_max_call_depth(options.max_call_depth);
+    if let Some(steps) = options.max_steps {
+        env.set_max_steps(steps);
+    }
+    if let Some(bytes) = options.max_size {
+        env.set_max_size(bytes);
+    }
+    if let Some(timeout) = options.timeout {
+        env.set_deadline(Instant::now() + timeout);
+    }
+    if options.prelude
+        && let Err(err) = prelude::load(&mut env)
+    {
+        eprintln!("Runtime error in the prelude: {}", err);
+        process::exit(1);
+    }
     let mut last_value = Value::Unit;
     for stmt in program {
-        last_value = eval_statement(stmt, &mut env);
+        last_value = match eval_statement(stmt, &mut env) {
+            Ok(value) => value,
+            Err(err) => {
+                // `process::exit` skips flushing output printed without a newline.
+                let _ = io::stdout().flush();
+                if let RuntimeError::Exit(code) = err.cause() {
+                    process::exit(*code);
+                }
+                eprintln!("Runtime error: {}", err.cause());
+                eprint!("{}", interpreter::traceback(err.trace(), filename));
+                process::exit(1);
+            }
+        };
     }
     if last_value != Value::Unit {
         println!("{}", last_value);
     }
 }
+
+/// Rewrites each file in the canonical style. With `--check`, only reports
+/// the files that would change and exits with status 1 if there are any.
+fn format_files(args: Vec<String>) {
+    let mut check = false;
+    let mut filenames = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ if arg.starts_with("--") => {
+                eprintln!("Unknown option: {}\n{}", arg, USAGE);
+                process::exit(2);
+            }
+            _ => filenames.push(arg),
+        }
+    }
+    if filenames.is_empty() {
+        println!("{}", USAGE);
+        return;
+    }
+    let mut unformatted = false;
+    for filename in filenames {
+        let code = fs::read_to_string(&filename).expect("Could not read file");
+        let formatted = match formatter::format_source(&code) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                eprintln!("Syntax error: {}:{}", filename, err);
+                process::exit(1);
+            }
+        };
+        if formatted == code {
+            continue;
+        }
+        if check {
+            println!("Would reformat: {}", filename);
+            unformatted = true;
+        } else {
+            fs::write(&filename, formatted).expect("Could not write file");
+        }
+    }
+    if unformatted {
+        process::exit(1);
+    }
+}
+
+/// Prints the lint diagnostics of each file, exiting with status 1 if any of
+/// them is denied.
+fn lint_files(filenames: Vec<String>) {
+    if filenames.is_empty() {
+        println!("{}", USAGE);
+        return;
+    }
+    let mut denied = false;
+    for filename in filenames {
+        let code = fs::read_to_string(&filename).expect("Could not read file");
+        let diagnostics = match lint::lint(&code) {
+            Ok(diagnostics) => diagnostics,
+            Err(err) => {
+                eprintln!("Syntax error: {}:{}", filename, err);
+                process::exit(1);
+            }
+        };
+        for diagnostic in diagnostics {
+            denied |= diagnostic.level == lint::Level::Deny;
+            println!("{}:{}", filename, diagnostic);
+        }
+    }
+    if denied {
+        process::exit(1);
+    }
+}
+
+fn check_types(filename: &str, code: &str) {
+    let program = parse_or_exit(filename, code);
+    let errors = checker::check(&program);
+    for err in &errors {
+        eprintln!("Type error: {}:{}", filename, err);
+    }
+    if !errors.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn dump_tokens(filename: &str, code: &str, json: bool) {
+    let tokens = match lexer::tokenize(code) {
+        Ok(tokens) => tokens,
+        Err(err) => {
+            eprintln!("Syntax error: {}:{}", filename, err);
+            process::exit(1);
+        }
+    };
+    if json {
+        println!("{}", dump::tokens_json(&tokens).pretty());
+    } else {
+        print!("{}", dump::tokens_text(&tokens));
+    }
+}
+
+fn dump_ast(filename: &str, code: &str, json: bool, optimize: bool) {
+    let mut program = parse_or_exit(filename, code);
+    if optimize {
+        program = optimizer::optimize(program);
+    }
+    if json {
+        println!("{}", dump::ast_json(&program).pretty());
+    } else {
+        print!("{}", dump::ast_text(&program));
+    }
+}
+
+fn parse(code: &str) -> Result<Vec<ast::Stmt>, parser::ParseError> {
+    let lexer = lexer::Lexer::new(code);
+    let mut parser = parser::Parser::new(lexer)?;
+    parser.parse_program()
+}
+
+fn parse_or_exit(filename: &str, code: &str) -> Vec<ast::Stmt> {
+    match parse(code) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("Syntax error: {}:{}", filename, err);
+            process::exit(1);
+        }
+    }
+}
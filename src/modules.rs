@@ -0,0 +1,610 @@
+//! Evaluating other files with `import`.
+//!
+//! Each file is evaluated once, the first time it is imported, in a global
+//! environment of its own. Its top-level definitions are renamed to
+//! `namespace::name` before it runs, so that they cannot clash with the
+//! importer's, and are then copied into every environment that imports it.
+//! Definitions that are not `pub` get a name that cannot be written in a
+//! script instead: the module's own functions can still use them when they
+//! are called from elsewhere, but nothing else can.
+//!
+//! Functions see the environment of their caller, so importing a file also
+//! copies in the definitions of the files it imports. Code can still only
+//! name the namespaces that its own file imports.
+
+use crate::ast::{Expr, Import, Pattern, Stmt, Visibility};
+use crate::interpreter::{Environment, RuntimeError, Value, eval_statement};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// An imported file, as seen by the code that runs in it.
+#[derive(Debug, PartialEq)]
+pub struct Module {
+    pub namespace: String,
+    /// The file, named relative to the working directory when it is inside
+    /// it, for stack traces.
+    pub file: String,
+}
+
+/// The files of a running program.
+#[derive(Default)]
+pub struct Modules {
+    /// The files being evaluated, the innermost last. Starts with the script
+    /// itself when it was read from a file.
+    loading: Vec<Loading>,
+    /// Every file evaluated so far.
+    loaded: HashMap<PathBuf, Loaded>,
+    /// The file each namespace was imported from.
+    namespaces: HashMap<String, PathBuf>,
+    /// The namespaces each file imports, by the namespace of the importing
+    /// file, or `None` for the script.
+    imported: HashMap<Option<String>, HashSet<String>>,
+    /// The directory of the script, if it was read from a file.
+    script_dir: Option<PathBuf>,
+}
+
+/// A file that is being evaluated.
+struct Loading {
+    path: PathBuf,
+    /// How the file was named by the script or the import, for messages.
+    name: String,
+    namespace: Option<String>,
+    /// The files it has imported so far.
+    imports: Vec<PathBuf>,
+}
+
+/// A file that has been evaluated.
+#[derive(Clone)]
+struct Loaded {
+    /// Its top-level definitions, qualified with its namespace.
+    definitions: Vec<(String, Value)>,
+    /// The files it imports, which its functions may call into.
+    imports: Vec<PathBuf>,
+}
+
+impl Modules {
+    /// Records that the script itself was read from `path`, so that imports
+    /// are resolved relative to it.
+    pub fn enter_script(&mut self, path: &Path) {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.script_dir = canonical.parent().map(Path::to_path_buf);
+        self.loading.push(Loading {
+            path: canonical,
+            name: path.display().to_string(),
+            namespace: None,
+            imports: Vec::new(),
+        });
+    }
+
+    /// The directory that imports in the file being evaluated are relative
+    /// to: that of the file, or the working directory for programs that were
+    /// not read from a file.
+    fn base_dir(&self) -> PathBuf {
+        self.loading
+            .last()
+            .and_then(|loading| loading.path.parent())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+    }
+
+    /// The namespace of the file being evaluated, if it was imported.
+    fn namespace(&self) -> Option<String> {
+        self.loading
+            .last()
+            .and_then(|loading| loading.namespace.clone())
+    }
+
+    /// Starts evaluating `path`, refusing if that would import it into itself.
+    fn enter(&mut self, path: PathBuf, name: &str, namespace: &str) -> Result<(), RuntimeError> {
+        if let Some(start) = self.loading.iter().position(|loading| loading.path == path) {
+            let mut cycle: Vec<&str> = self.loading[start..]
+                .iter()
+                .map(|loading| loading.name.as_str())
+                .collect();
+            cycle.push(name);
+            return Err(RuntimeError::Import(format!(
+                "import cycle: {}",
+                cycle.join(" -> ")
+            )));
+        }
+        self.loading.push(Loading {
+            path,
+            name: name.to_string(),
+            namespace: Some(namespace.to_string()),
+            imports: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Whether `path` can be imported without read access: it must be a
+    /// `.toy` file inside the script's directory, or the working directory
+    /// for programs that were not read from a file, or inside a directory of
+    /// `TOY_PATH`.
+    fn may_import(&self, path: &Path) -> bool {
+        if path.extension().is_none_or(|extension| extension != "toy") {
+            return false;
+        }
+        let script_dir = self
+            .script_dir
+            .clone()
+            .or_else(|| Path::new(".").canonicalize().ok());
+        script_dir
+            .into_iter()
+            .chain(
+                search_path()
+                    .into_iter()
+                    .filter_map(|dir| dir.canonicalize().ok()),
+            )
+            .any(|dir| path.starts_with(dir))
+    }
+
+    /// Reserves `namespace` for `path`; two files cannot share one.
+    fn claim(&mut self, namespace: &str, path: &Path) -> Result<(), RuntimeError> {
+        match self.namespaces.get(namespace) {
+            Some(other) if other != path => Err(RuntimeError::Import(format!(
+                "'{}' already names {}, cannot also import {}",
+                namespace,
+                display(other),
+                display(path)
+            ))),
+            Some(_) => Ok(()),
+            None => {
+                self.namespaces
+                    .insert(namespace.to_string(), path.to_path_buf());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Evaluates an `import` statement: loads the file unless it was already
+/// loaded and defines its qualified names, and any names listed in braces,
+/// in `env`.
+pub fn import(import: &Import, env: &mut Environment) -> Result<(), RuntimeError> {
+    let namespace = import.namespace();
+    let name = import.path();
+    let path = find(&name, &env.modules().base_dir())?;
+    if !env.modules().may_import(&path) {
+        env.permissions().check_read(&path.to_string_lossy())?;
+    }
+    env.modules().claim(&namespace, &path)?;
+    let cached = env.modules().loaded.get(&path).cloned();
+    let definitions = match cached {
+        Some(loaded) => loaded.definitions,
+        None => load(&path, &name, &namespace, env)?,
+    };
+    let current = env.modules().namespace();
+    let mut modules = env.modules();
+    if let Some(loading) = modules.loading.last_mut() {
+        loading.imports.push(path.clone());
+    }
+    modules
+        .imported
+        .entry(current.clone())
+        .or_default()
+        .insert(namespace.clone());
+    drop(modules);
+    for requested in import.names() {
+        let find = |qualified: String| definitions.iter().find(|(name, _)| *name == qualified);
+        let Some((_, value)) = find(format!("{}::{}", namespace, requested)) else {
+            if find(private_name(&namespace, requested)).is_some() {
+                return Err(RuntimeError::PrivateItem(requested.clone(), display(&path)));
+            }
+            return Err(RuntimeError::Import(format!(
+                "{} has no definition named '{}'",
+                name, requested
+            )));
+        };
+        // Names imported into another module are private to that module.
+        let local = match &current {
+            Some(current) => private_name(current, requested),
+            None => requested.clone(),
+        };
+        env.define(local, value.clone());
+    }
+    define_all(&path, env, &mut HashSet::new());
+    Ok(())
+}
+
+/// Defines the definitions of the file at `path` in `env`, and those of the
+/// files it imports in turn.
+fn define_all(path: &Path, env: &mut Environment, done: &mut HashSet<PathBuf>) {
+    if !done.insert(path.to_path_buf()) {
+        return;
+    }
+    let Some(loaded) = env.modules().loaded.get(path).cloned() else {
+        return;
+    };
+    for (name, value) in loaded.definitions {
+        env.define(name, value);
+    }
+    for import in &loaded.imports {
+        define_all(import, env, done);
+    }
+}
+
+/// Looks up `name` in `env`. Qualified names are only found in code whose
+/// file imports their namespace, or is that namespace.
+pub fn get(name: &str, env: &Environment) -> Option<Value> {
+    if !visible(name, env) {
+        return None;
+    }
+    env.get(name)
+}
+
+fn visible(name: &str, env: &Environment) -> bool {
+    let Some((namespace, _)) = name.split_once("::") else {
+        return true;
+    };
+    let current = env.module().map(|module| module.namespace.clone());
+    current.as_deref() == Some(namespace)
+        || env
+            .modules()
+            .imported
+            .get(&current)
+            .is_some_and(|namespaces| namespaces.contains(namespace))
+}
+
+/// The error for using the qualified `name` where it is not defined, if it
+/// names a definition that its module did not make `pub`.
+pub fn private_item(name: &str, env: &Environment) -> Option<RuntimeError> {
+    if !visible(name, env) {
+        return None;
+    }
+    let (namespace, item) = name.rsplit_once("::")?;
+    env.get(&private_name(namespace, item))?;
+    let file = display(env.modules().namespaces.get(namespace)?);
+    Some(RuntimeError::PrivateItem(item.to_string(), file))
+}
+
+/// Names a loaded file relative to the working directory when it is inside
+/// it.
+fn display(path: &Path) -> String {
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir.canonicalize().ok()?).ok());
+    relative.unwrap_or(path).display().to_string()
+}
+
+/// The name a definition of `namespace` that is not `pub` is stored under.
+fn private_name(namespace: &str, name: &str) -> String {
+    format!("{}::#{}", namespace, name)
+}
+
+/// The directories of the `TOY_PATH` environment variable.
+fn search_path() -> Vec<PathBuf> {
+    let search_path = std::env::var_os("TOY_PATH").unwrap_or_default();
+    std::env::split_paths(&search_path).collect()
+}
+
+/// Looks for `name` next to the importing file, then in each directory of
+/// the `TOY_PATH` environment variable.
+fn find(name: &str, base_dir: &Path) -> Result<PathBuf, RuntimeError> {
+    std::iter::once(base_dir.to_path_buf())
+        .chain(search_path())
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+        .and_then(|path| path.canonicalize().ok())
+        .ok_or_else(|| RuntimeError::Import(format!("cannot find {}", name)))
+}
+
+/// Evaluates the file at `path` and returns its definitions, qualified with
+/// `namespace`.
+fn load(
+    path: &Path,
+    name: &str,
+    namespace: &str,
+    env: &mut Environment,
+) -> Result<Vec<(String, Value)>, RuntimeError> {
+    let code = fs::read_to_string(path)
+        .map_err(|err| RuntimeError::Import(format!("cannot read {}: {}", name, err)))?;
+    let program = Parser::new(Lexer::new(&code))
+        .and_then(|mut parser| parser.parse_program())
+        .map_err(|err| RuntimeError::Import(format!("{}:{}", name, err)))?;
+    env.modules().enter(path.to_path_buf(), name, namespace)?;
+    let mut module_env = env.module_env();
+    let module = Rc::new(Module {
+        namespace: namespace.to_string(),
+        file: display(path),
+    });
+    let outcome = env.in_module(Some(module), || {
+        qualify(program, namespace)
+            .into_iter()
+            .try_for_each(|stmt| eval_statement(stmt, &mut module_env).map(|_| ()))
+    });
+    let loading = env.modules().loading.pop();
+    outcome?;
+    // Its own names, including the private ones its functions use, but not
+    // those of the files it imports.
+    let prefix = format!("{}::", namespace);
+    let definitions: Vec<(String, Value)> = module_env
+        .names()
+        .into_iter()
+        .filter(|name| name.starts_with(&prefix))
+        .filter_map(|name| Some((name.clone(), module_env.get(&name)?)))
+        .collect();
+    let loaded = Loaded {
+        definitions: definitions.clone(),
+        imports: loading.map(|loading| loading.imports).unwrap_or_default(),
+    };
+    env.modules().loaded.insert(path.to_path_buf(), loaded);
+    Ok(definitions)
+}
+
+/// Renames the top-level definitions of a module, and every use of them, to
+/// `namespace::name`, or to their private name if they are not `pub`.
+fn qualify(program: Vec<Stmt>, namespace: &str) -> Vec<Stmt> {
+    let mut globals = HashMap::new();
+    for stmt in &program {
+        match stmt {
+            Stmt::Let(name, _, _, _, vis, _) | Stmt::Fn(name, _, _, _, _, vis, _) => {
+                globals.insert(name.clone(), *vis);
+            }
+            // Tuples cannot be `pub`.
+            Stmt::LetTuple(..) => {
+                for name in stmt.declared_names() {
+                    globals.insert(name.clone(), Visibility::Private);
+                }
+            }
+            Stmt::Import(import, _) => {
+                for name in import.names() {
+                    globals.insert(name.clone(), Visibility::Private);
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut qualifier = Qualifier {
+        namespace,
+        globals,
+        scopes: Vec::new(),
+    };
+    program
+        .into_iter()
+        .map(|stmt| qualifier.stmt(stmt))
+        .collect()
+}
+
+struct Qualifier<'a> {
+    namespace: &'a str,
+    globals: HashMap<String, Visibility>,
+    /// The names bound inside the function or block being renamed, which
+    /// hide the globals.
+    scopes: Vec<HashSet<String>>,
+}
+
+impl Qualifier<'_> {
+    fn name(&self, name: String) -> String {
+        if self.scopes.iter().any(|scope| scope.contains(&name)) {
+            return name;
+        }
+        match self.globals.get(&name) {
+            Some(Visibility::Public) => format!("{}::{}", self.namespace, name),
+            Some(Visibility::Private) => private_name(self.namespace, &name),
+            None => name,
+        }
+    }
+
+    /// Renames a definition: at the top level it is a global, anywhere else
+    /// it hides one.
+    fn bind(&mut self, name: String) -> String {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.insert(name.clone());
+                name
+            }
+            None => self.name(name),
+        }
+    }
+
+    fn bind_pattern(&mut self, pattern: Pattern) -> Pattern {
+        match pattern {
+            Pattern::Name(name) => Pattern::Name(self.bind(name)),
+            Pattern::Tuple(items) => Pattern::Tuple(
+                items
+                    .into_iter()
+                    .map(|item| self.bind_pattern(item))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// A scope holding the names bound by `patterns`.
+    fn scope<'p>(patterns: impl IntoIterator<Item = &'p Pattern>) -> HashSet<String> {
+        patterns
+            .into_iter()
+            .flat_map(Pattern::names)
+            .cloned()
+            .collect()
+    }
+
+    fn stmt(&mut self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::Let(name, ty, value, doc, vis, span) => {
+                let value = self.expr(value);
+                Stmt::Let(self.bind(name), ty, value, doc, vis, span)
+            }
+            Stmt::LetTuple(patterns, ty, value, span) => {
+                let value = self.expr(value);
+                let patterns = patterns
+                    .into_iter()
+                    .map(|pattern| self.bind_pattern(pattern))
+                    .collect();
+                Stmt::LetTuple(patterns, ty, value, span)
+            }
+            Stmt::Fn(name, params, ret, body, doc, vis, span) => {
+                let name = self.bind(name);
+                self.scopes
+                    .push(Self::scope(params.iter().map(|param| &param.pattern)));
+                let body = self.expr(body);
+                self.scopes.pop();
+                Stmt::Fn(name, params, ret, body, doc, vis, span)
+            }
+            Stmt::Expression(expr) => Stmt::Expression(self.expr(expr)),
+            Stmt::ImplicitReturn(expr) => Stmt::ImplicitReturn(self.expr(expr)),
+            Stmt::Assign(name, value, span) => {
+                Stmt::Assign(self.name(name), self.expr(value), span)
+            }
+            Stmt::While(cond, body, span) => Stmt::While(self.expr(cond), self.expr(body), span),
+            Stmt::For(pattern, iterable, body, span) => {
+                let iterable = self.expr(iterable);
+                self.scopes.push(Self::scope([&pattern]));
+                let body = self.expr(body);
+                self.scopes.pop();
+                Stmt::For(pattern, iterable, body, span)
+            }
+            Stmt::Yield(value, span) => Stmt::Yield(self.expr(value), span),
+            Stmt::Import(..) => stmt,
+        }
+    }
+
+    fn expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Literal(..) => expr,
+            Expr::Binary(lhs, op, rhs) => {
+                Expr::Binary(Box::new(self.expr(*lhs)), op, Box::new(self.expr(*rhs)))
+            }
+            Expr::Unary(op, operand, span) => Expr::Unary(op, Box::new(self.expr(*operand)), span),
+            Expr::Variable(name, span) => Expr::Variable(self.name(name), span),
+            Expr::Call(name, args, span) => Expr::Call(
+                self.name(name),
+                args.into_iter().map(|arg| self.expr(arg)).collect(),
+                span,
+            ),
+            Expr::MethodCall(name, args, span) => Expr::MethodCall(
+                self.name(name),
+                args.into_iter().map(|arg| self.expr(arg)).collect(),
+                span,
+            ),
+            Expr::Block(stmts, tail, span) => {
+                self.scopes.push(HashSet::new());
+                let stmts = stmts.into_iter().map(|stmt| self.stmt(stmt)).collect();
+                let tail = tail.map(|tail| Box::new(self.expr(*tail)));
+                self.scopes.pop();
+                Expr::Block(stmts, tail, span)
+            }
+            Expr::If(cond, then, otherwise, span) => Expr::If(
+                Box::new(self.expr(*cond)),
+                Box::new(self.expr(*then)),
+                otherwise.map(|otherwise| Box::new(self.expr(*otherwise))),
+                span,
+            ),
+            Expr::Propagate(inner, span) => Expr::Propagate(Box::new(self.expr(*inner)), span),
+            Expr::Tuple(items, span) => Expr::Tuple(
+                items.into_iter().map(|item| self.expr(item)).collect(),
+                span,
+            ),
+            Expr::TupleIndex(tuple, index, span) => {
+                Expr::TupleIndex(Box::new(self.expr(*tuple)), index, span)
+            }
+            Expr::Try(body, name, handler, span) => {
+                let body = self.expr(*body);
+                self.scopes.push(HashSet::from([name.clone()]));
+                let handler = self.expr(*handler);
+                self.scopes.pop();
+                Expr::Try(Box::new(body), name, Box::new(handler), span)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump;
+
+    fn parse(input: &str) -> Vec<Stmt> {
+        Parser::new(Lexer::new(input))
+            .unwrap()
+            .parse_program()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_qualify() {
+        let program = qualify(
+            parse(
+                "
+                import other::{helper};
+                pub let base = 10;
+                pub fn scale(n) { helper(n) * base }
+                fn shadow(base) { let scale = base; scale }
+                fn retry() { try { scale(1) } catch base { base } }
+                base = other::value;
+                ",
+            ),
+            "m",
+        );
+        assert_eq!(
+            dump::ast_text(&program),
+            "Import other::{helper}\n\
+             Let m::base\n\
+             \x20 Public\n\
+             \x20 Literal Int 10\n\
+             Fn m::scale(n)\n\
+             \x20 Public\n\
+             \x20 Block\n\
+             \x20   Tail\n\
+             \x20     Binary Mul\n\
+             \x20       Call m::#helper\n\
+             \x20         Variable n\n\
+             \x20       Variable m::base\n\
+             Fn m::#shadow(base)\n\
+             \x20 Block\n\
+             \x20   Let scale\n\
+             \x20     Variable base\n\
+             \x20   Tail\n\
+             \x20     Variable scale\n\
+             Fn m::#retry()\n\
+             \x20 Block\n\
+             \x20   Tail\n\
+             \x20     Try\n\
+             \x20       Block\n\
+             \x20         Tail\n\
+             \x20           Call m::scale\n\
+             \x20             Literal Int 1\n\
+             \x20       Catch base\n\
+             \x20         Block\n\
+             \x20           Tail\n\
+             \x20             Variable base\n\
+             Assign m::base\n\
+             \x20 Variable other::value\n"
+        );
+    }
+
+    #[test]
+    fn test_import() {
+        let mut env = Environment::new();
+        env.set_script_path(Path::new("tests/modules/main.toy"));
+        for stmt in parse("import lib::shapes::{cube}; import \"utils.toy\";") {
+            eval_statement(stmt, &mut env).unwrap();
+        }
+        for (input, expected) in [
+            ("cube(3)", 27),
+            ("shapes::square(3)", 9),
+            ("utils::add(1, 2)", 3),
+        ] {
+            let [stmt] = <[Stmt; 1]>::try_from(parse(input)).unwrap();
+            assert_eq!(eval_statement(stmt, &mut env), Ok(Value::Int(expected)));
+        }
+        let private =
+            RuntimeError::PrivateItem("repeat".to_string(), "tests/modules/utils.toy".to_string());
+        for input in ["utils::repeat(1, 1, 0)", "import utils::{repeat};"] {
+            let [stmt] = <[Stmt; 1]>::try_from(parse(input)).unwrap();
+            assert_eq!(eval_statement(stmt, &mut env), Err(private.clone()));
+        }
+
+        let mut env = Environment::new();
+        env.set_script_path(Path::new("tests/modules/cycle_b.toy"));
+        let [stmt] = <[Stmt; 1]>::try_from(parse("import \"cycle_a.toy\";")).unwrap();
+        assert_eq!(
+            eval_statement(stmt, &mut env),
+            Err(RuntimeError::Import(
+                "import cycle: tests/modules/cycle_b.toy -> cycle_a.toy -> cycle_b.toy".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,480 @@
+//! AST optimiser enabled by `-O`.
+//!
+//! Every rewrite here must leave the observable behaviour of a program that
+//! runs successfully unchanged. Expressions whose evaluation would fail (such
+//! as `1 / 0`) are left alone so the error is still raised at runtime.
+
+use crate::ast::{BinaryOp, Expr, Literal, Pattern, Stmt, UnaryOp, Visibility};
+use crate::interpreter::{Environment, Value, eval_expression};
+use crate::lexer::Span;
+use std::collections::{HashMap, HashSet};
+
+/// Functions whose bodies are larger than this many expression nodes are
+/// never inlined.
+const INLINE_LIMIT: usize = 16;
+
+pub fn optimize(program: Vec<Stmt>) -> Vec<Stmt> {
+    let mut optimizer = Optimizer {
+        env: Environment::new(),
+        pinned: pinned_functions(&program),
+        inlinable: HashMap::new(),
+        depth: 0,
+    };
+    program
+        .into_iter()
+        .map(|stmt| optimizer.stmt(stmt))
+        .collect()
+}
+
+struct Optimizer {
+    /// Used to evaluate operators on literals with the interpreter's own rules.
+    env: Environment,
+    /// Top-level functions that are defined once and never rebound, so a
+    /// call by that name always reaches the same definition.
+    pinned: HashSet<String>,
+    inlinable: HashMap<String, (Vec<Pattern>, Expr)>,
+    /// How many blocks or function bodies enclose the current statement.
+    depth: usize,
+}
+
+impl Optimizer {
+    fn stmt(&mut self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::Let(name, ty, expr, doc, vis, span) => {
+                Stmt::Let(name, ty, self.expr(expr), doc, vis, span)
+            }
+            Stmt::LetTuple(patterns, ty, expr, span) => {
+                Stmt::LetTuple(patterns, ty, self.expr(expr), span)
+            }
+            Stmt::Fn(name, params, ret, body, doc, vis, span) => {
+                self.depth += 1;
+                let body = self.expr(body);
+                self.depth -= 1;
+                if self.depth == 0 && self.pinned.contains(&name) && can_inline(&name, &body) {
+                    let patterns = params.iter().map(|param| param.pattern.clone()).collect();
+                    self.inlinable
+                        .insert(name.clone(), (patterns, body.clone()));
+                }
+                Stmt::Fn(name, params, ret, body, doc, vis, span)
+            }
+            Stmt::Expression(expr) => Stmt::Expression(self.expr(expr)),
+            Stmt::ImplicitReturn(expr) => Stmt::ImplicitReturn(self.expr(expr)),
+            Stmt::Assign(name, expr, span) => Stmt::Assign(name, self.expr(expr), span),
+            Stmt::While(condition, body, span) => {
+                let condition = self.expr(condition);
+                self.depth += 1;
+                let body = self.expr(body);
+                self.depth -= 1;
+                Stmt::While(condition, body, span)
+            }
+            Stmt::For(pattern, iterable, body, span) => {
+                let iterable = self.expr(iterable);
+                self.depth += 1;
+                let body = self.expr(body);
+                self.depth -= 1;
+                Stmt::For(pattern, iterable, body, span)
+            }
+            Stmt::Yield(value, span) => Stmt::Yield(self.expr(value), span),
+            Stmt::Import(..) => stmt,
+        }
+    }
+
+    fn expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Literal(..) | Expr::Variable(..) => expr,
+            Expr::Binary(lhs, op, rhs) => {
+                let folded = Expr::Binary(Box::new(self.expr(*lhs)), op, Box::new(self.expr(*rhs)));
+                match &folded {
+                    Expr::Binary(lhs, _, rhs) if is_literal(lhs) && is_literal(rhs) => {
+                        self.fold(folded)
+                    }
+                    _ => folded,
+                }
+            }
+            Expr::Unary(op, rhs, span) => match self.expr(*rhs) {
+                // `!!x` is `x` for every boolean. Anything else must keep
+                // its type mismatch, which `try` can catch.
+                Expr::Unary(UnaryOp::Not, inner, _) if op == UnaryOp::Not && is_boolean(&inner) => {
+                    *inner
+                }
+                rhs if is_literal(&rhs) => self.fold(Expr::Unary(op, Box::new(rhs), span)),
+                rhs => Expr::Unary(op, Box::new(rhs), span),
+            },
+            Expr::Propagate(operand, span) => Expr::Propagate(Box::new(self.expr(*operand)), span),
+            Expr::TupleIndex(tuple, index, span) => {
+                Expr::TupleIndex(Box::new(self.expr(*tuple)), index, span)
+            }
+            Expr::Tuple(items, span) => Expr::Tuple(
+                items.into_iter().map(|item| self.expr(item)).collect(),
+                span,
+            ),
+            Expr::Try(body, name, handler, span) => {
+                let body = self.expr(*body);
+                let handler = self.expr(*handler);
+                Expr::Try(Box::new(body), name, Box::new(handler), span)
+            }
+            Expr::Call(name, args, span) => {
+                let args: Vec<Expr> = args.into_iter().map(|arg| self.expr(arg)).collect();
+                self.inline(name, args, span)
+            }
+            Expr::MethodCall(name, args, span) => Expr::MethodCall(
+                name,
+                args.into_iter().map(|arg| self.expr(arg)).collect(),
+                span,
+            ),
+            Expr::Block(statements, tail, span) => {
+                self.depth += 1;
+                let statements = statements.into_iter().map(|stmt| self.stmt(stmt)).collect();
+                let tail = tail.map(|tail| Box::new(self.expr(*tail)));
+                self.depth -= 1;
+                Expr::Block(statements, tail, span)
+            }
+            Expr::If(condition, then_branch, else_branch, span) => {
+                let condition = self.expr(*condition);
+                let then_branch = self.expr(*then_branch);
+                let else_branch = else_branch.map(|branch| self.expr(*branch));
+                match condition {
+                    // The interpreter takes the `else` branch for any
+                    // condition other than `true`.
+                    Expr::Literal(literal, _) => {
+                        if literal == Literal::Bool(true) {
+                            then_branch
+                        } else {
+                            else_branch.unwrap_or(Expr::Block(Vec::new(), None, span))
+                        }
+                    }
+                    condition => Expr::If(
+                        Box::new(condition),
+                        Box::new(then_branch),
+                        else_branch.map(Box::new),
+                        span,
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Evaluates an operator applied to literals, keeping the expression as
+    /// it is if that fails or produces something that is not a literal.
+    fn fold(&mut self, expr: Expr) -> Expr {
+        let span = expr.span();
+        let literal = match eval_expression(expr.clone(), &mut self.env) {
+            Ok(Value::Int(i)) => Literal::Int(i),
+            Ok(Value::BigInt(i)) => Literal::BigInt(i),
+            Ok(Value::Float(f)) => Literal::Float(f),
+            Ok(Value::Bool(b)) => Literal::Bool(b),
+            Ok(Value::Str(s)) => Literal::Str(s),
+            _ => return expr,
+        };
+        Expr::Literal(literal, span)
+    }
+
+    /// Replaces a call to an inlinable function with a block that binds the
+    /// parameters and then evaluates the body.
+    fn inline(&self, name: String, args: Vec<Expr>, span: Span) -> Expr {
+        let Some((params, body)) = self.inlinable.get(&name) else {
+            return Expr::Call(name, args, span);
+        };
+        if params.len() != args.len() || args.iter().any(assigns) {
+            return Expr::Call(name, args, span);
+        }
+        // Arguments are evaluated before any parameter is bound. When an
+        // argument reads a name that a parameter shadows, evaluate them all
+        // into temporaries first. `#` cannot appear in identifiers, so these
+        // never collide with the program's own names.
+        let shadowed = args.iter().any(|arg| {
+            params
+                .iter()
+                .flat_map(Pattern::names)
+                .any(|param| mentions(arg, param))
+        });
+        let mut statements = Vec::new();
+        if shadowed {
+            for (i, arg) in args.into_iter().enumerate() {
+                let temp = Pattern::Name(format!("#arg{}", i));
+                statements.push(binding(&temp, arg, span));
+            }
+            for (i, param) in params.iter().enumerate() {
+                let temp = Expr::Variable(format!("#arg{}", i), span);
+                statements.push(binding(param, temp, span));
+            }
+        } else {
+            for (param, arg) in params.iter().zip(args) {
+                statements.push(binding(param, arg, span));
+            }
+        }
+        Expr::Block(statements, Some(Box::new(body.clone())), span)
+    }
+}
+
+/// A `let` that binds `pattern` to `value`, as passing it as an argument
+/// would.
+fn binding(pattern: &Pattern, value: Expr, span: Span) -> Stmt {
+    match pattern {
+        Pattern::Name(name) => {
+            Stmt::Let(name.clone(), None, value, None, Visibility::Private, span)
+        }
+        Pattern::Tuple(patterns) => Stmt::LetTuple(patterns.clone(), None, value, span),
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(expr, Expr::Literal(..))
+}
+
+// Whether `expr` can only evaluate to a boolean, if it evaluates at all.
+fn is_boolean(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Literal(Literal::Bool(_), _)
+            | Expr::Unary(UnaryOp::Not, ..)
+            | Expr::Binary(
+                _,
+                BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Eq | BinaryOp::And | BinaryOp::Or,
+                _
+            )
+    )
+}
+
+/// A function body can be inlined as a block if it is small, does not call
+/// itself, and neither assigns to nor defines names: a call discards such
+/// changes, while a block would propagate them to the caller. Nor may it use
+/// `?`, which returns from the function it appears in, or `yield`, which
+/// makes it a generator.
+fn can_inline(name: &str, body: &Expr) -> bool {
+    let mut size = 0;
+    let mut recursive = false;
+    let mut propagates = false;
+    walk_expr(
+        body,
+        &mut |expr| {
+            size += 1;
+            recursive |= matches!(expr, Expr::Call(callee, ..) | Expr::MethodCall(callee, ..) if callee == name);
+            propagates |= matches!(expr, Expr::Propagate(..));
+        },
+        &mut |_| {},
+    );
+    !recursive && !propagates && !body.contains_yield() && !assigns(body) && size <= INLINE_LIMIT
+}
+
+fn assigns(expr: &Expr) -> bool {
+    let mut found = false;
+    walk_expr(expr, &mut |_| {}, &mut |stmt| {
+        found |= matches!(stmt, Stmt::Assign(..) | Stmt::Fn(..));
+    });
+    found
+}
+
+fn mentions(expr: &Expr, name: &str) -> bool {
+    let mut found = false;
+    walk_expr(
+        expr,
+        &mut |expr| {
+            found |= matches!(expr, Expr::Variable(n, _) | Expr::Call(n, ..) | Expr::MethodCall(n, ..) if n == name);
+        },
+        &mut |_| {},
+    );
+    found
+}
+
+/// Top-level functions that no other statement anywhere in the program
+/// defines, assigns or binds as a parameter.
+fn pinned_functions<'a>(program: &'a [Stmt]) -> HashSet<String> {
+    let mut definitions: HashMap<&str, usize> = HashMap::new();
+    let mut rebound = HashSet::new();
+    let mut caught = Vec::new();
+    for stmt in program {
+        let mut on_expr = |expr: &'a Expr| {
+            if let Expr::Try(_, name, ..) = expr {
+                caught.push(name);
+            }
+        };
+        walk_stmt(stmt, &mut on_expr, &mut |stmt| match stmt {
+            Stmt::Fn(name, params, ..) => {
+                *definitions.entry(name.as_str()).or_default() += 1;
+                let names = params.iter().flat_map(|param| param.pattern.names());
+                rebound.extend(names.map(String::as_str));
+            }
+            Stmt::Let(name, ..) | Stmt::Assign(name, ..) => {
+                rebound.insert(name.as_str());
+            }
+            Stmt::LetTuple(..) => {
+                rebound.extend(stmt.declared_names().into_iter().map(String::as_str))
+            }
+            Stmt::For(pattern, ..) => {
+                rebound.extend(pattern.names().into_iter().map(String::as_str))
+            }
+            Stmt::Import(import, _) => {
+                rebound.extend(import.names().iter().map(String::as_str));
+            }
+            _ => {}
+        });
+    }
+    rebound.extend(caught.iter().map(|name| name.as_str()));
+    program
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Fn(name, ..)
+                if definitions[name.as_str()] == 1 && !rebound.contains(name.as_str()) =>
+            {
+                Some(name.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn walk_stmt<'a>(
+    stmt: &'a Stmt,
+    on_expr: &mut impl FnMut(&'a Expr),
+    on_stmt: &mut impl FnMut(&'a Stmt),
+) {
+    on_stmt(stmt);
+    match stmt {
+        Stmt::Let(_, _, expr, ..)
+        | Stmt::LetTuple(_, _, expr, _)
+        | Stmt::Fn(_, _, _, expr, ..)
+        | Stmt::Expression(expr)
+        | Stmt::ImplicitReturn(expr)
+        | Stmt::Assign(_, expr, _)
+        | Stmt::Yield(expr, _) => walk_expr(expr, on_expr, on_stmt),
+        Stmt::While(condition, body, _) | Stmt::For(_, condition, body, _) => {
+            walk_expr(condition, on_expr, on_stmt);
+            walk_expr(body, on_expr, on_stmt);
+        }
+        Stmt::Import(..) => {}
+    }
+}
+
+fn walk_expr<'a>(
+    expr: &'a Expr,
+    on_expr: &mut impl FnMut(&'a Expr),
+    on_stmt: &mut impl FnMut(&'a Stmt),
+) {
+    on_expr(expr);
+    match expr {
+        Expr::Literal(..) | Expr::Variable(..) => {}
+        Expr::Binary(lhs, _, rhs) => {
+            walk_expr(lhs, on_expr, on_stmt);
+            walk_expr(rhs, on_expr, on_stmt);
+        }
+        Expr::Unary(_, rhs, _) | Expr::Propagate(rhs, _) | Expr::TupleIndex(rhs, _, _) => {
+            walk_expr(rhs, on_expr, on_stmt)
+        }
+        Expr::Try(body, _, handler, _) => {
+            walk_expr(body, on_expr, on_stmt);
+            walk_expr(handler, on_expr, on_stmt);
+        }
+        Expr::Call(_, args, _) | Expr::MethodCall(_, args, _) | Expr::Tuple(args, _) => {
+            for arg in args {
+                walk_expr(arg, on_expr, on_stmt);
+            }
+        }
+        Expr::Block(statements, tail, _) => {
+            for stmt in statements {
+                walk_stmt(stmt, on_expr, on_stmt);
+            }
+            if let Some(tail) = tail {
+                walk_expr(tail, on_expr, on_stmt);
+            }
+        }
+        Expr::If(condition, then_branch, else_branch, _) => {
+            walk_expr(condition, on_expr, on_stmt);
+            walk_expr(then_branch, on_expr, on_stmt);
+            if let Some(branch) = else_branch {
+                walk_expr(branch, on_expr, on_stmt);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump::ast_text;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let mut parser = Parser::new(Lexer::new(source)).unwrap();
+        parser.parse_program().unwrap()
+    }
+
+    fn assert_optimizes(source: &str, expected: &str) {
+        let optimized = optimize(parse(source));
+        assert_eq!(
+            ast_text(&optimized),
+            ast_text(&parse(expected)),
+            "{}",
+            source
+        );
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        assert_optimizes("let x = 2 * 3 + 4;", "let x = 10;");
+        assert_optimizes("let s = \"a\" + \"b\";", "let s = \"ab\";");
+        assert_optimizes("let b = 1 < 2 && !false;", "let b = true;");
+        assert_optimizes(
+            "let x = 9223372036854775807 + 1;",
+            "let x = 9223372036854775808;",
+        );
+        assert_optimizes("let f = 1.5 * 2;", "let f = 3.0;");
+        // Failing operations stay to report their error at runtime.
+        assert_optimizes("let x = 1 / 0;", "let x = 1 / 0;");
+        assert_optimizes("let x = 1 + true;", "let x = 1 + true;");
+        assert_optimizes("let x = y + 2 * 3;", "let x = y + 6;");
+    }
+
+    #[test]
+    fn test_dead_branches() {
+        assert_optimizes("if 1 < 2 { a(); } else { b(); }", "{ a(); }");
+        assert_optimizes("if false { a(); } else { b(); }", "{ b(); }");
+        assert_optimizes("let x = if false { 1 };", "let x = {};");
+        assert_optimizes("if c { a(); }", "if c { a(); }");
+    }
+
+    #[test]
+    fn test_double_negation() {
+        assert_optimizes("let x = !!(y < z);", "let x = y < z;");
+        assert_optimizes("let x = !!!y;", "let x = !y;");
+        // `y` might not be a boolean, and then `!!y` is a type mismatch.
+        assert_optimizes("let x = !!y;", "let x = !!y;");
+    }
+
+    #[test]
+    fn test_inlining() {
+        assert_optimizes(
+            "fn add(a, b) { a + b } let x = add(1, y);",
+            "fn add(a, b) { a + b } let x = { let a = 1; let b = y; { a + b } };",
+        );
+        // Recursive, mutating and redefined functions are left as calls.
+        let recursive = "fn f(n) { if n < 1 { 0 } else { f(n - 1) } } let x = f(3);";
+        assert_optimizes(recursive, recursive);
+        let mutating = "let c = 0; fn bump() { c = c + 1; c } let x = bump();";
+        assert_optimizes(mutating, mutating);
+        let redefined = "fn f() { 1 } fn f() { 2 } let x = f();";
+        assert_optimizes(redefined, redefined);
+        let rebound = "fn f() { 1 } fn g(f) { f } let x = f();";
+        assert_optimizes(rebound, rebound);
+        let rebound_in_tuple = "fn f() { 1 } fn g((a, f)) { f } let x = f();";
+        assert_optimizes(rebound_in_tuple, rebound_in_tuple);
+        assert_optimizes(
+            "fn first((a, b)) { a } let x = first(p);",
+            "fn first((a, b)) { a } let x = { let (a, b) = p; { a } };",
+        );
+    }
+
+    #[test]
+    fn test_inlining_keeps_argument_order() {
+        let optimized = optimize(parse(
+            "fn sub(a, b) { a - b } let a = 5; let b = 2; sub(b, a)",
+        ));
+        let mut env = Environment::new();
+        let mut last = Value::Unit;
+        for stmt in optimized {
+            last = crate::interpreter::eval_statement(stmt, &mut env).unwrap();
+        }
+        assert_eq!(last, Value::Int(-3));
+    }
+}
@@ -1,86 +1,175 @@
-use crate::ast::{BinaryOp, Expr, Literal, Stmt};
-use crate::lexer::{Lexer, Token};
+use crate::ast::{BinaryOp, Expr, Import, Literal, Param, Pattern, Stmt, Type, Visibility};
+use crate::lexer::{LexError, Lexer, Span, Token};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+impl From<LexError> for ParseError {
+    fn from(err: LexError) -> Self {
+        ParseError {
+            message: err.message,
+            span: err.span,
+        }
+    }
+}
 
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     current_token: Token,
+    current_span: Span,
+    // `///` lines directly preceding the current token.
+    doc_comments: Vec<String>,
+    // `//!` lines at the start of the file.
+    module_doc: Vec<String>,
+    // How many function bodies the current token is inside.
+    function_depth: usize,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(mut lexer: Lexer<'a>) -> Self {
-        let mut first_token = lexer.next_token();
-        while let Token::Comment = first_token {
-            first_token = lexer.next_token();
-        }
-        Parser {
+    pub fn new(lexer: Lexer<'a>) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            current_span: lexer.span(),
             lexer,
-            current_token: first_token,
+            current_token: Token::Eof,
+            doc_comments: Vec::new(),
+            module_doc: Vec::new(),
+            function_depth: 0,
+        };
+        parser.advance()?;
+        Ok(parser)
+    }
+
+    /// The `//!` documentation at the start of the file, if any.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn module_doc(&self) -> Option<String> {
+        if self.module_doc.is_empty() {
+            None
+        } else {
+            Some(self.module_doc.join("\n"))
         }
     }
 
-    fn advance(&mut self) {
+    fn advance(&mut self) -> Result<(), ParseError> {
+        let at_start = self.current_token == Token::Eof;
+        self.doc_comments.clear();
         loop {
-            self.current_token = self.lexer.next_token();
-            if !matches!(self.current_token, Token::Comment) {
-                break;
+            self.current_token = self.lexer.next_token()?;
+            self.current_span = self.lexer.span();
+            match &self.current_token {
+                Token::Comment(_) => {}
+                Token::DocComment(text) => self.doc_comments.push(text.clone()),
+                Token::InnerDocComment(text) => {
+                    if !at_start || !self.doc_comments.is_empty() {
+                        return self
+                            .error("Inner doc comments ('//!') must come first in the file");
+                    }
+                    self.module_doc.push(text.clone());
+                }
+                _ => break,
             }
         }
+        // Doc comments only document the declaration that follows them.
+        if !matches!(self.current_token, Token::Let | Token::Fn | Token::Pub) {
+            self.doc_comments.clear();
+        }
+        Ok(())
+    }
+
+    fn take_doc(&mut self) -> Option<String> {
+        if self.doc_comments.is_empty() {
+            None
+        } else {
+            Some(self.doc_comments.drain(..).collect::<Vec<_>>().join("\n"))
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ParseError> {
+        Err(ParseError {
+            message: message.into(),
+            span: self.current_span,
+        })
     }
 
-    fn expect(&mut self, expected: Token) {
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
         if self.current_token == expected {
-            self.advance();
+            self.advance()
         } else {
-            panic!("Expected {:?}, but got {:?}", expected, self.current_token);
+            self.error(format!(
+                "Expected {:?}, but got {:?}",
+                expected, self.current_token
+            ))
         }
     }
 
-    pub fn parse_program(&mut self) -> Vec<Stmt> {
+    pub fn parse_program(&mut self) -> Result<Vec<Stmt>, ParseError> {
         let mut statements = Vec::new();
         while self.current_token != Token::Eof {
             match self.current_token {
                 Token::Let | Token::Fn => {
-                    statements.push(self.parse_statement());
+                    statements.push(self.parse_statement()?);
                 }
                 Token::While => {
-                    statements.push(self.parse_while_statement());
+                    statements.push(self.parse_while_statement()?);
+                }
+                Token::For => {
+                    statements.push(self.parse_for_statement()?);
+                }
+                Token::Yield => {
+                    statements.push(self.parse_yield_statement()?);
+                }
+                Token::Import => {
+                    statements.push(self.parse_import_statement()?);
+                }
+                Token::Pub => {
+                    statements.push(self.parse_public_declaration()?);
                 }
                 // Expressions (e.g., "1 + 1") or Assignments (e.g. "x += 1").
                 _ => {
-                    statements.push(self.parse_expression_statement());
+                    statements.push(self.parse_expression_statement()?);
                 }
             }
         }
-        statements
+        Ok(statements)
     }
 
-    fn parse_unary(&mut self) -> Expr {
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
         if self.current_token == Token::Bang || self.current_token == Token::Minus {
+            let span = self.current_span;
             let op = match self.current_token {
                 Token::Bang => crate::ast::UnaryOp::Not,
                 Token::Minus => crate::ast::UnaryOp::Neg,
                 _ => unreachable!(),
             };
-            self.advance(); // Eat the `!` or `-`.
-            let right = self.parse_unary();
-            return Expr::Unary(op, Box::new(right));
+            self.advance()?; // Eat the `!` or `-`.
+            let right = self.parse_unary()?;
+            return Ok(Expr::Unary(op, Box::new(right), span));
         }
         self.parse_primary()
     }
 
-    pub fn parse_expression(&mut self, min_precedence: u8) -> Expr {
-        let mut lhs = self.parse_unary();
+    pub fn parse_expression(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let mut lhs = self.parse_unary()?;
         while self.get_precedence() > min_precedence {
             let op_precedence = self.get_precedence();
             let op = self.get_binary_op().unwrap();
-            self.advance(); // Eat the operator
-            let rhs = self.parse_expression(op_precedence);
+            self.advance()?; // Eat the operator
+            let rhs = self.parse_expression(op_precedence)?;
             lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
         }
-        lhs
+        Ok(lhs)
     }
 
-    pub fn parse_statement(&mut self) -> Stmt {
+    pub fn parse_statement(&mut self) -> Result<Stmt, ParseError> {
         match self.current_token {
             // For example: let x = 123;
             Token::Let => self.parse_let_statement(),
@@ -88,32 +177,107 @@ impl<'a> Parser<'a> {
             Token::Fn => self.parse_function_statement(),
             // For example: while cond {}
             Token::While => self.parse_while_statement(),
+            // For example: for x in xs {}
+            Token::For => self.parse_for_statement(),
+            // For example: yield x;
+            Token::Yield => self.parse_yield_statement(),
             // For example: a + 1;
             _ => self.parse_expression_statement(),
         }
     }
 
-    fn parse_while_statement(&mut self) -> Stmt {
-        self.advance(); // Eat `while`.
-        let condition = self.parse_expression(0);
-        let body = self.parse_block();
-        Stmt::While(condition, body)
+    fn parse_while_statement(&mut self) -> Result<Stmt, ParseError> {
+        let span = self.current_span;
+        self.advance()?; // Eat `while`.
+        let condition = self.parse_expression(0)?;
+        let body = self.parse_block()?;
+        Ok(Stmt::While(condition, body, span))
     }
 
-    fn try_parse_assignment(&mut self, expr: Expr) -> Result<Stmt, Expr> {
+    fn parse_for_statement(&mut self) -> Result<Stmt, ParseError> {
+        let span = self.current_span;
+        self.advance()?; // Eat `for`.
+        let pattern = self.parse_pattern("after 'for'")?;
+        if self.current_token != Token::In {
+            return self.error("Expected 'in' after the loop variable");
+        }
+        self.advance()?; // Eat `in`.
+        let iterable = self.parse_expression(0)?;
+        let body = self.parse_block()?;
+        Ok(Stmt::For(pattern, iterable, body, span))
+    }
+
+    fn parse_yield_statement(&mut self) -> Result<Stmt, ParseError> {
+        if self.function_depth == 0 {
+            return self.error("'yield' is only allowed inside functions");
+        }
+        let span = self.current_span;
+        self.advance()?; // Eat `yield`.
+        let value = self.parse_expression(0)?;
+        self.expect(Token::SemiColon)?;
+        Ok(Stmt::Yield(value, span))
+    }
+
+    // Parses `import "path.toy";` or `import module::{name, ...};`.
+    fn parse_import_statement(&mut self) -> Result<Stmt, ParseError> {
+        let span = self.current_span;
+        self.advance()?; // Eat `import`.
+        let import = match &self.current_token {
+            Token::Str(path) => {
+                let path = path.clone();
+                self.advance()?;
+                Import::File(path)
+            }
+            Token::Identifier(_) => {
+                let mut module = Vec::new();
+                while let Token::Identifier(segment) = &self.current_token {
+                    module.push(segment.clone());
+                    self.advance()?;
+                    self.expect(Token::ColonColon)?;
+                }
+                self.expect(Token::LBrace)?;
+                let mut names = Vec::new();
+                while let Token::Identifier(name) = &self.current_token {
+                    names.push(name.clone());
+                    self.advance()?;
+                    if self.current_token == Token::Comma {
+                        self.advance()?;
+                    } else {
+                        break;
+                    }
+                }
+                if names.is_empty() {
+                    return self.error("Expected the names to import");
+                }
+                self.expect(Token::RBrace)?;
+                Import::Names(module, names)
+            }
+            _ => return self.error("Expected a file name or a module after 'import'"),
+        };
+        self.expect(Token::SemiColon)?;
+        Ok(Stmt::Import(import, span))
+    }
+
+    fn try_parse_assignment(&mut self, expr: Expr) -> Result<Result<Stmt, Expr>, ParseError> {
         if matches!(
             self.current_token,
             Token::PlusEq | Token::MinusEq | Token::StarEq | Token::SlashEq | Token::Eq
         ) {
-            let name = match expr {
-                Expr::Variable(n) => n,
-                _ => panic!("Invalid assignment target. Only variables can be assigned to."),
+            let (name, span) = match expr {
+                Expr::Variable(n, _) if n.contains("::") => {
+                    return self.error(format!("Cannot assign to '{}' outside of its module", n));
+                }
+                Expr::Variable(n, span) => (n, span),
+                _ => {
+                    return self
+                        .error("Invalid assignment target. Only variables can be assigned to.");
+                }
             };
             if self.current_token == Token::Eq {
-                self.advance();
-                let right = self.parse_expression(0);
-                self.expect(Token::SemiColon);
-                return Ok(Stmt::Assign(name, right));
+                self.advance()?;
+                let right = self.parse_expression(0)?;
+                self.expect(Token::SemiColon)?;
+                return Ok(Ok(Stmt::Assign(name, right, span)));
             }
             let op = match self.current_token {
                 Token::PlusEq => BinaryOp::Add,
@@ -122,34 +286,51 @@ impl<'a> Parser<'a> {
                 Token::SlashEq => BinaryOp::Div,
                 _ => unreachable!(),
             };
-            self.advance(); // Eat the operator (+=, etc).
-            let right = self.parse_expression(0);
-            self.expect(Token::SemiColon);
-            let new_value_expr =
-                Expr::Binary(Box::new(Expr::Variable(name.clone())), op, Box::new(right));
-            return Ok(Stmt::Assign(name, new_value_expr));
+            self.advance()?; // Eat the operator (+=, etc).
+            let right = self.parse_expression(0)?;
+            self.expect(Token::SemiColon)?;
+            // `x += 1` desugars to `x = x + 1`, reusing the target's span.
+            let new_value_expr = Expr::Binary(
+                Box::new(Expr::Variable(name.clone(), span)),
+                op,
+                Box::new(right),
+            );
+            return Ok(Ok(Stmt::Assign(name, new_value_expr, span)));
         }
-        Err(expr)
+        Ok(Err(expr))
     }
 
-    fn parse_block(&mut self) -> Expr {
-        self.expect(Token::LBrace);
+    fn parse_block(&mut self) -> Result<Expr, ParseError> {
+        let span = self.current_span;
+        self.expect(Token::LBrace)?;
         let mut statements = Vec::new();
         let mut tail_expr = None;
         while self.current_token != Token::RBrace && self.current_token != Token::Eof {
             match self.current_token {
                 Token::Let => {
-                    statements.push(self.parse_let_statement());
+                    statements.push(self.parse_let_statement()?);
                 }
                 Token::Fn => {
-                    statements.push(self.parse_function_statement());
+                    statements.push(self.parse_function_statement()?);
                 }
                 Token::While => {
-                    statements.push(self.parse_while_statement());
+                    statements.push(self.parse_while_statement()?);
+                }
+                Token::For => {
+                    statements.push(self.parse_for_statement()?);
+                }
+                Token::Yield => {
+                    statements.push(self.parse_yield_statement()?);
+                }
+                Token::Import => {
+                    return self.error("Imports are only allowed at the top level of a file");
+                }
+                Token::Pub => {
+                    return self.error("Only top-level definitions can be 'pub'");
                 }
                 _ => {
-                    let expr = self.parse_expression(0);
-                    match self.try_parse_assignment(expr) {
+                    let expr = self.parse_expression(0)?;
+                    match self.try_parse_assignment(expr)? {
                         Ok(stmt) => {
                             statements.push(stmt);
                             continue;
@@ -157,14 +338,14 @@ impl<'a> Parser<'a> {
                         Err(expr) => {
                             if self.current_token == Token::SemiColon {
                                 // A statement. For example: "1 + 1;"
-                                self.advance();
+                                self.advance()?;
                                 statements.push(Stmt::Expression(expr));
                             } else {
                                 // An expression. For example: "1 + 1"
                                 if self.current_token == Token::RBrace {
                                     tail_expr = Some(Box::new(expr));
                                 } else {
-                                    panic!("Expected ';' or '}}' after expression");
+                                    return self.error("Expected ';' or '}' after expression");
                                 }
                             }
                         }
@@ -172,64 +353,64 @@ impl<'a> Parser<'a> {
                 }
             }
         }
-        self.expect(Token::RBrace);
-        Expr::Block(statements, tail_expr)
+        self.expect(Token::RBrace)?;
+        Ok(Expr::Block(statements, tail_expr, span))
     }
 
-    fn parse_primary(&mut self) -> Expr {
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
         let token = self.current_token.clone();
-        match token {
+        let span = self.current_span;
+        let expr = match token {
             Token::Int(val) => {
-                self.advance();
-                Expr::Literal(Literal::Int(val))
+                self.advance()?;
+                Expr::Literal(Literal::Int(val), span)
+            }
+            Token::BigInt(val) => {
+                self.advance()?;
+                Expr::Literal(Literal::BigInt(val), span)
             }
             Token::Float(val) => {
-                self.advance();
-                Expr::Literal(Literal::Float(val))
+                self.advance()?;
+                Expr::Literal(Literal::Float(val), span)
             }
             Token::Str(val) => {
-                self.advance();
-                Expr::Literal(Literal::Str(val))
+                self.advance()?;
+                Expr::Literal(Literal::Str(val), span)
             }
             Token::True => {
-                self.advance();
-                Expr::Literal(Literal::Bool(true))
+                self.advance()?;
+                Expr::Literal(Literal::Bool(true), span)
             }
             Token::False => {
-                self.advance();
-                Expr::Literal(Literal::Bool(false))
+                self.advance()?;
+                Expr::Literal(Literal::Bool(false), span)
             }
             Token::Identifier(name) => {
-                self.advance(); // Eat the name.
-                if self.current_token == Token::LParen {
-                    self.advance(); // Eat `(`.
-                    let mut args = Vec::new();
-                    if self.current_token != Token::RParen {
-                        loop {
-                            args.push(self.parse_expression(0));
-                            if self.current_token == Token::Comma {
-                                self.advance();
-                            } else {
-                                break;
-                            }
-                        }
+                self.advance()?; // Eat the name.
+                // A qualified name such as `utils::add`.
+                let mut name = name;
+                while self.current_token == Token::ColonColon {
+                    self.advance()?; // Eat `::`.
+                    match &self.current_token {
+                        Token::Identifier(part) => name = format!("{}::{}", name, part),
+                        _ => return self.error("Expected a name after '::'"),
                     }
-                    self.expect(Token::RParen); // Eat `)`.
-                    Expr::Call(name, args)
+                    self.advance()?;
+                }
+                if self.current_token == Token::LParen {
+                    let args = self.parse_arguments()?;
+                    Expr::Call(name, args, span)
                 } else {
-                    Expr::Variable(name)
+                    Expr::Variable(name, span)
                 }
             }
-            Token::LParen => {
-                self.advance();
-                let expr = self.parse_expression(0);
-                self.expect(Token::RParen);
-                expr
-            }
-            Token::LBrace => self.parse_block(),
-            Token::If => self.parse_if_expression(),
-            _ => panic!("Unexpected token: {:?}", token),
-        }
+            Token::LParen => self.parse_parenthesized()?,
+            Token::LBrace => self.parse_block()?,
+            Token::If => self.parse_if_expression()?,
+            Token::Try => self.parse_try_expression()?,
+            _ => return self.error(format!("Unexpected token: {:?}", token)),
+        };
+        self.parse_postfix(expr)
     }
 
     fn get_precedence(&self) -> u8 {
@@ -258,113 +439,350 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_let_statement(&mut self) -> Stmt {
-        self.advance(); // Eat the `let`.
-        let name = match &self.current_token {
-            Token::Identifier(n) => n.clone(),
-            _ => panic!("Expected variable name after 'let'"),
+    fn parse_let_statement(&mut self) -> Result<Stmt, ParseError> {
+        let doc = self.take_doc();
+        let span = self.current_span;
+        self.advance()?; // Eat the `let`.
+        let name = match self.parse_pattern("after 'let'")? {
+            Pattern::Name(name) => name,
+            // For example: let (q, r) = divmod(7, 2);
+            Pattern::Tuple(patterns) => {
+                let ty = self.parse_annotation()?;
+                self.expect(Token::Eq)?;
+                let value = self.parse_expression(0)?;
+                self.expect(Token::SemiColon)?;
+                return Ok(Stmt::LetTuple(patterns, ty, value, span));
+            }
         };
-        self.advance(); // Eat the `name`.
-        self.expect(Token::Eq);
+        let ty = self.parse_annotation()?;
+        self.expect(Token::Eq)?;
         // Parse the value (RHS).
-        let value = self.parse_expression(0);
-        self.expect(Token::SemiColon);
-        Stmt::Let(name, value)
+        let value = self.parse_expression(0)?;
+        self.expect(Token::SemiColon)?;
+        Ok(Stmt::Let(name, ty, value, doc, Visibility::Private, span))
+    }
+
+    // Parses an optional `: type` annotation.
+    fn parse_annotation(&mut self) -> Result<Option<Type>, ParseError> {
+        if self.current_token != Token::Colon {
+            return Ok(None);
+        }
+        self.advance()?; // Eat the `:`.
+        self.parse_type().map(Some)
+    }
+
+    // For example: int, (), (int, str), fn(int, str) -> bool
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
+        let ty = match &self.current_token {
+            Token::Identifier(name) => match name.as_str() {
+                "int" => Type::Int,
+                "float" => Type::Float,
+                "bool" => Type::Bool,
+                "str" => Type::Str,
+                _ => return self.error(format!("Unknown type '{}'", name)),
+            },
+            Token::LParen => {
+                let (mut items, trailing_comma) = self.parse_tuple(Self::parse_type)?;
+                return Ok(match items.len() {
+                    0 => Type::Unit,
+                    1 if !trailing_comma => items.remove(0),
+                    _ => Type::Tuple(items),
+                });
+            }
+            Token::Fn => {
+                self.advance()?; // Eat `fn`.
+                self.expect(Token::LParen)?;
+                let mut params = Vec::new();
+                while self.current_token != Token::RParen {
+                    params.push(self.parse_type()?);
+                    if self.current_token == Token::Comma {
+                        self.advance()?;
+                    } else {
+                        break;
+                    }
+                }
+                self.expect(Token::RParen)?;
+                let ret = self.parse_return_type()?.unwrap_or(Type::Unit);
+                return Ok(Type::Fn(params, Box::new(ret)));
+            }
+            _ => return self.error(format!("Expected a type, found {:?}", self.current_token)),
+        };
+        self.advance()?;
+        Ok(ty)
     }
 
-    fn parse_expression_statement(&mut self) -> Stmt {
-        let expr = self.parse_expression(0);
-        match self.try_parse_assignment(expr) {
-            Ok(stmt) => stmt,
+    // Parses an optional `-> type`.
+    fn parse_return_type(&mut self) -> Result<Option<Type>, ParseError> {
+        if self.current_token != Token::Arrow {
+            return Ok(None);
+        }
+        self.advance()?; // Eat the `->`.
+        self.parse_type().map(Some)
+    }
+
+    fn parse_expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let expr = self.parse_expression(0)?;
+        match self.try_parse_assignment(expr)? {
+            Ok(stmt) => Ok(stmt),
             Err(expr) => {
-                // Allow omitting semicolon for block-like expressions (If, Block).
-                let is_block_like = matches!(expr, Expr::If(..) | Expr::Block(..));
+                // Allow omitting semicolon for block-like expressions (If, Block, Try).
+                let is_block_like = matches!(expr, Expr::If(..) | Expr::Block(..) | Expr::Try(..));
                 if self.current_token == Token::SemiColon {
-                    self.advance();
-                    Stmt::Expression(expr)
+                    self.advance()?;
+                    Ok(Stmt::Expression(expr))
                 } else if is_block_like {
-                    Stmt::Expression(expr)
+                    Ok(Stmt::Expression(expr))
                 } else if self.current_token == Token::Eof {
-                    Stmt::ImplicitReturn(expr)
+                    Ok(Stmt::ImplicitReturn(expr))
                 } else {
-                    panic!("Expected ';' after expression");
+                    self.error("Expected ';' after expression")
                 }
             }
         }
     }
 
-    fn parse_function_statement(&mut self) -> Stmt {
-        self.advance(); // Eat `fn`.
+    fn parse_function_statement(&mut self) -> Result<Stmt, ParseError> {
+        let doc = self.take_doc();
+        let span = self.current_span;
+        self.advance()?; // Eat `fn`.
         let name = match &self.current_token {
             Token::Identifier(n) => n.clone(),
-            _ => panic!("Expected function name"),
+            _ => return self.error("Expected function name"),
         };
-        self.advance();
+        self.advance()?;
         // Parse parameters (param1, param2, ...).
-        self.expect(Token::LParen);
+        self.expect(Token::LParen)?;
         let mut params = Vec::new();
         if self.current_token != Token::RParen {
             loop {
-                match &self.current_token {
-                    Token::Identifier(param_name) => {
-                        params.push(param_name.clone());
-                        self.advance();
-                    }
-                    _ => panic!("Expected parameter name"),
-                }
+                let pattern = self.parse_pattern("for the parameter")?;
+                let ty = self.parse_annotation()?;
+                params.push(Param { pattern, ty });
                 if self.current_token == Token::Comma {
-                    self.advance();
+                    self.advance()?;
                 } else {
                     break;
                 }
             }
         }
-        self.expect(Token::RParen);
+        self.expect(Token::RParen)?;
+        let ret = self.parse_return_type()?;
         // Parse function body.
+        self.function_depth += 1;
         let body = self.parse_block();
-        Stmt::Fn(name, params, body)
+        self.function_depth -= 1;
+        let body = body?;
+        Ok(Stmt::Fn(
+            name,
+            params,
+            ret,
+            body,
+            doc,
+            Visibility::Private,
+            span,
+        ))
+    }
+
+    /// Parses `pub let ...` or `pub fn ...`.
+    fn parse_public_declaration(&mut self) -> Result<Stmt, ParseError> {
+        // The doc comments before `pub` would be dropped on the next token.
+        let doc = self.take_doc();
+        self.advance()?; // Eat `pub`.
+        let mut stmt = match self.current_token {
+            Token::Let => self.parse_let_statement()?,
+            Token::Fn => self.parse_function_statement()?,
+            _ => return self.error("Expected 'let' or 'fn' after 'pub'"),
+        };
+        if let Stmt::LetTuple(.., span) = stmt {
+            return Err(ParseError {
+                message: "Only single names can be 'pub', not tuples".to_string(),
+                span,
+            });
+        }
+        if let Stmt::Let(_, _, _, stmt_doc, vis, _) | Stmt::Fn(_, _, _, _, stmt_doc, vis, _) =
+            &mut stmt
+        {
+            *stmt_doc = doc;
+            *vis = Visibility::Public;
+        }
+        Ok(stmt)
+    }
+
+    // Parses the parenthesized arguments of a call.
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, ParseError> {
+        self.expect(Token::LParen)?;
+        let mut args = Vec::new();
+        if self.current_token != Token::RParen {
+            loop {
+                args.push(self.parse_expression(0)?);
+                if self.current_token == Token::Comma {
+                    self.advance()?;
+                } else {
+                    break;
+                }
+            }
+        }
+        self.expect(Token::RParen)?;
+        Ok(args)
+    }
+
+    // Parses `(expr)`, or a tuple such as `()`, `(a,)` or `(a, b)`.
+    fn parse_parenthesized(&mut self) -> Result<Expr, ParseError> {
+        let span = self.current_span;
+        let (mut items, trailing_comma) = self.parse_tuple(|parser| parser.parse_expression(0))?;
+        Ok(match items.len() {
+            1 if !trailing_comma => items.remove(0),
+            _ => Expr::Tuple(items, span),
+        })
     }
 
-    fn parse_if_expression(&mut self) -> Expr {
-        self.advance(); // Eat `if`.
-        let condition = self.parse_expression(0);
-        let then_branch = self.parse_block();
+    // Parses a name, or a tuple of patterns such as `(a, (b, c))`.
+    fn parse_pattern(&mut self, context: &str) -> Result<Pattern, ParseError> {
+        match &self.current_token {
+            Token::Identifier(name) => {
+                let name = name.clone();
+                self.advance()?;
+                Ok(Pattern::Name(name))
+            }
+            Token::LParen => {
+                let (mut items, trailing_comma) =
+                    self.parse_tuple(|parser| parser.parse_pattern(context))?;
+                Ok(match items.len() {
+                    1 if !trailing_comma => items.remove(0),
+                    _ => Pattern::Tuple(items),
+                })
+            }
+            _ => self.error(format!("Expected a name or a tuple {}", context)),
+        }
+    }
+
+    // Parses the comma-separated items between `(` and `)`, and whether the
+    // last one was followed by a comma.
+    fn parse_tuple<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<(Vec<T>, bool), ParseError> {
+        self.expect(Token::LParen)?;
+        let mut items = Vec::new();
+        let mut trailing_comma = false;
+        while self.current_token != Token::RParen {
+            items.push(item(self)?);
+            trailing_comma = self.current_token == Token::Comma;
+            if trailing_comma {
+                self.advance()?;
+            } else {
+                break;
+            }
+        }
+        self.expect(Token::RParen)?;
+        Ok((items, trailing_comma))
+    }
+
+    // Parses any number of postfix `?` operators, `.name(args)` method calls
+    // and `.0` tuple indexes after `expr`.
+    fn parse_postfix(&mut self, mut expr: Expr) -> Result<Expr, ParseError> {
+        loop {
+            match self.current_token {
+                Token::Question => {
+                    let span = self.current_span;
+                    self.advance()?; // Eat `?`.
+                    expr = Expr::Propagate(Box::new(expr), span);
+                }
+                Token::Dot => {
+                    self.advance()?; // Eat `.`.
+                    let span = self.current_span;
+                    let name = match &self.current_token {
+                        Token::Identifier(name) => name.clone(),
+                        Token::Int(index) => {
+                            let index = *index as usize;
+                            self.advance()?;
+                            expr = Expr::TupleIndex(Box::new(expr), index, span);
+                            continue;
+                        }
+                        _ => {
+                            return self.error("Expected a method name or a tuple index after '.'");
+                        }
+                    };
+                    self.advance()?;
+                    if self.current_token != Token::LParen {
+                        return self.error(format!("Expected '(' after method name '{}'", name));
+                    }
+                    let mut args = vec![expr];
+                    args.extend(self.parse_arguments()?);
+                    expr = Expr::MethodCall(name, args, span);
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn parse_try_expression(&mut self) -> Result<Expr, ParseError> {
+        let span = self.current_span;
+        self.advance()?; // Eat `try`.
+        let body = self.parse_block()?;
+        if self.current_token != Token::Catch {
+            return self.error("Expected 'catch' after 'try' block");
+        }
+        self.advance()?; // Eat `catch`.
+        let name = match &self.current_token {
+            Token::Identifier(name) => name.clone(),
+            _ => return self.error("Expected a name for the caught error"),
+        };
+        self.advance()?;
+        let handler = self.parse_block()?;
+        Ok(Expr::Try(Box::new(body), name, Box::new(handler), span))
+    }
+
+    fn parse_if_expression(&mut self) -> Result<Expr, ParseError> {
+        let span = self.current_span;
+        self.advance()?; // Eat `if`.
+        let condition = self.parse_expression(0)?;
+        let then_branch = self.parse_block()?;
         let else_branch = if self.current_token == Token::Else {
-            self.advance(); // Eat `else`.
+            self.advance()?; // Eat `else`.
             if self.current_token == Token::If {
-                Some(Box::new(self.parse_if_expression()))
+                Some(Box::new(self.parse_if_expression()?))
             } else {
-                Some(Box::new(self.parse_block()))
+                Some(Box::new(self.parse_block()?))
             }
         } else {
             None
         };
-        Expr::If(Box::new(condition), Box::new(then_branch), else_branch)
+        Ok(Expr::If(
+            Box::new(condition),
+            Box::new(then_branch),
+            else_branch,
+            span,
+        ))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ast::{BinaryOp, Expr, Literal, Stmt, UnaryOp};
+    use crate::ast::{BinaryOp, Expr, Literal, Param, Pattern, Stmt, Type, UnaryOp};
     use crate::lexer::Lexer;
 
-    fn parse_helper(input: &str) -> Vec<Stmt> {
+    fn try_parse(input: &str) -> Result<Vec<Stmt>, ParseError> {
         let lexer = Lexer::new(input);
-        let mut parser = Parser::new(lexer);
+        let mut parser = Parser::new(lexer)?;
         parser.parse_program()
     }
 
+    fn parse_helper(input: &str) -> Vec<Stmt> {
+        try_parse(input).unwrap_or_else(|err| panic!("{}", err))
+    }
+
     #[test]
     fn test_let_statement() {
         let input = "let x = 5;";
         let statements = parse_helper(input);
         assert_eq!(statements.len(), 1);
         match &statements[0] {
-            Stmt::Let(name, expr) => {
+            Stmt::Let(name, None, expr, ..) => {
                 assert_eq!(name, "x");
                 match expr {
-                    Expr::Literal(Literal::Int(val)) => assert_eq!(*val, 5),
+                    Expr::Literal(Literal::Int(val), _) => assert_eq!(*val, 5),
                     _ => panic!("Expected integer literal"),
                 }
             }
@@ -384,18 +802,18 @@ mod tests {
                     Expr::Binary(lhs, op, rhs) => {
                         assert_eq!(*op, BinaryOp::Add);
                         match &**lhs {
-                            Expr::Literal(Literal::Int(v)) => assert_eq!(*v, 1),
+                            Expr::Literal(Literal::Int(v), _) => assert_eq!(*v, 1),
                             _ => panic!("Left side should be 1"),
                         }
                         match &**rhs {
                             Expr::Binary(r_lhs, r_op, r_rhs) => {
                                 assert_eq!(*r_op, BinaryOp::Mul);
                                 match &**r_lhs {
-                                    Expr::Literal(Literal::Int(v)) => assert_eq!(*v, 2),
+                                    Expr::Literal(Literal::Int(v), _) => assert_eq!(*v, 2),
                                     _ => panic!("Inner left should be 2"),
                                 }
                                 match &**r_rhs {
-                                    Expr::Literal(Literal::Int(v)) => assert_eq!(*v, 3),
+                                    Expr::Literal(Literal::Int(v), _) => assert_eq!(*v, 3),
                                     _ => panic!("Inner right should be 3"),
                                 }
                             }
@@ -419,11 +837,11 @@ mod tests {
                 Expr::Binary(lhs, op, rhs) => {
                     assert_eq!(*op, BinaryOp::Add);
                     match &**lhs {
-                        Expr::Variable(name) => assert_eq!(name, "x"),
+                        Expr::Variable(name, _) => assert_eq!(name, "x"),
                         _ => panic!("Expected variable"),
                     }
                     match &**rhs {
-                        Expr::Literal(Literal::Int(v)) => assert_eq!(*v, 5),
+                        Expr::Literal(Literal::Int(v), _) => assert_eq!(*v, 5),
                         _ => panic!("Expected 5"),
                     }
                 }
@@ -438,10 +856,10 @@ mod tests {
         let input = "-5;";
         let statements = parse_helper(input);
         match &statements[0] {
-            Stmt::Expression(Expr::Unary(op, expr)) => {
+            Stmt::Expression(Expr::Unary(op, expr, _)) => {
                 assert_eq!(*op, UnaryOp::Neg);
                 match &**expr {
-                    Expr::Literal(Literal::Int(v)) => assert_eq!(*v, 5),
+                    Expr::Literal(Literal::Int(v), _) => assert_eq!(*v, 5),
                     _ => panic!("Expected 5"),
                 }
             }
@@ -462,17 +880,17 @@ mod tests {
         let statements = parse_helper(input);
         assert_eq!(statements.len(), 1);
         match &statements[0] {
-            Stmt::While(cond, body) => {
+            Stmt::While(cond, body, _) => {
                 match cond {
-                    Expr::Literal(Literal::Bool(b)) => assert_eq!(*b, true),
+                    Expr::Literal(Literal::Bool(b), _) => assert!(*b),
                     _ => panic!("Expected boolean literal"),
                 }
                 match body {
-                    Expr::Block(stmts, tail) => {
+                    Expr::Block(stmts, tail, _) => {
                         assert_eq!(stmts.len(), 0);
                         match tail {
                             Some(expr) => match &**expr {
-                                Expr::Literal(Literal::Int(v)) => assert_eq!(*v, 1),
+                                Expr::Literal(Literal::Int(v), _) => assert_eq!(*v, 1),
                                 _ => panic!("Expected 1"),
                             },
                             None => panic!("Expected tail expression"),
@@ -491,10 +909,10 @@ mod tests {
         let input = "x = 5;";
         let statements = parse_helper(input);
         match &statements[0] {
-            Stmt::Assign(name, expr) => {
+            Stmt::Assign(name, expr, _) => {
                 assert_eq!(name, "x");
                 match expr {
-                    Expr::Literal(Literal::Int(v)) => assert_eq!(*v, 5),
+                    Expr::Literal(Literal::Int(v), _) => assert_eq!(*v, 5),
                     _ => panic!("Expected 5"),
                 }
             }
@@ -505,18 +923,18 @@ mod tests {
         let input = "x += 1;";
         let statements = parse_helper(input);
         match &statements[0] {
-            Stmt::Assign(name, expr) => {
+            Stmt::Assign(name, expr, _) => {
                 assert_eq!(name, "x");
                 // x += 1 parses to x = x + 1
                 match expr {
                     Expr::Binary(lhs, op, rhs) => {
                         assert_eq!(*op, BinaryOp::Add);
                         match &**lhs {
-                            Expr::Variable(n) => assert_eq!(n, "x"),
+                            Expr::Variable(n, _) => assert_eq!(n, "x"),
                             _ => panic!("Expected variable x"),
                         }
                         match &**rhs {
-                            Expr::Literal(Literal::Int(v)) => assert_eq!(*v, 1),
+                            Expr::Literal(Literal::Int(v), _) => assert_eq!(*v, 1),
                             _ => panic!("Expected 1"),
                         }
                     }
@@ -538,18 +956,18 @@ mod tests {
                     Expr::Binary(lhs, op, rhs) => {
                         assert_eq!(*op, BinaryOp::Or);
                         match &**lhs {
-                            Expr::Literal(Literal::Bool(b)) => assert!(b),
+                            Expr::Literal(Literal::Bool(b), _) => assert!(b),
                             _ => panic!("Expected true"),
                         }
                         match &**rhs {
                             Expr::Binary(r_lhs, r_op, r_rhs) => {
                                 assert_eq!(*r_op, BinaryOp::And);
                                 match &**r_lhs {
-                                    Expr::Literal(Literal::Bool(b)) => assert!(!b),
+                                    Expr::Literal(Literal::Bool(b), _) => assert!(!b),
                                     _ => panic!("Expected false"),
                                 }
                                 match &**r_rhs {
-                                    Expr::Literal(Literal::Bool(b)) => assert!(!b),
+                                    Expr::Literal(Literal::Bool(b), _) => assert!(!b),
                                     _ => panic!("Expected false"),
                                 }
                             }
@@ -562,4 +980,293 @@ mod tests {
             _ => panic!("Expected Expression statement"),
         }
     }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = try_parse("let x = ;").unwrap_err();
+        assert_eq!(err.message, "Unexpected token: SemiColon");
+        assert_eq!(err.span, Span { line: 1, col: 9 });
+
+        let err = try_parse("fn f() {\n    1 2\n}").unwrap_err();
+        assert_eq!(err.to_string(), "2:7: Expected ';' or '}' after expression");
+
+        // Lexer errors are reported through the parser.
+        let err = try_parse("let x = 0b2;").unwrap_err();
+        assert_eq!(err.to_string(), "1:9: Invalid digit '2' in binary literal");
+
+        let err = try_parse("let x: integer = 1;").unwrap_err();
+        assert_eq!(err.to_string(), "1:8: Unknown type 'integer'");
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        let statements =
+            parse_helper("let x: float = 1.0;\nfn apply(f: fn(int) -> int, n) -> () { f(n); }");
+        assert!(matches!(
+            &statements[0],
+            Stmt::Let(_, Some(Type::Float), ..)
+        ));
+        match &statements[1] {
+            Stmt::Fn(_, params, ret, ..) => {
+                assert_eq!(
+                    params[0].ty,
+                    Some(Type::Fn(vec![Type::Int], Box::new(Type::Int)))
+                );
+                assert_eq!(
+                    params[1],
+                    Param {
+                        pattern: Pattern::Name("n".to_string()),
+                        ty: None
+                    }
+                );
+                assert_eq!(*ret, Some(Type::Unit));
+            }
+            _ => panic!("Expected Fn statement"),
+        }
+    }
+
+    #[test]
+    fn test_imports() {
+        let statements = parse_helper(
+            "import \"lib/strings.toy\";\nimport lib::math::{add, mul};\nmath::add(strings::x, 1)",
+        );
+        assert_eq!(
+            statements[0],
+            Stmt::Import(
+                Import::File("lib/strings.toy".to_string()),
+                Span { line: 1, col: 1 }
+            )
+        );
+        let Stmt::Import(import, _) = &statements[1] else {
+            panic!("Expected an import");
+        };
+        assert_eq!(import.path(), "lib/math.toy");
+        assert_eq!(import.namespace(), "math");
+        assert_eq!(import.names(), ["add", "mul"]);
+        match &statements[2] {
+            Stmt::ImplicitReturn(Expr::Call(name, args, _)) => {
+                assert_eq!(name, "math::add");
+                assert!(matches!(&args[0], Expr::Variable(name, _) if name == "strings::x"));
+            }
+            other => panic!("Expected a qualified call, got {:?}", other),
+        }
+        assert_eq!(
+            try_parse("fn f() { import \"a.toy\"; }")
+                .unwrap_err()
+                .to_string(),
+            "1:10: Imports are only allowed at the top level of a file"
+        );
+        assert_eq!(
+            try_parse("utils::x = 1;").unwrap_err().to_string(),
+            "1:10: Cannot assign to 'utils::x' outside of its module"
+        );
+        assert_eq!(
+            try_parse("import utils::{};").unwrap_err().to_string(),
+            "1:16: Expected the names to import"
+        );
+    }
+
+    #[test]
+    fn test_visibility() {
+        let statements =
+            parse_helper("/// Exported.\npub fn f() { 1 }\npub let x = 2;\nlet y = 3;");
+        match &statements[0] {
+            Stmt::Fn(name, _, _, _, doc, vis, span) => {
+                assert_eq!(name, "f");
+                assert_eq!(doc.as_deref(), Some("Exported."));
+                assert_eq!(*vis, Visibility::Public);
+                assert_eq!(*span, Span { line: 2, col: 5 });
+            }
+            other => panic!("Expected a function, got {:?}", other),
+        }
+        assert!(matches!(
+            &statements[1],
+            Stmt::Let(_, _, _, _, Visibility::Public, _)
+        ));
+        assert!(matches!(
+            &statements[2],
+            Stmt::Let(_, _, _, _, Visibility::Private, _)
+        ));
+        assert_eq!(
+            try_parse("pub x + 1;").unwrap_err().to_string(),
+            "1:5: Expected 'let' or 'fn' after 'pub'"
+        );
+        assert_eq!(
+            try_parse("fn f() { pub let x = 1; x }")
+                .unwrap_err()
+                .to_string(),
+            "1:10: Only top-level definitions can be 'pub'"
+        );
+    }
+
+    #[test]
+    fn test_try_and_propagate() {
+        let statements = parse_helper("try { -f()?? } catch e { e }\nlet x = 1;");
+        match &statements[0] {
+            Stmt::Expression(Expr::Try(body, name, _, span)) => {
+                assert_eq!(name, "e");
+                assert_eq!(*span, Span { line: 1, col: 1 });
+                let Expr::Block(_, Some(tail), _) = body.as_ref() else {
+                    panic!("Expected a block with a tail");
+                };
+                // `?` binds tighter than unary minus.
+                let Expr::Unary(UnaryOp::Neg, inner, _) = tail.as_ref() else {
+                    panic!("Expected negation");
+                };
+                let Expr::Propagate(inner, span) = inner.as_ref() else {
+                    panic!("Expected `?`");
+                };
+                assert_eq!(*span, Span { line: 1, col: 12 });
+                assert!(
+                    matches!(inner.as_ref(), Expr::Propagate(call, _) if matches!(call.as_ref(), Expr::Call(..)))
+                );
+            }
+            other => panic!("Expected a try expression, got {:?}", other),
+        }
+        assert_eq!(
+            try_parse("try { 1 } e { 2 }").unwrap_err().to_string(),
+            "1:11: Expected 'catch' after 'try' block"
+        );
+    }
+
+    #[test]
+    fn test_for_yield_and_methods() {
+        let statements = parse_helper("fn evens(xs) { for x in xs.filter(even)? { yield x; } }");
+        let Stmt::Fn(_, _, _, Expr::Block(body, None, _), ..) = &statements[0] else {
+            panic!("Expected a function");
+        };
+        let Stmt::For(pattern, iterable, Expr::Block(loop_body, None, _), span) = &body[0] else {
+            panic!("Expected a for loop, got {:?}", body[0]);
+        };
+        assert_eq!(*pattern, Pattern::Name("x".to_string()));
+        assert_eq!(*span, Span { line: 1, col: 16 });
+        let Expr::Propagate(call, _) = iterable else {
+            panic!("Expected `?`");
+        };
+        let Expr::MethodCall(method, args, span) = call.as_ref() else {
+            panic!("Expected a method call");
+        };
+        assert_eq!(method, "filter");
+        // The receiver is the first argument.
+        assert!(matches!(&args[..], [Expr::Variable(name, _), _] if name == "xs"));
+        assert_eq!(*span, Span { line: 1, col: 28 });
+        assert!(matches!(&loop_body[0], Stmt::Yield(Expr::Variable(name, _), _) if name == "x"));
+
+        assert_eq!(
+            try_parse("yield 1;").unwrap_err().to_string(),
+            "1:1: 'yield' is only allowed inside functions"
+        );
+        assert_eq!(
+            try_parse("for x xs {}").unwrap_err().to_string(),
+            "1:7: Expected 'in' after the loop variable"
+        );
+        assert_eq!(
+            try_parse("xs.len;").unwrap_err().to_string(),
+            "1:7: Expected '(' after method name 'len'"
+        );
+    }
+
+    #[test]
+    fn test_tuples_and_patterns() {
+        let statements = parse_helper(
+            "let (q, (r,)): (int, (int,)) = (f(), (1,));\nfn swap((a, b)) { (b, a).1 }\nfor (i, x) in xs { () }",
+        );
+        let name = |name: &str| Pattern::Name(name.to_string());
+        let Stmt::LetTuple(patterns, Some(ty), Expr::Tuple(items, _), _) = &statements[0] else {
+            panic!("Expected a tuple let, got {:?}", statements[0]);
+        };
+        assert_eq!(*patterns, vec![name("q"), Pattern::Tuple(vec![name("r")])]);
+        assert_eq!(
+            *ty,
+            Type::Tuple(vec![Type::Int, Type::Tuple(vec![Type::Int])])
+        );
+        assert!(matches!(&items[1], Expr::Tuple(inner, _) if inner.len() == 1));
+
+        let Stmt::Fn(_, params, _, Expr::Block(_, Some(tail), _), ..) = &statements[1] else {
+            panic!("Expected a function");
+        };
+        assert_eq!(
+            params[0].pattern,
+            Pattern::Tuple(vec![name("a"), name("b")])
+        );
+        let Expr::TupleIndex(tuple, 1, span) = &**tail else {
+            panic!("Expected a tuple index, got {:?}", tail);
+        };
+        assert!(matches!(&**tuple, Expr::Tuple(items, _) if items.len() == 2));
+        assert_eq!(*span, Span { line: 2, col: 26 });
+
+        let Stmt::For(pattern, _, Expr::Block(_, Some(unit), _), _) = &statements[2] else {
+            panic!("Expected a for loop");
+        };
+        assert_eq!(*pattern, Pattern::Tuple(vec![name("i"), name("x")]));
+        assert!(matches!(&**unit, Expr::Tuple(items, _) if items.is_empty()));
+
+        // Parentheses around a single item do not make a tuple.
+        assert!(matches!(
+            &parse_helper("let x = (1);")[0],
+            Stmt::Let(_, _, Expr::Literal(..), ..)
+        ));
+        assert_eq!(
+            try_parse("pub let (a, b) = t;").unwrap_err().to_string(),
+            "1:5: Only single names can be 'pub', not tuples"
+        );
+        assert_eq!(
+            try_parse("let (a, 1) = t;").unwrap_err().to_string(),
+            "1:9: Expected a name or a tuple after 'let'"
+        );
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let input = "
+            //! Helpers for shapes.
+            //! Second line.
+
+            /// The answer.
+            let x = 42;
+
+            /// Adds two numbers.
+            ///
+            /// Returns the sum.
+            fn add(a, b) {
+                /* not a doc */ a + b
+            }
+
+            /// Dangling docs are dropped.
+            x + 1;
+            // Plain comment.
+            let y = 1;
+        ";
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer).unwrap();
+        let statements = parser.parse_program().unwrap();
+        assert_eq!(
+            parser.module_doc(),
+            Some("Helpers for shapes.\nSecond line.".to_string())
+        );
+        match &statements[0] {
+            Stmt::Let(_, _, _, doc, ..) => assert_eq!(doc.as_deref(), Some("The answer.")),
+            _ => panic!("Expected Let statement"),
+        }
+        match &statements[1] {
+            Stmt::Fn(name, _, _, _, doc, ..) => {
+                assert_eq!(name, "add");
+                assert_eq!(
+                    doc.as_deref(),
+                    Some("Adds two numbers.\n\nReturns the sum.")
+                );
+            }
+            _ => panic!("Expected Fn statement"),
+        }
+        match &statements[3] {
+            Stmt::Let(_, _, _, doc, ..) => assert_eq!(*doc, None),
+            _ => panic!("Expected Let statement"),
+        }
+
+        let err = try_parse("let x = 1;\n//! Too late.").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2:1: Inner doc comments ('//!') must come first in the file"
+        );
+    }
 }
@@ -0,0 +1,207 @@
+//! What a script is allowed to touch outside the interpreter.
+//!
+//! Everything is denied unless the host grants it. Builtins that need a
+//! capability are only registered when it is granted, and file access is
+//! further limited to the directories it was granted for.
+
+use crate::interpreter::RuntimeError;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// A kind of access to the world outside the interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Read,
+    Write,
+    Env,
+    Process,
+    Clock,
+}
+
+impl Capability {
+    /// The command line flag that grants this capability.
+    pub fn flag(self) -> &'static str {
+        match self {
+            Capability::Read => "--allow-read",
+            Capability::Write => "--allow-write",
+            Capability::Env => "--allow-env",
+            Capability::Process => "--allow-run",
+            Capability::Clock => "--allow-clock",
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.flag())
+    }
+}
+
+/// Where files may be read or written.
+#[derive(Debug, Clone, Default)]
+enum PathGrant {
+    #[default]
+    Nowhere,
+    /// Inside these directories, stored as absolute paths.
+    Within(Vec<PathBuf>),
+    Anywhere,
+}
+
+impl PathGrant {
+    fn add(&mut self, dir: Option<&Path>) {
+        let Some(dir) = dir else {
+            *self = PathGrant::Anywhere;
+            return;
+        };
+        let dir = resolve(dir);
+        match self {
+            PathGrant::Nowhere => *self = PathGrant::Within(vec![dir]),
+            PathGrant::Within(dirs) => dirs.push(dir),
+            PathGrant::Anywhere => {}
+        }
+    }
+
+    fn covers(&self, path: &Path) -> bool {
+        match self {
+            PathGrant::Nowhere => false,
+            PathGrant::Within(dirs) => dirs.iter().any(|dir| path.starts_with(dir)),
+            PathGrant::Anywhere => true,
+        }
+    }
+}
+
+/// The capabilities granted to a script. The default grants nothing.
+#[derive(Debug, Clone, Default)]
+pub struct Permissions {
+    read: PathGrant,
+    write: PathGrant,
+    env: bool,
+    process: bool,
+    clock: bool,
+}
+
+impl Permissions {
+    /// Grants every capability, for tools that only inspect scripts.
+    pub fn all() -> Self {
+        Permissions {
+            read: PathGrant::Anywhere,
+            write: PathGrant::Anywhere,
+            env: true,
+            process: true,
+            clock: true,
+        }
+    }
+
+    /// Grants `capability`. For file access, `dir` limits it to one
+    /// directory; `None` allows any path.
+    pub fn allow(&mut self, capability: Capability, dir: Option<&Path>) {
+        match capability {
+            Capability::Read => self.read.add(dir),
+            Capability::Write => self.write.add(dir),
+            Capability::Env => self.env = true,
+            Capability::Process => self.process = true,
+            Capability::Clock => self.clock = true,
+        }
+    }
+
+    /// Whether `capability` is granted for at least some use.
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Read => !matches!(self.read, PathGrant::Nowhere),
+            Capability::Write => !matches!(self.write, PathGrant::Nowhere),
+            Capability::Env => self.env,
+            Capability::Process => self.process,
+            Capability::Clock => self.clock,
+        }
+    }
+
+    /// Checks that `path` may be read and returns it made absolute.
+    pub fn check_read(&self, path: &str) -> Result<PathBuf, RuntimeError> {
+        check_path(&self.read, path, "read", Capability::Read)
+    }
+
+    /// Checks that `path` may be written and returns it made absolute.
+    pub fn check_write(&self, path: &str) -> Result<PathBuf, RuntimeError> {
+        check_path(&self.write, path, "write", Capability::Write)
+    }
+}
+
+fn check_path(
+    grant: &PathGrant,
+    path: &str,
+    action: &str,
+    capability: Capability,
+) -> Result<PathBuf, RuntimeError> {
+    let resolved = resolve(Path::new(path));
+    if grant.covers(&resolved) {
+        Ok(resolved)
+    } else {
+        Err(RuntimeError::PermissionDenied(format!(
+            "cannot {} '{}' (grant access with {})",
+            action, path, capability
+        )))
+    }
+}
+
+/// Makes `path` absolute and removes `.` and `..`, following symbolic links
+/// in the part of it that exists so that they cannot lead out of a granted
+/// directory.
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut resolved = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => {
+                resolved.push(component);
+                if let Ok(real) = resolved.canonicalize() {
+                    resolved = real;
+                }
+            }
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deny_by_default() {
+        let permissions = Permissions::default();
+        for capability in [
+            Capability::Read,
+            Capability::Write,
+            Capability::Env,
+            Capability::Process,
+            Capability::Clock,
+        ] {
+            assert!(!permissions.allows(capability));
+        }
+        assert!(permissions.check_read("tests/print.toy").is_err());
+    }
+
+    #[test]
+    fn test_path_grants() {
+        let mut permissions = Permissions::default();
+        permissions.allow(Capability::Read, Some(Path::new("./tests")));
+        assert!(permissions.allows(Capability::Read));
+        assert!(!permissions.allows(Capability::Write));
+        assert!(permissions.check_read("tests/print.toy").is_ok());
+        assert!(permissions.check_read("tests/../tests/missing.toy").is_ok());
+        assert_eq!(
+            permissions.check_read("tests/../Cargo.toml"),
+            Err(RuntimeError::PermissionDenied(
+                "cannot read 'tests/../Cargo.toml' (grant access with --allow-read)".to_string()
+            ))
+        );
+        assert!(permissions.check_write("tests/out.txt").is_err());
+
+        permissions.allow(Capability::Write, None);
+        assert!(permissions.check_write("/tmp/out.txt").is_ok());
+    }
+}
@@ -0,0 +1,104 @@
+//! Helpers written in toy and bundled into the binary.
+//!
+//! The prelude is evaluated before the script, in the script's global
+//! environment, and its definitions are also given to every file the script
+//! imports. A script can redefine any of them.
+
+use crate::ast::Stmt;
+use crate::interpreter::{Environment, RuntimeError, eval_statement};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::permissions::Permissions;
+
+/// The source of the prelude.
+pub const SOURCE: &str = include_str!("prelude.toy");
+
+/// Parses the prelude. It is part of the binary, so failing to parse it is a
+/// bug in the interpreter rather than in the script.
+fn parse() -> Vec<Stmt> {
+    Parser::new(Lexer::new(SOURCE))
+        .and_then(|mut parser| parser.parse_program())
+        .unwrap_or_else(|err| panic!("The prelude does not parse: {}", err))
+}
+
+/// Defines the prelude's functions in `env` and in the environments of the
+/// files it imports.
+pub fn load(env: &mut Environment) -> Result<(), RuntimeError> {
+    let mut names = Vec::new();
+    for stmt in parse() {
+        if let Stmt::Fn(name, ..) | Stmt::Let(name, ..) = &stmt {
+            names.push(name.clone());
+        }
+        eval_statement(stmt, env)?;
+    }
+    let definitions = names
+        .into_iter()
+        .filter_map(|name| Some((name.clone(), env.get(&name)?)))
+        .collect();
+    env.set_prelude(definitions);
+    Ok(())
+}
+
+/// An environment with every builtin and the prelude, for tools that look
+/// at scripts without running them.
+pub fn globals() -> Environment {
+    let mut env = Environment::with_permissions(Permissions::all());
+    load(&mut env).unwrap_or_else(|err| panic!("The prelude does not run: {}", err));
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Value, eval_in};
+
+    fn eval(input: &str) -> Result<Value, RuntimeError> {
+        let mut env = Environment::new();
+        load(&mut env)?;
+        eval_in(input, &mut env)
+    }
+
+    #[test]
+    fn test_prelude_parses() {
+        let program = parse();
+        assert!(
+            program
+                .iter()
+                .all(|stmt| matches!(stmt, Stmt::Fn(_, _, _, _, Some(_), _, _))),
+            "every prelude definition is a documented function"
+        );
+        assert_eq!(
+            crate::formatter::format_source(SOURCE).as_deref(),
+            Ok(SOURCE)
+        );
+    }
+
+    #[test]
+    fn test_prelude_runs() {
+        assert_eq!(eval("range_sum(1, 11)"), Ok(Value::Int(55)));
+        assert_eq!(eval("abs(-3) + min(4, 2) + max(4, 2)"), Ok(Value::Int(9)));
+        assert_eq!(
+            eval("fn add(a, b) { a + b } reduce(list(1, 2, 3), 10, add)"),
+            Ok(Value::Int(16))
+        );
+        assert_eq!(
+            eval(
+                "fn double(n) { n * 2 } fn big(n) { n > 2 }
+                 sum(filter(map(list(1, 2, 3), double), big))"
+            ),
+            Ok(Value::Int(10))
+        );
+        assert_eq!(eval("assert(true); assert_eq(2, 1 + 1)"), Ok(Value::Unit));
+        assert_eq!(
+            eval("assert_eq(2, 3)").map_err(|err| err.cause().clone()),
+            Err(RuntimeError::Raised(Value::Str(
+                "assertion failed: 2 != 3".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_prelude_can_be_redefined() {
+        assert_eq!(eval("fn abs(n) { 0 } abs(-3)"), Ok(Value::Int(0)));
+    }
+}
@@ -0,0 +1,126 @@
+//! Builtins that connect a script to the process running it.
+
+use crate::interpreter::{Environment, RuntimeError, Value, str_operand};
+use crate::iterators::Iter;
+use std::env;
+use std::io;
+
+/// Returns the command line arguments given after the script.
+pub fn args(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    no_operands(args)?;
+    Ok(Value::List(
+        env.script_args().iter().cloned().map(Value::Str).collect(),
+    ))
+}
+
+/// Stops the script with the given exit status.
+pub fn exit(args: Vec<Value>, _: &Environment) -> Result<Value, RuntimeError> {
+    match args.as_slice() {
+        [Value::Int(code)] => match i32::try_from(*code) {
+            Ok(code) => Err(RuntimeError::Exit(code)),
+            Err(_) => Err(RuntimeError::TypeMismatch(format!(
+                "exit status {} is out of range",
+                code
+            ))),
+        },
+        [other] => Err(RuntimeError::TypeMismatch(format!(
+            "exit expects an integer, got {:?}",
+            other
+        ))),
+        _ => Err(RuntimeError::ArgumentMismatch(1, args.len())),
+    }
+}
+
+/// Returns the value of an environment variable, including those the
+/// script set itself.
+pub fn env_var(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    let name = str_operand("env_var", args)?;
+    let value = match env.env_var_override(&name) {
+        Some(value) => Some(value),
+        None => env::var(&name).ok(),
+    };
+    Ok(match value {
+        Some(value) => Value::Ok(Box::new(Value::Str(value))),
+        None => Value::Err(Box::new(Value::Str(format!(
+            "environment variable '{}' is not set",
+            name
+        )))),
+    })
+}
+
+/// Sets an environment variable for the rest of the script. The variables
+/// of the process itself are left alone.
+pub fn set_env_var(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    match args.as_slice() {
+        [Value::Str(name), Value::Str(value)] => {
+            env.set_env_var_override(name.clone(), value.clone());
+            Ok(Value::Unit)
+        }
+        [_, _] => Err(RuntimeError::TypeMismatch(format!(
+            "set_env_var expects a name and a string, got {:?}",
+            args
+        ))),
+        _ => Err(RuntimeError::ArgumentMismatch(2, args.len())),
+    }
+}
+
+/// Reads the next line of input, without its line ending. At the end of the
+/// input, returns an `Err` saying so.
+pub fn read_line(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    no_operands(args)?;
+    Ok(match next_line(env) {
+        Ok(Some(line)) => Value::Ok(Box::new(Value::Str(line))),
+        Ok(None) => Value::Err(Box::new(Value::Str(END_OF_INPUT.to_string()))),
+        Err(err) => input_error(err),
+    })
+}
+
+/// Reads the rest of the input as one string.
+pub fn read_all(args: Vec<Value>, env: &Environment) -> Result<Value, RuntimeError> {
+    no_operands(args)?;
+    let mut text = String::new();
+    Ok(match env.input().read_to_string(&mut text) {
+        Ok(_) => Value::Ok(Box::new(Value::Str(text))),
+        Err(err) => input_error(err),
+    })
+}
+
+/// Returns an iterator over the rest of the input, which reads a line each
+/// time an item is asked for.
+pub fn stdin_lines(args: Vec<Value>, _: &Environment) -> Result<Value, RuntimeError> {
+    no_operands(args)?;
+    Ok(Iter::lines())
+}
+
+/// Reads the next line of input without its line ending, or `None` at the
+/// end of the input.
+pub fn next_line(env: &Environment) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    Ok(match env.input().read_line(&mut line)? {
+        0 => None,
+        _ => Some(strip_line_ending(line)),
+    })
+}
+
+const END_OF_INPUT: &str = "end of input";
+
+fn no_operands(args: Vec<Value>) -> Result<(), RuntimeError> {
+    match args.len() {
+        0 => Ok(()),
+        count => Err(RuntimeError::ArgumentMismatch(0, count)),
+    }
+}
+
+fn strip_line_ending(mut line: String) -> String {
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    line
+}
+
+pub fn input_error(err: io::Error) -> Value {
+    Value::Err(Box::new(Value::Str(format!("cannot read input: {}", err))))
+}
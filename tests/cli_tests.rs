@@ -1,9 +1,10 @@
-use std::process::Command;
+use std::io::Write;
+use std::process::{Command, Stdio};
 
 #[test]
 fn test_cli_recursion() {
     let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
-        .args(&["tests/recursion.toy"])
+        .args(["tests/recursion.toy"])
         .output()
         .expect("Failed to run binary");
 
@@ -15,7 +16,7 @@ fn test_cli_recursion() {
 #[test]
 fn test_cli_math() {
     let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
-        .args(&["tests/math.toy"])
+        .args(["tests/math.toy"])
         .output()
         .expect("Failed to run binary");
 
@@ -27,7 +28,7 @@ fn test_cli_math() {
 #[test]
 fn test_cli_strings() {
     let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
-        .args(&["tests/strings.toy"])
+        .args(["tests/strings.toy"])
         .output()
         .expect("Failed to run binary");
 
@@ -39,7 +40,7 @@ fn test_cli_strings() {
 #[test]
 fn test_cli_control_flow() {
     let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
-        .args(&["tests/control_flow.toy"])
+        .args(["tests/control_flow.toy"])
         .output()
         .expect("Failed to run binary");
 
@@ -51,7 +52,7 @@ fn test_cli_control_flow() {
 #[test]
 fn test_cli_assignment() {
     let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
-        .args(&["tests/assignment.toy"])
+        .args(["tests/assignment.toy"])
         .output()
         .expect("Failed to run binary");
 
@@ -63,7 +64,7 @@ fn test_cli_assignment() {
 #[test]
 fn test_cli_comments() {
     let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
-        .args(&["tests/comments.toy"])
+        .args(["tests/comments.toy"])
         .output()
         .expect("Failed to run binary");
 
@@ -75,7 +76,7 @@ fn test_cli_comments() {
 #[test]
 fn test_cli_logical() {
     let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
-        .args(&["tests/logical.toy"])
+        .args(["tests/logical.toy"])
         .output()
         .expect("Failed to run binary");
 
@@ -87,7 +88,7 @@ fn test_cli_logical() {
 #[test]
 fn test_cli_loop() {
     let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
-        .args(&["tests/loop.toy"])
+        .args(["tests/loop.toy"])
         .output()
         .expect("Failed to run binary");
 
@@ -99,7 +100,7 @@ fn test_cli_loop() {
 #[test]
 fn test_cli_print() {
     let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
-        .args(&["tests/print.toy"])
+        .args(["tests/print.toy"])
         .output()
         .expect("Failed to run binary");
 
@@ -107,3 +108,719 @@ fn test_cli_print() {
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert_eq!(stdout, "Hello, World!\nThe answer is: 42\nDone.\n");
 }
+
+#[test]
+fn test_cli_division_by_zero() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/division_by_zero.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.trim(), "5");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        "Runtime error: Division by zero\n    at average (tests/division_by_zero.toy:6:1)\n"
+    );
+}
+
+#[test]
+fn test_cli_big_integers() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/big_integers.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.trim(), "870");
+}
+
+#[test]
+fn test_cli_numbers() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/numbers.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.trim(), "255 493 10 1000000 0.0015");
+}
+
+#[test]
+fn test_cli_syntax_error() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/syntax_error.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr.trim(),
+        "Syntax error: tests/syntax_error.toy:2:11: Invalid digit '2' in binary literal"
+    );
+}
+
+#[test]
+fn test_cli_doc_comments() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/doc_comments.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.trim(), "49");
+}
+
+#[test]
+fn test_cli_dump_tokens() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["--dump-tokens", "tests/dump.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 13);
+    assert_eq!(lines[0], "1:1     Let");
+    assert_eq!(lines[1], "1:5     Identifier \"x\"");
+    assert_eq!(lines[12], "3:1     Eof");
+}
+
+#[test]
+fn test_cli_dump_ast() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["--dump-ast", "tests/dump.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout,
+        "Let x\n  Binary Add\n    Literal Int 1\n    Literal Int 2\nExpression\n  Call println\n    Variable x\n"
+    );
+}
+
+#[test]
+fn test_cli_dump_ast_json() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["--dump-ast", "--json", "tests/dump.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("[\n  {\n    \"node\": \"Let\",\n    \"name\": \"x\",\n"));
+    assert!(stdout.contains("\"node\": \"Call\",\n      \"name\": \"println\""));
+}
+
+#[test]
+fn test_cli_fmt_check() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args([
+            "fmt",
+            "--check",
+            "tests/comments.toy",
+            "tests/unformatted.toy",
+        ])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.trim(), "Would reformat: tests/unformatted.toy");
+}
+
+#[test]
+fn test_cli_fmt() {
+    let path = std::env::temp_dir().join(format!("toy-rs-fmt-{}.toy", std::process::id()));
+    std::fs::copy("tests/unformatted.toy", &path).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .arg("fmt")
+        .arg(&path)
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    let formatted = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        formatted,
+        "fn double(n) {\n    n * 2\n} // doubles\nlet x = double(0x10);\nx += 1;\nx\n"
+    );
+}
+
+#[test]
+fn test_cli_lint() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["lint", "tests/lint.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout,
+        "tests/lint.toy:2:1: warning[unused_parameter]: unused parameter `factor`\n\
+         tests/lint.toy:5:1: warning[unused_variable]: unused variable `unused`\n\
+         tests/lint.toy:7:4: warning[constant_condition]: `if` condition is always true\n\
+         tests/lint.toy:10:1: error[undefined_function]: call to undefined function `report`\n"
+    );
+}
+
+#[test]
+fn test_cli_lsp() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run binary");
+    let messages = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.toy","languageId":"toy","version":1,"text":"missing();"}}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+        r#"{"jsonrpc":"2.0","method":"exit"}"#,
+    ];
+    let stdin = child.stdin.as_mut().unwrap();
+    for message in messages {
+        write!(
+            stdin,
+            "Content-Length: {}\r\n\r\n{}",
+            message.len(),
+            message
+        )
+        .unwrap();
+    }
+    let output = child.wait_with_output().expect("Failed to run binary");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#""documentFormattingProvider":true"#));
+    assert!(stdout.contains(r#""code":"undefined_function""#));
+    assert!(stdout.ends_with(r#"{"jsonrpc":"2.0","id":2,"result":null}"#));
+}
+
+#[test]
+fn test_cli_check() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["check", "tests/types.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        "Type error: tests/types.toy:10:7: expected str, found int\n\
+         Type error: tests/types.toy:11:1: 'area' expects 2 arguments, found 3\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["check", "tests/math.toy"])
+        .output()
+        .expect("Failed to run binary");
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_cli_optimize() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["-O", "tests/optimize.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "day: 86400\n36 -3 0 true\ncaught\nconstant\n");
+}
+
+#[test]
+fn test_cli_optimize_preserves_output() {
+    let mut examples: Vec<_> = std::fs::read_dir("tests")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toy"))
+        .collect();
+    examples.sort();
+    for example in examples {
+        let run = |flags: &[&str]| {
+            Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+                .args(flags)
+                .arg(&example)
+                .output()
+                .expect("Failed to run binary")
+        };
+        let plain = run(&[]);
+        let optimized = run(&["-O"]);
+        assert_eq!(
+            plain.status.code(),
+            optimized.status.code(),
+            "{:?}",
+            example
+        );
+        assert_eq!(plain.stdout, optimized.stdout, "{:?}", example);
+        // Inlined calls leave no frame behind, so only the error message
+        // itself has to match, not the stack trace below it.
+        let message = |stderr: &[u8]| {
+            String::from_utf8_lossy(stderr)
+                .lines()
+                .next()
+                .map(String::from)
+        };
+        assert_eq!(
+            message(&plain.stderr),
+            message(&optimized.stderr),
+            "{:?}",
+            example
+        );
+    }
+}
+
+#[test]
+fn test_cli_tail_calls() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/tail_calls.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "liftoff\n12502500\n");
+}
+
+#[test]
+fn test_cli_stack_overflow() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["--max-call-depth=3", "tests/recursion.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        "Runtime error: Stack overflow: more than 3 nested calls\n    at fib (tests/recursion.toy:5:9)\n    [previous frame repeated 1 more time]\n    at fib (tests/recursion.toy:9:1)\n"
+    );
+
+    // The deepest allowed chain of calls fits on the interpreter's stack.
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args([
+            "--max-call-depth=10000",
+            "-e",
+            "fn depth(n) { if n == 0 { 0 } else { 1 + depth(n - 1) } }\nprintln(depth(9990));",
+        ])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "9990\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["--max-call-depth=100000", "-e", "1"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("Invalid call depth: 100000 (at most 10000)\n"));
+}
+
+#[test]
+fn test_cli_limits() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["--max-steps=50", "tests/recursion.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("Runtime error: Step limit exceeded: more than 50 steps\n"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["--timeout=soon", "tests/recursion.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("Invalid timeout: soon\n"));
+}
+
+#[test]
+fn test_cli_permissions() {
+    let dir = std::env::temp_dir().join(format!("toy-rs-permissions-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("clock.toy");
+    std::fs::write(&script, "clock() > 0.0").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .arg(&script)
+        .output()
+        .expect("Failed to run binary");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        "Runtime error: Permission denied: clock requires --allow-clock\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .arg("--allow-clock")
+        .arg(&script)
+        .output()
+        .expect("Failed to run binary");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "true\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["--allow-env=./data", "tests/print.toy"])
+        .output()
+        .expect("Failed to run binary");
+    assert_eq!(output.status.code(), Some(2));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_script_arguments() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["--allow-env", "tests/args.toy", "one", "--two"])
+        .env("TOY_GREETING", "hello")
+        .output()
+        .expect("Failed to run binary");
+
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "one\n--two\nhello\n");
+    assert!(output.stderr.is_empty());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/args.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        "Runtime error: Permission denied: env_var requires --allow-env\n"
+    );
+}
+
+#[test]
+fn test_cli_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .arg("tests/stdin.toy")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"apples\r\npears\nplums")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "1: apples\n2: pears\n3: plums\n3\n");
+}
+
+#[test]
+fn test_cli_shebang() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/shebang.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "shebang ok\n");
+}
+
+#[test]
+fn test_cli_inline_program() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["-e", "println(1 + 2); get(args(), 1)", "a", "b"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\nb\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["-e", "1 +"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr, "Syntax error: <eval>:1:4: Unexpected token: Eof\n");
+}
+
+#[test]
+fn test_cli_program_from_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["-", "world"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"println(\"hello \" + get(args(), 0));\n1 / 0")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello world\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr, "Runtime error: Division by zero\n");
+}
+
+#[test]
+fn test_cli_errors() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/errors.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout,
+        "Ok(42)\nErr(invalid integer: 'two')\nfalse 1.5\ncaught: cannot divide by zero\n-1 1\n\
+         Division by zero\ncaught: invalid integer: 'x'\n"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr, "Runtime error: invalid integer: 'x'\n");
+}
+
+#[test]
+fn test_cli_imports() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/modules/main.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "loading utils\n5\n16\n8\n1\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/modules/cycle_a.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Runtime error: Import error: import cycle: \
+         tests/modules/cycle_a.toy -> cycle_b.toy -> cycle_a.toy\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["-e", "import \"greet.toy\"; greet::hello(\"toy\")"])
+        .env("TOY_PATH", "tests/modules/vendor")
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello, toy!\n");
+
+    // Files outside the script's directory and `TOY_PATH` need read access.
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["-e", "import \"/etc/hostname\";"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Runtime error: Permission denied: cannot read '/etc/hostname' \
+         (grant access with --allow-read)\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/modules/escape.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("Runtime error: Permission denied: cannot read '/"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.ends_with("/tests/print.toy' (grant access with --allow-read)\n"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["--allow-read=tests", "tests/modules/escape.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["-e", "import \"Cargo.toml\";"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("Runtime error: Permission denied: cannot read '/"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.ends_with("/Cargo.toml' (grant access with --allow-read)\n"));
+}
+
+#[test]
+fn test_cli_private_definitions() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/modules/private.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Runtime error: 'repeat' is private to tests/modules/utils.toy\n"
+    );
+}
+
+#[test]
+fn test_cli_transitive_imports() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/modules/transitive.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "loading utils\n9\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Runtime error: Undefined function: utils::add\n"
+    );
+}
+
+#[test]
+fn test_cli_imported_stack_trace() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/modules/traced.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Runtime error: negative: -1\n    at checks::check (tests/modules/lib/checks.toy:2:19)\n    at checks::positive (tests/modules/traced.toy:5:5)\n    at run (tests/modules/traced.toy:8:1)\n"
+    );
+}
+
+#[test]
+fn test_cli_prelude() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/prelude.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "30 10 7\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["--no-prelude", "tests/prelude.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Runtime error: Undefined function: assert_eq\n"
+    );
+}
+
+#[test]
+fn test_cli_collections() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/collections.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "12\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Runtime error: Division by zero\n    at map (tests/collections.toy:14:1)\n"
+    );
+}
+
+#[test]
+fn test_cli_iterators() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/iterators.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "2 3 5 7 11 \n0 -1\n"
+    );
+    // `map` calls `inverse` only when `next` asks for the third item.
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Runtime error: Division by zero\n    at map (tests/iterators.toy:34:1)\n"
+    );
+}
+
+#[test]
+fn test_cli_tuples() {
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["tests/tuples.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "3 1\n(2, 3) 2\n0 kim 72\n1 ada 48\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Runtime error: Cannot take (1, 2, 3) apart as a tuple of 2 items\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-rs"))
+        .args(["check", "tests/tuples.toy"])
+        .output()
+        .expect("Failed to run binary");
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Type error: tests/tuples.toy:18:14: cannot take (int, int, int) apart as a tuple of 2 items\n"
+    );
+}